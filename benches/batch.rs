@@ -0,0 +1,91 @@
+// 2022 Hiroyuki Ogasawara
+// vim:ts=4 sw=4 noet:
+
+// cargo bench --bench batch
+// Converts a synthetic corpus of exported pages to doku and puki with the
+// batch mode of the command, on 1, 2, 4 and 8 threads and on all the cores.
+
+// explicit returns are the coding style of this project
+#![allow(clippy::needless_return)]
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::{Command,Stdio};
+use std::thread;
+use std::time::Instant;
+
+
+//-----------------------------------------------------------------------------
+
+const	PAGES: usize= 400;
+
+// About 32KB of markdown per page, the inline marks, lists and tables for the md decoder and the doku and puki encoders.
+fn	page( index: usize ) -> String
+{
+	let	mut	page= format!( "# Page {}\n\n", index );
+	for i in 0..100 {
+		page+= &format!( "## Section {}\n\n", i );
+		page+= "Some **strong** and *emphasis* text with `code` and a [link](https://example.com/page).\n";
+		page+= "The second line ~~with a strike~~, an ![image](a.png) and <https://example.com>.\n\n";
+		page+= "- first item with **strong**\n- second item with `code`\n  - nested [item](b.md)\n\n";
+		page+= "| Name | Value |\n|:-----|------:|\n| *one* | 1 |\n| **two** | 2 |\n\n";
+	}
+	return	page;
+}
+
+// Pages in 10 subdirectories like an exported wiki.
+fn	make_corpus( root: &Path )
+{
+	for index in 0..PAGES {
+		let	dir= root.join( format!( "section{}", index % 10 ) );
+		fs::create_dir_all( &dir ).unwrap();
+		fs::write( dir.join( format!( "page{}.md", index ) ), page( index ) ).unwrap();
+	}
+}
+
+// msec of one batch run.
+fn	run_batch( corpus: &Path, output: &Path, threads: usize ) -> f64
+{
+	let	start= Instant::now();
+	let	status= Command::new( env!( "CARGO_BIN_EXE_mdtowiki" ) )
+			.arg( "-sdoku" ).arg( "-spuki" ).arg( "--quiet" )
+			.arg( format!( "-j{}", threads ) )
+			.arg( format!( "-d{}", output.display() ) )
+			.arg( corpus )
+			.stderr( Stdio::null() )
+			.status().unwrap();
+	assert!( status.success() );
+	return	start.elapsed().as_secs_f64() * 1000.0;
+}
+
+
+//-----------------------------------------------------------------------------
+
+fn	main()
+{
+	let	root= env::temp_dir().join( format!( "mdtowiki-bench-batch-{}", std::process::id() ) );
+	let	corpus= root.join( "corpus" );
+	let	output= root.join( "output" );
+	make_corpus( &corpus );
+	println!( "{} pages, {} KB each", PAGES, page( 0 ).len() / 1024 );
+	let	cores= thread::available_parallelism().map_or( 1, |count| count.get() );
+	let	mut	thread_list= vec![1, 2, 4, 8];
+	if !thread_list.contains( &cores ) {
+		thread_list.push( cores );
+	}
+	let	mut	base= 0.0;
+	for threads in thread_list {
+		let	_= fs::remove_dir_all( &output );
+		let	time= run_batch( &corpus, &output, threads );
+		if threads == 1 {
+			base= time;
+		}
+		println!( "-j{:<3} {:>10.2} ms  x{:.2}", threads, time, base / time );
+	}
+	fs::remove_dir_all( &root ).unwrap();
+}
+
+
+//-----------------------------------------------------------------------------
+
@@ -0,0 +1,127 @@
+// 2022 Hiroyuki Ogasawara
+// vim:ts=4 sw=4 noet:
+
+// cargo bench --bench encode
+// Compares encode() + write_all() with the streaming encode_to_writer()
+// on a large page, the allocator counts the peak memory and the allocations.
+
+// explicit returns are the coding style of this project
+#![allow(clippy::needless_return)]
+
+use std::alloc::{GlobalAlloc,Layout,System};
+use std::io::{self,Write};
+use std::sync::atomic::{AtomicUsize,Ordering};
+use std::time::Instant;
+use mdtowiki::{Document,Format,TextDecoder,TextEncoder};
+
+
+//-----------------------------------------------------------------------------
+
+struct CountingAlloc;
+
+static	CURRENT: AtomicUsize= AtomicUsize::new( 0 );
+static	PEAK: AtomicUsize= AtomicUsize::new( 0 );
+static	COUNT: AtomicUsize= AtomicUsize::new( 0 );
+
+fn	grow( size: usize )
+{
+	let	current= CURRENT.fetch_add( size, Ordering::Relaxed ) + size;
+	PEAK.fetch_max( current, Ordering::Relaxed );
+	COUNT.fetch_add( 1, Ordering::Relaxed );
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+	unsafe fn	alloc( &self, layout: Layout ) -> *mut u8
+	{
+		grow( layout.size() );
+		System.alloc( layout )
+	}
+	unsafe fn	dealloc( &self, ptr: *mut u8, layout: Layout )
+	{
+		CURRENT.fetch_sub( layout.size(), Ordering::Relaxed );
+		System.dealloc( ptr, layout )
+	}
+	unsafe fn	realloc( &self, ptr: *mut u8, layout: Layout, new_size: usize ) -> *mut u8
+	{
+		CURRENT.fetch_sub( layout.size(), Ordering::Relaxed );
+		grow( new_size );
+		System.realloc( ptr, layout, new_size )
+	}
+}
+
+#[global_allocator]
+static	ALLOCATOR: CountingAlloc= CountingAlloc;
+
+
+//-----------------------------------------------------------------------------
+
+// About 1.5MB of markdown with every kind of block.
+fn	large_page() -> String
+{
+	let	mut	page= String::new();
+	for i in 0..4000 {
+		page+= &format!( "## Section {}\n\n", i );
+		page+= "Some **strong** and *emphasis* text with `code` and a [link](https://example.com/page).\n";
+		page+= "The second line of the paragraph ~~with a strike~~ and an ![image](a.png).\n\n";
+		page+= "- first item\n- second item\n  - nested item\n1. ordered\n\n";
+		page+= "> quoted text\n> over two lines\n\n";
+		page+= "```rust\nfn main() {\n    println!( \"hello\" );\n}\n```\n\n";
+		page+= "| Name | Value |\n|:-----|------:|\n| one | 1 |\n| two | 2 |\n\n";
+	}
+	return	page;
+}
+
+struct Measure {
+	time	: f64,		// msec
+	peak	: usize,	// bytes above the document
+	count	: usize,	// allocations
+}
+
+fn	measure( run: impl Fn() ) -> Measure
+{
+	let	base= CURRENT.load( Ordering::Relaxed );
+	PEAK.store( base, Ordering::Relaxed );
+	COUNT.store( 0, Ordering::Relaxed );
+	let	start= Instant::now();
+	run();
+	return	Measure{
+		time:	start.elapsed().as_secs_f64() * 1000.0,
+		peak:	PEAK.load( Ordering::Relaxed ) - base,
+		count:	COUNT.load( Ordering::Relaxed ),
+	};
+}
+
+fn	bench_format( document: &Document, format: Format )
+{
+	let	encoder= format.encoder( None, None );
+	let	string= measure( || {
+			let	mut	writer= io::BufWriter::new( io::sink() );
+			writer.write_all( encoder.encode( document ).as_bytes() ).unwrap();
+			writer.flush().unwrap();
+		});
+	let	stream= measure( || {
+			encoder.encode_to_writer( document, io::BufWriter::new( io::sink() ) ).unwrap();
+		});
+	for (name,result) in [("string", string), ("stream", stream)] {
+		println!( "{:<6} {:<8} {:>10.2} ms {:>10} KB peak {:>10} allocs",
+				format.name(), name, result.time, result.peak / 1024, result.count );
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+fn	main()
+{
+	let	text= large_page();
+	let	decoder= Format::Md.decoder( None ).unwrap();
+	let	document= decoder.load_str( &text );
+	println!( "page {} KB, {} blocks", text.len() / 1024, document.element_list.len() );
+	for format in [Format::Md, Format::Doku, Format::Puki, Format::Red, Format::Conf, Format::Html, Format::Media] {
+		bench_format( &document, format );
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
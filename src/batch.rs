@@ -0,0 +1,250 @@
+// 2022 Hiroyuki Ogasawara
+// vim:ts=4 sw=4 noet:
+
+use std::collections::{HashMap,HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path,PathBuf};
+use std::sync::atomic::{AtomicUsize,Ordering};
+use std::sync::mpsc;
+use std::thread;
+use mdtowiki::{Error,Format,ParagraphMode};
+use super::{Log,Settings,exit_code,load_document,save_document,report_save_error};
+
+
+//-----------------------------------------------------------------------------
+
+// Converts every file of the input tree, the layout is mirrored under the output root.
+pub struct Batch {
+	pub output_root	: String,
+	pub template	: String,		// "{stem}.{format}.txt", {ext} is the input extension
+	pub load_type	: Option<String>,
+	pub format_list	: Vec<(String,Option<ParagraphMode>)>,
+	pub threads		: usize,		// 1 converts the files one by one
+}
+
+struct Job {
+	input	: PathBuf,
+	outputs	: Vec<(String,Option<ParagraphMode>,PathBuf)>,
+}
+
+#[derive(Default)]
+pub struct Summary {
+	pub files	: usize,		// inputs
+	pub written	: usize,		// outputs
+	pub warned	: usize,		// outputs written with warnings
+	pub failed	: usize,		// inputs and outputs
+	pub exit	: Option<i32>,	// the code of the first failure
+}
+
+impl Summary {
+	fn	fail( &mut self, err: &Error )
+	{
+		self.failed+= 1;
+		self.exit= self.exit.or( Some( exit_code( err ) ) );
+	}
+	fn	add( &mut self, job: &Summary )
+	{
+		self.files+= job.files;
+		self.written+= job.written;
+		self.warned+= job.warned;
+		self.failed+= job.failed;
+		self.exit= self.exit.or( job.exit );
+	}
+	pub fn	report( &self )
+	{
+		eprintln!( "{} files: {} written, {} with warnings, {} failed", self.files, self.written, self.warned, self.failed );
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+// Sorted for the same order on every run, the output root is not walked.
+fn	collect_files( dir: &Path, skip: &Option<PathBuf>, list: &mut Vec<PathBuf> ) -> io::Result<()>
+{
+	let	mut	entries= fs::read_dir( dir )?.map( |entry| entry.map( |e| e.path() ) ).collect::<io::Result<Vec<PathBuf>>>()?;
+	entries.sort();
+	for path in entries {
+		if path.is_dir() {
+			if skip.as_ref() != path.canonicalize().ok().as_ref() {
+				collect_files( &path, skip, list )?;
+			}
+		}else{
+			list.push( path );
+		}
+	}
+	return	Ok( () );
+}
+
+// The same file for "tree/a.md" and "./tree/a.md", the output may not exist yet.
+fn	file_key( path: &Path ) -> PathBuf
+{
+	match (path.parent().and_then( |dir| if dir.as_os_str().is_empty() { Path::new( "." ) } else { dir }.canonicalize().ok() ), path.file_name()) {
+		(Some(dir),Some(name)) => dir.join( name ),
+		_ => path.to_path_buf(),
+	}
+}
+
+impl Batch {
+	// Only the extensions of the -l format, or of any format with a decoder.
+	// The extension named after the -l format is taken too, "-lconf" reads "*.conf".
+	fn	accepts( &self, path: &Path ) -> bool
+	{
+		let	format= Format::from_path( &path.to_string_lossy() );
+		match &self.load_type {
+			Some(ltype) => match format {
+				Some(format) => format.name() == ltype,
+				None => path.extension().is_some_and( |ext| ext.eq_ignore_ascii_case( ltype.as_str() ) ),
+			},
+			None => format.is_some_and( |format| format.decoder( None ).is_ok() ),
+		}
+	}
+	fn	output_path( &self, input_root: &Path, input: &Path, format: &str ) -> PathBuf
+	{
+		let	relative= input.strip_prefix( input_root ).unwrap_or( input );
+		let	stem= input.file_stem().map_or( String::new(), |stem| stem.to_string_lossy().to_string() );
+		let	ext= input.extension().map_or( String::new(), |ext| ext.to_string_lossy().to_string() );
+		let	name= self.template.replace( "{stem}", &stem ).replace( "{format}", format ).replace( "{ext}", &ext );
+		let	mut	path= PathBuf::from( &self.output_root );
+		if let Some(dir)= relative.parent() {
+			path.push( dir );
+		}
+		path.push( name );
+		return	path;
+	}
+	// An output is never written over an input of the run, like a.md to a.md after -smd,
+	// and the output of two inputs, like a.md and a.puki to a.doku, is written by neither of them.
+	fn	plan( &self, input_root: &Path ) -> io::Result<(Vec<Job>,Vec<Error>)>
+	{
+		let	input_dir= input_root.canonicalize()?;
+		let	skip= Path::new( &self.output_root ).canonicalize().ok().filter( |dir| *dir != input_dir );
+		let	mut	files= Vec::new();
+		collect_files( input_root, &skip, &mut files )?;
+		let	mut	jobs: Vec<Job>= files.into_iter().filter( |path| self.accepts( path ) ).map( |input| {
+				let	outputs= self.format_list.iter().map( |(format,paragraph)| (format.clone(), *paragraph, self.output_path( input_root, &input, format )) ).collect();
+				Job{ input: input, outputs: outputs }
+			}).collect();
+		let	inputs: HashSet<PathBuf>= jobs.iter().map( |job| file_key( &job.input ) ).collect();
+		let	mut	owners: HashMap<PathBuf,Vec<PathBuf>>= HashMap::new();
+		for job in &jobs {
+			for (_,_,output) in &job.outputs {
+				owners.entry( file_key( output ) ).or_default().push( job.input.clone() );
+			}
+		}
+		let	mut	conflicts= Vec::new();
+		for job in &mut jobs {
+			job.outputs.retain( |(_,_,output)| {
+					let	key= file_key( output );
+					let	note= if inputs.contains( &key ) {
+							"also an input of this run".to_string()
+						}else if let Some(other)= owners[&key].iter().find( |other| **other != job.input ) {
+							format!( "also the output of {}", other.display() )
+						}else{
+							return	true;
+						};
+					let	err= io::Error::new( io::ErrorKind::AlreadyExists, note );
+					conflicts.push( Error::Io( format!( "{} from {}", output.display(), job.input.display() ), err ) );
+					return	false;
+				});
+		}
+		jobs.retain( |job| !job.outputs.is_empty() );
+		return	Ok( (jobs, conflicts) );
+	}
+	fn	convert( &self, job: &Job, settings: &Settings, log: &mut Log ) -> Summary
+	{
+		let	mut	summary= Summary{ files: 1, ..Summary::default() };
+		let	doc= match load_document( &job.input.to_string_lossy(), &self.load_type, settings, log ) {
+			Ok(doc) => doc,
+			Err(err) => {
+				log.line( format!( "error: {}", err ) );
+				summary.fail( &err );
+				return	summary;
+			},
+		};
+		for (save_type,paragraph,output) in &job.outputs {
+			let	output_file= output.to_string_lossy();
+			if let Some(dir)= output.parent() {
+				if let Err(err)= fs::create_dir_all( dir ) {
+					let	err= Error::Io( dir.to_string_lossy().to_string(), err );
+					log.line( format!( "error: {}", err ) );
+					summary.fail( &err );
+					continue;
+				}
+			}
+			match save_document( &doc, save_type, *paragraph, &output_file, settings, log ) {
+				Ok(0) => {
+					summary.written+= 1;
+				},
+				Ok(_) => {
+					summary.written+= 1;
+					summary.warned+= 1;
+				},
+				Err(err) => {
+					report_save_error( &err, &output_file, log );
+					summary.fail( &err );
+				},
+			}
+		}
+		return	summary;
+	}
+	// The threads take the next job one by one, the logs are written in the order
+	// of the jobs whichever thread finishes first. --dump writes to stdout, so it runs on one thread.
+	pub fn	run( &self, input_root: &str, settings: &Settings ) -> Summary
+	{
+		let	mut	summary= Summary::default();
+		let	jobs= match self.plan( Path::new( input_root ) ) {
+			Ok((jobs,conflicts)) => {
+				for err in &conflicts {
+					eprintln!( "error: {}", err );
+					summary.fail( err );
+				}
+				jobs
+			},
+			Err(err) => {
+				let	err= Error::Io( input_root.to_string(), err );
+				eprintln!( "error: {}", err );
+				summary.fail( &err );
+				return	summary;
+			},
+		};
+		let	threads= if settings.debug_dump { 1 } else { self.threads.clamp( 1, jobs.len().max( 1 ) ) };
+		let	next= AtomicUsize::new( 0 );
+		let	(sender,receiver)= mpsc::channel::<(usize,Log,Summary)>();
+		thread::scope( |scope| {
+			for _ in 0..threads {
+				let	sender= sender.clone();
+				let	(jobs,next)= (&jobs,&next);
+				scope.spawn( move || {
+					loop {
+						let	index= next.fetch_add( 1, Ordering::Relaxed );
+						if index >= jobs.len() {
+							break;
+						}
+						let	mut	log= Log::buffer();
+						let	result= self.convert( &jobs[index], settings, &mut log );
+						if sender.send( (index, log, result) ).is_err() {
+							break;
+						}
+					}
+				});
+			}
+			drop( sender );
+			let	mut	pending: Vec<Option<(Log,Summary)>>= (0..jobs.len()).map( |_| None ).collect();
+			let	mut	written= 0;
+			for (index,log,result) in receiver {
+				pending[index]= Some( (log, result) );
+				while let Some(Some((mut log,result)))= pending.get_mut( written ).map( Option::take ) {
+					log.flush();
+					summary.add( &result );
+					written+= 1;
+				}
+			}
+		});
+		return	summary;
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
@@ -0,0 +1,322 @@
+// 2022 Hiroyuki Ogasawara
+// vim:ts=4 sw=4 noet:
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path,PathBuf};
+use toml::{Table,Value};
+use mdtowiki::{Error,Format,ParagraphMode,Transform};
+
+
+//-----------------------------------------------------------------------------
+
+pub const	CONFIG_FILE: &str= "mdtowiki.toml";
+
+// mdtowiki.toml
+//
+//   input = "docs"                   # without the input on the command line
+//   load = "md"
+//   output_dir = "wiki"
+//   template = "{stem}.{format}.txt"
+//   paragraph = "join"
+//   threads = 4
+//   strict = true
+//   root = "div.page"                # the content of the html input
+//   heading_offset = 1               # the transform of every output format
+//   toc = true
+//   code_language = { js = "javascript" }
+//   link = [ { pattern = '^(.*)\.md$', replace = "$1" } ]
+//
+//   [[output]]                       # the output_file is stdout without file, and not used for the input_dir
+//   format = "doku"
+//   file = "wiki.txt"
+//
+//   [format.md]                      # the transform of one output format
+//   heading_offset = 0
+//
+//   [profile.redmine]                # --profile=redmine, the same keys override the others
+//   output = [ { format = "red" } ]
+//
+// The paths are relative to the directory of mdtowiki.toml.
+pub type Output= (String,Option<String>,Option<ParagraphMode>);	// format, file, paragraph
+
+pub struct Config {
+	pub file		: String,
+	pub input		: Option<String>,
+	pub load		: Option<String>,
+	pub outputs		: Vec<Output>,
+	pub output_dir	: Option<String>,
+	pub template	: Option<String>,
+	pub paragraph	: Option<ParagraphMode>,
+	pub threads		: Option<usize>,
+	pub strict		: bool,
+	pub root		: Option<String>,
+	pub transforms	: HashMap<String,Transform>,	// by the output format
+}
+
+const	LAYER_KEYS: &[&str]= &["input", "load", "output", "output_dir", "template", "paragraph", "threads", "strict", "root", "format"];
+const	TRANSFORM_KEYS: &[&str]= &["heading_offset", "toc", "code_language", "link"];
+const	FORMAT_NAMES: &[&str]= &["md", "doku", "puki", "red", "conf", "html", "media"];
+
+
+//-----------------------------------------------------------------------------
+
+// From the directory of the input to the root, the current directory for stdin.
+pub fn	find( input_file: Option<&str> ) -> Option<PathBuf>
+{
+	let	start= match input_file {
+		Some(input) if input != "-" => {
+			let	path= Path::new( input );
+			if path.is_dir() { path.to_path_buf() } else { path.parent().map_or( PathBuf::new(), |dir| dir.to_path_buf() ) }
+		},
+		_ => PathBuf::new(),
+	};
+	let	start= if start.as_os_str().is_empty() { PathBuf::from( "." ) } else { start };
+	let	start= start.canonicalize().unwrap_or( start );
+	return	start.ancestors().map( |dir| dir.join( CONFIG_FILE ) ).find( |path| path.is_file() );
+}
+
+struct Reader<'a> {
+	file	: &'a str,
+	dir		: &'a Path,
+}
+
+impl Reader<'_> {
+	fn	error( &self, message: String ) -> Error
+	{
+		Error::Parse( self.file.to_string(), message )
+	}
+	fn	check_keys( &self, table: &Table, path: &str, keys: &[&[&str]] ) -> Result<(),Error>
+	{
+		for key in table.keys() {
+			if !keys.iter().any( |list| list.contains( &key.as_str() ) ) {
+				return	Err( self.error( format!( "unknown key \"{}{}\"", path, key ) ) );
+			}
+		}
+		return	Ok( () );
+	}
+	fn	string( &self, table: &Table, path: &str, key: &str ) -> Result<Option<String>,Error>
+	{
+		match table.get( key ) {
+			None => Ok( None ),
+			Some(Value::String(text)) => Ok( Some( text.clone() ) ),
+			Some(_) => Err( self.error( format!( "{}{} is not a string", path, key ) ) ),
+		}
+	}
+	fn	path( &self, table: &Table, path: &str, key: &str ) -> Result<Option<String>,Error>
+	{
+		return	Ok( self.string( table, path, key )?.map( |file| self.relative( &file ) ) );
+	}
+	fn	relative( &self, file: &str ) -> String
+	{
+		if file == "-" || Path::new( file ).is_absolute() {
+			return	file.to_string();
+		}
+		return	self.dir.join( file ).to_string_lossy().to_string();
+	}
+	fn	integer( &self, table: &Table, path: &str, key: &str ) -> Result<Option<i64>,Error>
+	{
+		match table.get( key ) {
+			None => Ok( None ),
+			Some(Value::Integer(value)) => Ok( Some( *value ) ),
+			Some(_) => Err( self.error( format!( "{}{} is not an integer", path, key ) ) ),
+		}
+	}
+	fn	boolean( &self, table: &Table, path: &str, key: &str ) -> Result<Option<bool>,Error>
+	{
+		match table.get( key ) {
+			None => Ok( None ),
+			Some(Value::Boolean(value)) => Ok( Some( *value ) ),
+			Some(_) => Err( self.error( format!( "{}{} is not true or false", path, key ) ) ),
+		}
+	}
+	fn	table<'t>( &self, table: &'t Table, path: &str, key: &str ) -> Result<Option<&'t Table>,Error>
+	{
+		match table.get( key ) {
+			None => Ok( None ),
+			Some(Value::Table(child)) => Ok( Some( child ) ),
+			Some(_) => Err( self.error( format!( "{}{} is not a table", path, key ) ) ),
+		}
+	}
+	fn	format( &self, name: &str, path: &str ) -> Result<String,Error>
+	{
+		if Format::from_name( name ).is_none() {
+			return	Err( self.error( format!( "{} \"{}\" is not {}", path, name, FORMAT_NAMES.join( ", " ) ) ) );
+		}
+		return	Ok( name.to_string() );
+	}
+	fn	paragraph( &self, table: &Table, path: &str ) -> Result<Option<ParagraphMode>,Error>
+	{
+		let	mode= match table.get( "paragraph" ) {
+			None => return	Ok( None ),
+			Some(Value::String(name)) => ParagraphMode::from_name( name ),
+			Some(Value::Integer(width)) => ParagraphMode::from_name( &width.to_string() ),
+			Some(_) => None,
+		};
+		return	mode.map( Some ).ok_or_else( || self.error( format!( "{}paragraph is not keep, join or a width", path ) ) );
+	}
+
+	fn	outputs( &self, table: &Table, path: &str ) -> Result<Option<Vec<Output>>,Error>
+	{
+		let	list= match table.get( "output" ) {
+			None => return	Ok( None ),
+			Some(Value::Array(list)) => list,
+			Some(_) => return	Err( self.error( format!( "{}output is not an array of tables", path ) ) ),
+		};
+		let	mut	outputs= Vec::new();
+		for (i,entry) in list.iter().enumerate() {
+			let	path= format!( "{}output[{}].", path, i );
+			let	Value::Table(entry)= entry else {
+				return	Err( self.error( format!( "{} is not a table", path.trim_end_matches( '.' ) ) ) );
+			};
+			self.check_keys( entry, &path, &[&["format", "file", "paragraph"]] )?;
+			let	format= self.string( entry, &path, "format" )?.ok_or_else( || self.error( format!( "{}format is missing", path ) ) )?;
+			let	format= self.format( &format, &format!( "{}format", path ) )?;
+			outputs.push( (format, self.path( entry, &path, "file" )?, self.paragraph( entry, &path )?) );
+		}
+		return	Ok( Some( outputs ) );
+	}
+
+	// heading_offset and toc replace, code_language adds, link replaces the list.
+	fn	transform( &self, table: &Table, path: &str, transform: &mut Transform ) -> Result<(),Error>
+	{
+		if let Some(offset)= self.integer( table, path, "heading_offset" )? {
+			transform.heading_offset= offset.clamp( -5, 5 ) as i32;
+		}
+		if let Some(toc)= self.boolean( table, path, "toc" )? {
+			transform.toc= toc;
+		}
+		if let Some(map)= self.table( table, path, "code_language" )? {
+			for (from,to) in map {
+				let	Value::String(to)= to else {
+					return	Err( self.error( format!( "{}code_language.{} is not a string", path, from ) ) );
+				};
+				transform.code_language.insert( from.clone(), to.clone() );
+			}
+		}
+		match table.get( "link" ) {
+			None => {
+			},
+			Some(Value::Array(list)) => {
+				transform.links.clear();
+				for (i,entry) in list.iter().enumerate() {
+					let	path= format!( "{}link[{}].", path, i );
+					let	Value::Table(entry)= entry else {
+						return	Err( self.error( format!( "{} is not a table", path.trim_end_matches( '.' ) ) ) );
+					};
+					self.check_keys( entry, &path, &[&["pattern", "replace"]] )?;
+					let	pattern= self.string( entry, &path, "pattern" )?.ok_or_else( || self.error( format!( "{}pattern is missing", path ) ) )?;
+					let	replace= self.string( entry, &path, "replace" )?.unwrap_or_default();
+					transform.add_link( &pattern, &replace ).map_err( |err| match err {
+							Error::Parse(_,message) => self.error( format!( "{}pattern {}", path, message ) ),
+							_ => err,
+						})?;
+				}
+			},
+			Some(_) => return	Err( self.error( format!( "{}link is not an array of tables", path ) ) ),
+		}
+		return	Ok( () );
+	}
+
+	// The top level or a profile over the config.
+	fn	layer( &self, table: &Table, path: &str, config: &mut Config ) -> Result<(),Error>
+	{
+		if let Some(input)= self.path( table, path, "input" )? {
+			config.input= Some( input );
+		}
+		if let Some(load)= self.string( table, path, "load" )? {
+			config.load= Some( self.format( &load, &format!( "{}load", path ) )? );
+		}
+		if let Some(outputs)= self.outputs( table, path )? {
+			config.outputs= outputs;
+		}
+		if let Some(dir)= self.path( table, path, "output_dir" )? {
+			config.output_dir= Some( dir );
+		}
+		if let Some(template)= self.string( table, path, "template" )? {
+			config.template= Some( template );
+		}
+		if let Some(mode)= self.paragraph( table, path )? {
+			config.paragraph= Some( mode );
+		}
+		if let Some(threads)= self.integer( table, path, "threads" )? {
+			if threads <= 0 {
+				return	Err( self.error( format!( "{}threads is not a positive integer", path ) ) );
+			}
+			config.threads= Some( threads as usize );
+		}
+		if let Some(strict)= self.boolean( table, path, "strict" )? {
+			config.strict= strict;
+		}
+		if let Some(root)= self.string( table, path, "root" )? {
+			config.root= Some( root );
+		}
+		let	formats= self.table( table, path, "format" )?;
+		if let Some(formats)= formats {
+			self.check_keys( formats, &format!( "{}format.", path ), &[FORMAT_NAMES] )?;
+		}
+		for name in FORMAT_NAMES {
+			let	transform= config.transforms.entry( name.to_string() ).or_default();
+			self.transform( table, path, transform )?;
+			if let Some(format)= self.table( formats.unwrap_or( &Table::new() ), &format!( "{}format.", path ), name )? {
+				let	path= format!( "{}format.{}.", path, name );
+				self.check_keys( format, &path, &[TRANSFORM_KEYS] )?;
+				self.transform( format, &path, transform )?;
+			}
+		}
+		return	Ok( () );
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+// The top level, then the profile over it.
+pub fn	load( path: &Path, profile: Option<&str> ) -> Result<Config,Error>
+{
+	let	file= path.to_string_lossy().to_string();
+	let	text= fs::read_to_string( path ).map_err( |err| Error::Io( file.clone(), err ) )?;
+	let	table= text.parse::<Table>().map_err( |err| {
+			let	offset= err.span().map_or( 0, |span| span.start );
+			let	line= text[..offset].matches( '\n' ).count() + 1;
+			let	column= text[..offset].rsplit( '\n' ).next().map_or( 0, |head| head.chars().count() ) + 1;
+			Error::Parse( format!( "{}:{}:{}", file, line, column ), err.message().to_string() )
+		})?;
+	let	reader= Reader{ file: &file, dir: path.parent().unwrap_or( Path::new( "" ) ) };
+	reader.check_keys( &table, "", &[LAYER_KEYS, TRANSFORM_KEYS, &["profile"]] )?;
+	let	mut	config= Config{
+			file:		file.clone(),
+			input:		None,
+			load:		None,
+			outputs:	Vec::new(),
+			output_dir:	None,
+			template:	None,
+			paragraph:	None,
+			threads:	None,
+			strict:		false,
+			root:		None,
+			transforms:	HashMap::new(),
+		};
+	reader.layer( &table, "", &mut config )?;
+	let	profiles= reader.table( &table, "", "profile" )?;
+	if let Some(profiles)= profiles {
+		for (name,profile) in profiles {
+			let	Value::Table(profile)= profile else {
+				return	Err( reader.error( format!( "profile.{} is not a table", name ) ) );
+			};
+			reader.check_keys( profile, &format!( "profile.{}.", name ), &[LAYER_KEYS, TRANSFORM_KEYS] )?;
+		}
+	}
+	if let Some(name)= profile {
+		let	profile= profiles.and_then( |profiles| profiles.get( name ) ).and_then( |profile| profile.as_table() );
+		let	Some(profile)= profile else {
+			return	Err( reader.error( format!( "no profile \"{}\"", name ) ) );
+		};
+		reader.layer( profile, &format!( "profile.{}.", name ), &mut config )?;
+	}
+	return	Ok( config );
+}
+
+
+//-----------------------------------------------------------------------------
+
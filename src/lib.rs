@@ -0,0 +1,62 @@
+// 2022 Hiroyuki Ogasawara
+// vim:ts=4 sw=4 noet:
+
+// explicit returns and field names are the coding style of this project
+#![allow(clippy::needless_return)]
+#![allow(clippy::redundant_field_names)]
+#![allow(clippy::redundant_pattern_matching)]
+#![allow(clippy::needless_borrow)]
+#![allow(clippy::upper_case_acronyms)]
+// the decoders and encoders are made with new()
+#![allow(clippy::new_without_default)]
+
+// The module has the name of the crate, the api is the flat one below.
+mod mdtowiki;
+
+pub use mdtowiki::span::{Span,SourceLine};
+pub use mdtowiki::inline::{Inline,InlineNode,Image};
+pub use mdtowiki::element::{Block,HTagElement,ListElement,ListItem,PRETagElement,TABLEElement,TableColumn,TableAttr,ETableAlign,BlockQuoteElement,ParagraphElement,TOCElement};
+pub use mdtowiki::document::{Document,ParagraphMode,TextDecoder,TextEncoder};
+pub use mdtowiki::warning::{LossKind,Warning};
+pub use mdtowiki::error::Error;
+pub use mdtowiki::format::{detect,Detection,Format};
+pub use mdtowiki::transform::Transform;
+pub use mdtowiki::w_html::Page;
+pub use mdtowiki::w_md::{Decoder as MdDecoder,Encoder as MdEncoder};
+pub use mdtowiki::w_doku::{Decoder as DokuDecoder,Encoder as DokuEncoder};
+pub use mdtowiki::w_puki::{Decoder as PukiDecoder,Encoder as PukiEncoder};
+pub use mdtowiki::w_red::{Decoder as RedDecoder,Encoder as RedEncoder};
+pub use mdtowiki::w_conf::{Decoder as ConfDecoder,Encoder as ConfEncoder};
+pub use mdtowiki::w_html::{Decoder as HtmlDecoder,Encoder as HtmlEncoder};
+pub use mdtowiki::w_media::{Decoder as MediaDecoder,Encoder as MediaEncoder};
+
+
+//-----------------------------------------------------------------------------
+
+#[derive(Debug,Clone,Default)]
+pub struct Options {
+	pub paragraph	: Option<ParagraphMode>,	// None is the default of the output format
+	pub strict		: bool,						// fail with Error::Lossy instead of losing anything
+	pub transform	: Transform,				// headings, code languages, links and toc of the output
+	pub page		: Option<Page>,				// the html is the standalone page, the fragment without it
+	pub root		: Option<String>,			// selector of the content in the html input, the whole page without it
+}
+
+// ex.  convert( text, Format::Md, Format::Doku, &Options::default() )
+pub fn	convert( input: &str, from: Format, to: Format, options: &Options ) -> Result<String,Error>
+{
+	let	mut	document= from.decoder( options.root.as_deref() )?.load_str( input );
+	options.transform.apply( &mut document );
+	let	encoder= to.encoder( options.paragraph, options.page.as_ref() );
+	if options.strict {
+		let	warnings= encoder.check( &document );
+		if !warnings.is_empty() {
+			return	Err( Error::Lossy( warnings ) );
+		}
+	}
+	return	Ok( encoder.encode( &document ) );
+}
+
+
+//-----------------------------------------------------------------------------
+
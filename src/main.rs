@@ -1,44 +1,305 @@
 // 2022 Hiroyuki Ogasawara
 // vim:ts=4 sw=4 noet:
 
+// explicit returns and field names are the coding style of this project
+#![allow(clippy::needless_return)]
+#![allow(clippy::redundant_field_names)]
+
+use std::collections::HashMap;
 use std::env;
-mod mdtowiki;
+use std::fs;
+use std::io::{self,Read};
+use std::thread;
+use mdtowiki::{Document,Error,Format,Page,ParagraphMode,TextDecoder,TextEncoder,Transform};
+
+mod batch;
+mod config;
+mod watch;
 
 fn	usage()
 {
-	println!( "mdtowiki v1.11 2022 Hiroyuki Ogasawara" );
-	println!( "usage: mdtowiki [<options>] <input_file>" );
-	println!( "option:" );
-	println!( "  -l<type>     md,doku,puki" );
-	println!( "  -s<type>     md,doku,puki,red,conf" );
-	println!( "  -o<output_file>" );
-	println!( "  --all" );
-	println!( "  --dump" );
-	println!( "ex.  mdtowiki -lmd readme.md -sdoku -odokuwiki.txt -spuki -opukiwiki.txt" );
+	eprintln!( "mdtowiki v1.11 2022 Hiroyuki Ogasawara" );
+	eprintln!( "usage: mdtowiki [<options>] [<input_file>|-|<input_dir>]" );
+	eprintln!( "option:" );
+	eprintln!( "  -l<type>     md,doku,puki,red,conf,html,media  detected from the extension or the content without -l" );
+	eprintln!( "  -s<type>     md,doku,puki,red,conf,html,media" );
+	eprintln!( "  -w<mode>     keep,join,<width>  paragraph lines of the following outputs" );
+	eprintln!( "  -o<output_file>|-  stdout without -o" );
+	eprintln!( "  -d<output_dir>     output root of the input_dir, the input_dir without -d" );
+	eprintln!( "  -t<template>       output name in the output_dir, {{stem}}.{{format}} without -t" );
+	eprintln!( "  -j<threads>        files of the input_dir converted in parallel, 1 without -j, the cores with -j" );
+	eprintln!( "  --all" );
+	eprintln!( "  --dump" );
+	eprintln!( "  --strict     fail instead of writing a lossy conversion" );
+	eprintln!( "  --verbose    status lines on stderr" );
+	eprintln!( "  --quiet      no warnings" );
+	eprintln!( "  --watch      convert again when the input_file or a file of the input_dir is saved" );
+	eprintln!( "  --profile=<name>   the profile of mdtowiki.toml" );
+	eprintln!( "  --standalone       the html output is the page with <head>, not the fragment" );
+	eprintln!( "  --title=<title>    title of the html page, the first h1 without it" );
+	eprintln!( "  --stylesheet=<url> stylesheet link of the html page" );
+	eprintln!( "  --root=<selector>  content of the html input, \"div.page\", \"#main-content\" or \"main article\"" );
+	eprintln!( "mdtowiki.toml in the directory of the input or above has the defaults of the options," );
+	eprintln!( "the input without <input_file>, and the transform of each format" );
+	eprintln!( "ex.  mdtowiki -lmd readme.md -sdoku -odokuwiki.txt -spuki -opukiwiki.txt" );
+	eprintln!( "     cat readme.md | mdtowiki -sdoku - > dokuwiki.txt" );
+	eprintln!( "     mdtowiki -sdoku -spuki -dwiki -t{{stem}}.{{format}}.txt -j8 docs" );
+	eprintln!( "exit: 1 usage, 2 I/O, 3 parse, 4 unsupported, 5 encoding, 6 lossy (--strict)" );
 	std::process::exit( 1 );
 }
 
 
+fn	exit_code( err: &Error ) -> i32
+{
+	match err {
+		Error::Io(..) => 2,
+		Error::Parse(..) => 3,
+		Error::Unsupported(..) => 4,
+		Error::Encoding(..) => 5,
+		Error::Lossy(..) => 6,
+	}
+}
+
+fn	fail( err: &Error ) -> !
+{
+	eprintln!( "error: {}", err );
+	std::process::exit( exit_code( err ) );
+}
+
+
+//-----------------------------------------------------------------------------
+
+// The switches shared by every conversion.
+pub struct Settings {
+	pub strict		: bool,
+	pub verbose		: bool,
+	pub quiet		: bool,
+	pub debug_dump	: bool,
+	pub transforms	: HashMap<String,Transform>,	// by the output format, from mdtowiki.toml
+	pub page		: Option<Page>,					// the html output is the standalone page
+	pub root		: Option<String>,				// selector of the content in the html input
+}
+
+// The status lines and the errors go to stderr, or into the buffer of a batch job
+// to be written in the order of the jobs.
+pub struct Log {
+	buffer	: Option<String>,
+}
+
+impl Log {
+	pub fn	stderr() -> Self
+	{
+		Log{ buffer: None }
+	}
+	pub fn	buffer() -> Self
+	{
+		Log{ buffer: Some( String::new() ) }
+	}
+	pub fn	line( &mut self, text: String )
+	{
+		match &mut self.buffer {
+			Some(buffer) => {
+				buffer.push_str( &text );
+				buffer.push( '\n' );
+			},
+			None => eprintln!( "{}", text ),
+		}
+	}
+	pub fn	flush( &mut self )
+	{
+		if let Some(buffer)= &mut self.buffer {
+			eprint!( "{}", buffer );
+			buffer.clear();
+		}
+	}
+}
+
+// The whole input is read first for the format detection, "-" is stdin.
+fn	read_input( input_file: &str ) -> Result<Vec<u8>,Error>
+{
+	if input_file == "-" {
+		let	mut	source= Vec::new();
+		io::stdin().lock().read_to_end( &mut source )?;
+		return	Ok( source );
+	}
+	return	fs::read( input_file ).map_err( |err| Error::Io( input_file.to_string(), err ) );
+}
+
+// The format of -l, or the detected one.
+fn	input_format( load_type: &Option<String>, input_file: &str, source: &[u8], settings: &Settings, log: &mut Log ) -> String
+{
+	if let Some(ltype)= load_type {
+		return	ltype.clone();
+	}
+	match mdtowiki::detect( input_file, &String::from_utf8_lossy( source ) ) {
+		Some(detection) => {
+			if settings.verbose {
+				log.line( format!( "detect [{}]:  {}, confidence {}%", detection.format.name(), detection.reason, detection.confidence ) );
+			}
+			return	detection.format.name().to_string();
+		},
+		None => {
+			if settings.verbose {
+				log.line( "detect [md]:  no marks of any format, default".to_string() );
+			}
+			return	"md".to_string();
+		},
+	}
+}
+
+pub fn	load_document( input_file: &str, load_type: &Option<String>, settings: &Settings, log: &mut Log ) -> Result<Document,Error>
+{
+	let	source= read_input( input_file )?;
+	let	load_type= input_format( load_type, input_file, &source, settings, log );
+	let	file_name= if input_file == "-" { "<stdin>" } else { input_file };
+	if settings.verbose {
+		log.line( format!( "load [{}]:  {}", load_type, input_file ) );
+	}
+	let	format= Format::from_name( &load_type ).ok_or_else( || Error::Unsupported( format!( "load type \"{}\"", load_type ) ) )?;
+	let	mut	doc= format.decoder( settings.root.as_deref() )?.load_from_reader( source.as_slice() ).map_err( |err| err.with_file( file_name ) )?;
+	doc.file_name= file_name.to_string();
+	if settings.debug_dump {
+		doc.dump();
+	}
+	return	Ok( doc );
+}
+
+// Reports what the format loses, the strict mode doesn't write the lossy output.
+// "-" is stdout. Returns the number of the warnings.
+pub fn	save_document( doc: &Document, save_type: &str, paragraph: Option<ParagraphMode>, output_file: &str, settings: &Settings, log: &mut Log ) -> Result<usize,Error>
+{
+	if settings.verbose {
+		log.line( format!( "save [{}]:  {}", save_type, output_file ) );
+	}
+	let	format= Format::from_name( save_type ).ok_or_else( || Error::Unsupported( format!( "save type \"{}\"", save_type ) ) )?;
+	let	encoder= format.encoder( paragraph, settings.page.as_ref() );
+	let	transformed;
+	let	doc= match settings.transforms.get( save_type ) {
+		Some(transform) if !transform.is_empty() => {
+			let	mut	copy= doc.clone();
+			transform.apply( &mut copy );
+			transformed= copy;
+			&transformed
+		},
+		_ => doc,
+	};
+	let	warnings= encoder.check( doc );
+	if !settings.quiet {
+		for warning in &warnings {
+			log.line( format!( "warning: {}: {}", doc.location( warning.span ), warning ) );
+		}
+	}
+	if settings.strict && !warnings.is_empty() {
+		return	Err( Error::Lossy( warnings ) );
+	}
+	if output_file == "-" {
+		encoder.encode_to_writer( doc, io::BufWriter::new( io::stdout().lock() ) )?;
+	}else{
+		doc.save( output_file, &encoder )?;
+	}
+	return	Ok( warnings.len() );
+}
+
+pub fn	report_save_error( err: &Error, output_file: &str, log: &mut Log )
+{
+	match err {
+		Error::Lossy(_) if output_file == "-" => log.line( format!( "error: {}, stdout is not written", err ) ),
+		Error::Lossy(_) => log.line( format!( "error: {}, \"{}\" is not written", err, output_file ) ),
+		_ => log.line( format!( "error: {}", err ) ),
+	}
+}
+
+// Writes every output of the save_list, returns the exit code of the first failure.
+fn	convert_file( input_file: &str, load_type: &Option<String>, save_list: &[(String,String,Option<ParagraphMode>)], settings: &Settings ) -> Option<i32>
+{
+	let	mut	log= Log::stderr();
+	let	doc= match load_document( input_file, load_type, settings, &mut log ) {
+		Ok(doc) => doc,
+		Err(err) => {
+			log.line( format!( "error: {}", err ) );
+			return	Some( exit_code( &err ) );
+		},
+	};
+	let	mut	failed= None;
+	for (save_type,output_file,paragraph) in save_list {
+		if let Err(err)= save_document( &doc, save_type, *paragraph, output_file, settings, &mut log ) {
+			report_save_error( &err, output_file, &mut log );
+			failed= failed.or( Some( exit_code( &err ) ) );
+		}
+	}
+	return	failed;
+}
+
+
+//-----------------------------------------------------------------------------
+
 fn	main()
 {
-	let	mut	load_type= String::from("md");
+	let	mut	load_type= None;
 	let	mut	save_type= String::from("doku");
 	let	mut	input_file= String::new();
-	let	mut	debug_dump= false;
-	let	mut	save_list: Vec<(String,String)>= Vec::new();
+	let	mut	paragraph= None;
+	let	mut	save_list: Vec<(String,String,Option<ParagraphMode>)>= Vec::new();
+	let	mut	format_list: Vec<(String,Option<ParagraphMode>)>= Vec::new();
+	let	mut	output_root= None;
+	let	mut	template= None;
+	let	mut	threads= None;
+	let	mut	profile= None;
 	let	mut	all_flag= false;
+	let	mut	watch_flag= false;
+	let	mut	settings= Settings{ strict: false, verbose: false, quiet: false, debug_dump: false, transforms: HashMap::new(), page: None, root: None };
 	for arg in env::args().skip( 1 ) {
-		if arg.starts_with( "-" ) {
-			if arg.starts_with( "-l" ) {
-				load_type= arg[2..].to_string();
-			}else if arg.starts_with( "-s" ) {
-				save_type= arg[2..].to_string();
-			}else if arg.starts_with( "-o" ) {
-				save_list.push( (save_type.clone(), arg[2..].to_string()) );
+		if arg == "-" {
+			input_file= arg;
+		}else if arg.starts_with( "-" ) {
+			if let Some(ltype)= arg.strip_prefix( "-l" ) {
+				load_type= Some( ltype.to_string() );
+			}else if let Some(stype)= arg.strip_prefix( "-s" ) {
+				save_type= stype.to_string();
+				format_list.push( (save_type.clone(), paragraph) );
+			}else if let Some(output)= arg.strip_prefix( "-o" ) {
+				save_list.push( (save_type.clone(), output.to_string(), paragraph) );
+			}else if let Some(mode)= arg.strip_prefix( "-w" ) {
+				paragraph= ParagraphMode::from_name( mode );
+				if paragraph.is_none() {
+					usage();
+				}
+			}else if let Some(dir)= arg.strip_prefix( "-d" ) {
+				output_root= Some( dir.to_string() );
+			}else if let Some(name)= arg.strip_prefix( "-t" ) {
+				template= Some( name.to_string() );
+			}else if let Some(count)= arg.strip_prefix( "-j" ) {
+				let	count= if count.is_empty() {
+						thread::available_parallelism().map_or( 1, |count| count.get() )
+					}else{
+						count.parse().unwrap_or( 0 )
+					};
+				if count == 0 {
+					usage();
+				}
+				threads= Some( count );
+			}else if let Some(name)= arg.strip_prefix( "--profile=" ) {
+				profile= Some( name.to_string() );
 			}else if arg == "--dump" {
-				debug_dump= true;
+				settings.debug_dump= true;
 			}else if arg == "--all" {
 				all_flag= true;
+			}else if arg == "--watch" {
+				watch_flag= true;
+			}else if arg == "--standalone" {
+				settings.page.get_or_insert_with( Page::default );
+			}else if let Some(title)= arg.strip_prefix( "--title=" ) {
+				settings.page.get_or_insert_with( Page::default ).title= Some( title.to_string() );
+			}else if let Some(url)= arg.strip_prefix( "--stylesheet=" ) {
+				settings.page.get_or_insert_with( Page::default ).stylesheet= Some( url.to_string() );
+			}else if let Some(selector)= arg.strip_prefix( "--root=" ) {
+				settings.root= Some( selector.to_string() );
+			}else if arg == "--strict" {
+				settings.strict= true;
+			}else if arg == "--verbose" {
+				settings.verbose= true;
+			}else if arg == "--quiet" {
+				settings.quiet= true;
 			}else{
 				usage();
 			}
@@ -48,71 +309,96 @@ fn	main()
 	}
 
 
+	// The flags of the command line override mdtowiki.toml, its outputs are used without -s, -o and --all.
+	let	input= if input_file.is_empty() { None } else { Some( input_file.as_str() ) };
+	let	config= match (config::find( input ), &profile) {
+		(Some(path),_) => Some( config::load( &path, profile.as_deref() ).unwrap_or_else( |err| fail( &err ) ) ),
+		(None,Some(name)) => fail( &Error::Parse( config::CONFIG_FILE.to_string(), format!( "not found for the profile \"{}\"", name ) ) ),
+		(None,None) => None,
+	};
+	if let Some(config)= config {
+		if settings.verbose {
+			eprintln!( "config:  {}", config.file );
+		}
+		if input_file.is_empty() {
+			input_file= config.input.unwrap_or_default();
+		}
+		load_type= load_type.or( config.load );
+		output_root= output_root.or( config.output_dir );
+		template= template.or( config.template );
+		threads= threads.or( config.threads );
+		settings.strict|= config.strict;
+		settings.root= settings.root.take().or( config.root );
+		settings.transforms= config.transforms;
+		if save_list.is_empty() && format_list.is_empty() && !all_flag {
+			for (format,file,mode) in config.outputs {
+				format_list.push( (format.clone(), mode) );
+				save_list.push( (format, file.unwrap_or_else( || "-".to_string() ), mode) );
+			}
+		}
+		for (_,_,mode) in &mut save_list {
+			*mode= mode.or( config.paragraph );
+		}
+		for (_,mode) in &mut format_list {
+			*mode= mode.or( config.paragraph );
+		}
+		paragraph= paragraph.or( config.paragraph );
+	}
+
+
 	if input_file.is_empty() {
 		usage();
 	}
 
 
-	println!( "load [{}]:  {}", load_type, input_file );
-	let	document;
-	match load_type.as_str() {
-		"md" => {
-			let	loader= mdtowiki::w_md::Decoder::new();
-			document= loader.load( &input_file );
-		},
-		"doku" => {
-			let	loader= mdtowiki::w_doku::Decoder::new();
-			document= loader.load( &input_file );
-		},
-		"puki" => {
-			let	loader= mdtowiki::w_puki::Decoder::new();
-			document= loader.load( &input_file );
-		},
-		_ => {
-			println!( "Unknown load type \"{}\"", load_type );
-			std::process::exit( 1 );
-		},
+	if watch_flag && input_file == "-" {
+		usage();
+	}
+	if fs::metadata( &input_file ).is_ok_and( |meta| meta.is_dir() ) {
+		if all_flag {
+			format_list= ["md", "doku", "puki", "red", "conf", "html", "media"].iter().map( |name| (name.to_string(), paragraph) ).collect();
+		}
+		if format_list.is_empty() {
+			format_list.push( (save_type, paragraph) );
+		}
+		let	batch= batch::Batch{
+				output_root:	output_root.unwrap_or_else( || input_file.clone() ),
+				template:		template.unwrap_or_else( || "{stem}.{format}".to_string() ),
+				load_type:		load_type,
+				format_list:	format_list,
+				threads:		threads.unwrap_or( 1 ),
+			};
+		let	run= || {
+				let	summary= batch.run( &input_file, &settings );
+				summary.report();
+				return	summary.exit;
+			};
+		if watch_flag {
+			watch::watch( &input_file, &settings, || { run(); } );
+		}
+		if let Some(code)= run() {
+			std::process::exit( code );
+		}
+		return;
 	}
 
-	match document {
-		Ok(doc) => {
-			if debug_dump {
-				doc.dump();
-			}
-			if all_flag {
-				save_list.push( ("md".to_string(), "output.md".to_string()) );
-				save_list.push( ("doku".to_string(), "output.doku".to_string()) );
-				save_list.push( ("puki".to_string(), "output.puki".to_string()) );
-				save_list.push( ("red".to_string(), "output.red".to_string()) );
-				save_list.push( ("conf".to_string(), "output.conf".to_string()) );
-			}
-			for (save_type,output_file) in &save_list {
-				println!( "save [{}]:  {}", save_type, output_file );
-				match save_type.as_str() {
-					"md" => {
-						doc.save( &output_file, &mdtowiki::w_md::Encoder::new() ).unwrap();
-					},
-					"doku" => {
-						doc.save( &output_file, &mdtowiki::w_doku::Encoder::new() ).unwrap();
-					},
-					"puki" => {
-						doc.save( &output_file, &mdtowiki::w_puki::Encoder::new() ).unwrap();
-					},
-					"red" => {
-						doc.save( &output_file, &mdtowiki::w_red::Encoder::new() ).unwrap();
-					},
-					"conf" => {
-						doc.save( &output_file, &mdtowiki::w_conf::Encoder::new() ).unwrap();
-					},
-					_ => {
-						usage();
-					},
-				}
-			}
-		},
-		_ => {
-			println!( "Load Error \"{}\"", input_file );
-			std::process::exit( 1 );
-		},
+
+	if all_flag {
+		save_list.push( ("md".to_string(), "output.md".to_string(), paragraph) );
+		save_list.push( ("doku".to_string(), "output.doku".to_string(), paragraph) );
+		save_list.push( ("puki".to_string(), "output.puki".to_string(), paragraph) );
+		save_list.push( ("red".to_string(), "output.red".to_string(), paragraph) );
+		save_list.push( ("conf".to_string(), "output.conf".to_string(), paragraph) );
+		save_list.push( ("html".to_string(), "output.html".to_string(), paragraph) );
+		save_list.push( ("media".to_string(), "output.media".to_string(), paragraph) );
+	}
+	if save_list.is_empty() && !settings.debug_dump {
+		save_list.push( (save_type.clone(), "-".to_string(), paragraph) );
+	}
+	if watch_flag {
+		watch::watch( &input_file, &settings, || { convert_file( &input_file, &load_type, &save_list, &settings ); } );
+	}
+	if let Some(code)= convert_file( &input_file, &load_type, &save_list, &settings ) {
+		std::process::exit( code );
 	}
 }
@@ -2,49 +2,350 @@
 // vim:ts=4 sw=4 noet:
 
 use std::fs;
-use std::io::{self,Write};
+use std::io::{self,BufRead,Write};
+use super::span::{Span,SourceLine};
+use super::inline::*;
 use super::element::*;
+use super::warning::*;
+use super::error::Error;
 
 //-----------------------------------------------------------------------------
 
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum ParagraphMode {
+	Keep,			// keep the source line breaks
+	Join,			// one logical line
+	Wrap(usize),	// re-wrap at the column
+}
+
+impl ParagraphMode {
+	pub fn	from_name( name: &str ) -> Option<Self>
+	{
+		match name {
+			"keep" => Some( ParagraphMode::Keep ),
+			"join" => Some( ParagraphMode::Join ),
+			_ => name.parse::<usize>().ok().filter( |w| *w > 0 ).map( ParagraphMode::Wrap ),
+		}
+	}
+}
+
+fn	join_lines( text: &[Inline] ) -> Vec<Inline>
+{
+	let	mut	list= Vec::new();
+	for node in text {
+		match node.node {
+			InlineNode::SoftBreak => {
+				push_text( &mut list, " ", node.span );
+			},
+			_ => {
+				push_inline( &mut list, node.clone() );
+			},
+		}
+	}
+	return	list;
+}
+
+fn	wrap_lines( text: &[Inline], width: usize, encode: fn( &[Inline] ) -> String ) -> String
+{
+	let	mut	buffer= String::new();
+	let	mut	line= String::new();
+	let	mut	word= String::new();
+	let	mut	words: Vec<String>= Vec::new();
+	let	flush_line= |buffer: &mut String, line: &mut String, words: &mut Vec<String>| {
+		for word in words.drain( .. ) {
+			if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+				*buffer+= line;
+				*buffer+= "\n";
+				line.clear();
+			}
+			if !line.is_empty() {
+				line.push( ' ' );
+			}
+			*line+= &word;
+		}
+	};
+	for node in text {
+		match &node.node {
+			InlineNode::Text(text) => {
+				for (i,piece) in text.split( char::is_whitespace ).enumerate() {
+					if i != 0 && !word.is_empty() {
+						words.push( std::mem::take( &mut word ) );
+					}
+					word+= &encode( &[Inline::at( InlineNode::Text( piece.to_string() ), node.span )] );
+				}
+			},
+			InlineNode::SoftBreak => {
+				if !word.is_empty() {
+					words.push( std::mem::take( &mut word ) );
+				}
+			},
+			InlineNode::LineBreak => {
+				if !word.is_empty() {
+					words.push( std::mem::take( &mut word ) );
+				}
+				flush_line( &mut buffer, &mut line, &mut words );
+				buffer+= &line;
+				buffer+= &encode( &[Inline::at( InlineNode::LineBreak, node.span )] );
+				line.clear();
+			},
+			_ => {
+				word+= &encode( std::slice::from_ref( node ) );
+			},
+		}
+	}
+	if !word.is_empty() {
+		words.push( word );
+	}
+	flush_line( &mut buffer, &mut line, &mut words );
+	buffer+= &line;
+	return	buffer;
+}
+
+pub fn	render_paragraph( text: &[Inline], mode: ParagraphMode, encode: fn( &[Inline] ) -> String ) -> String
+{
+	match mode {
+		ParagraphMode::Keep => encode( text ),
+		ParagraphMode::Join => encode( &join_lines( text ) ),
+		ParagraphMode::Wrap(width) => wrap_lines( text, width, encode ),
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+fn	check_inlines<T:TextEncoder+?Sized>( encoder: &T, list: &[Inline], warnings: &mut Vec<Warning> )
+{
+	for node in list {
+		encoder.check_inline( node, warnings );
+		match &node.node {
+			InlineNode::Strong(child) | InlineNode::Emphasis(child) | InlineNode::Strike(child) => {
+				check_inlines( encoder, child, warnings );
+			},
+			InlineNode::Link{ text, .. } => {
+				check_inlines( encoder, text, warnings );
+			},
+			_ => {
+			},
+		}
+	}
+}
+
+// For the wikis writing a list item in one line, the other blocks of the item end the list.
+pub fn	check_list_items( list: &ListElement, warnings: &mut Vec<Warning> )
+{
+	for item in &list.items {
+		for block in item.blocks.iter().filter( |block| !matches!( block, Block::Paragraph(_) | Block::List(_) ) ) {
+			warnings.push( Warning::new( LossKind::Degraded, "block in list item", "written after the item, out of the list", block.span() ) );
+		}
+	}
+}
+
+fn	check_blocks<T:TextEncoder+?Sized>( encoder: &T, list: &[Block], warnings: &mut Vec<Warning> )
+{
+	for element in list {
+		encoder.check_block( element, warnings );
+		match element {
+			Block::Paragraph(e) => {
+				check_inlines( encoder, &e.text, warnings );
+			},
+			Block::HTag(e) => {
+				check_inlines( encoder, &e.title, warnings );
+			},
+			Block::List(e) => {
+				for item in &e.items {
+					check_blocks( encoder, &item.blocks, warnings );
+				}
+			},
+			Block::Table(e) => {
+				for column in &e.data {
+					for attr in &column.data {
+						check_inlines( encoder, &attr.text, warnings );
+					}
+				}
+			},
+			Block::Quote(e) => {
+				check_blocks( encoder, &e.blocks, warnings );
+			},
+			_ => {
+			},
+		}
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+// Same as BufRead::lines(), the line not in UTF-8 is an Encoding error with the position.
+fn	read_lines( mut reader: impl BufRead ) -> Result<Vec<String>,Error>
+{
+	let	mut	lines= Vec::new();
+	let	mut	buffer= Vec::new();
+	while reader.read_until( b'\n', &mut buffer )? != 0 {
+		if buffer.ends_with( b"\n" ) {
+			buffer.pop();
+			if buffer.ends_with( b"\r" ) {
+				buffer.pop();
+			}
+		}
+		match String::from_utf8( std::mem::take( &mut buffer ) ) {
+			Ok(line) => {
+				lines.push( line );
+			},
+			Err(err) => {
+				let	valid= &err.as_bytes()[..err.utf8_error().valid_up_to()];
+				let	column= String::from_utf8_lossy( valid ).chars().count() + 1;
+				return	Err( Error::Encoding( format!( "{}:{}", lines.len() + 1, column ) ) );
+			},
+		}
+	}
+	return	Ok( lines );
+}
+
+pub trait TextDecoder {
+	fn	decode_lines( &self, lines: &[SourceLine] ) -> Document;
+	fn	load_str( &self, text: &str ) -> Document
+	{
+		self.decode_lines( &SourceLine::number( text.lines().map( String::from ).collect() ) )
+	}
+	fn	load_from_reader( &self, reader: impl BufRead ) -> Result<Document,Error> where Self: Sized
+	{
+		let	lines= read_lines( reader )?;
+		Ok(self.decode_lines( &SourceLine::number( lines ) ))
+	}
+	fn	load( &self, file_name: &str ) -> Result<Document,Error> where Self: Sized
+	{
+		let	file= fs::File::open( file_name ).map_err( |err| Error::Io( file_name.to_string(), err ) )?;
+		let	mut	page= self.load_from_reader( io::BufReader::new( file ) ).map_err( |err| err.with_file( file_name ) )?;
+		page.file_name= file_name.to_string();
+		Ok(page)
+	}
+}
+
+// Box<dyn TextDecoder> from Format::decoder() takes the generic methods too.
+impl<T:TextDecoder+?Sized> TextDecoder for Box<T> {
+	fn	decode_lines( &self, lines: &[SourceLine] ) -> Document
+	{
+		(**self).decode_lines( lines )
+	}
+}
+
+#[derive(Clone)]
 pub struct Document {
-	pub element_list	: Vec<Box<dyn Element>>,
+	pub element_list	: Vec<Block>,
+	pub file_name		: String,	// source of the spans
 }
 
 #[allow(dead_code)]
 pub trait TextEncoder {
-	fn	encode_single( &self, element: &Box<dyn Element> ) -> String;
-	fn	encode( &self, document: &Document ) -> String
+	fn	encode_single( &self, element: &Block ) -> String;
+	// What the format loses from the element itself, the children are checked by check().
+	fn	check_block( &self, _element: &Block, _warnings: &mut Vec<Warning> )
+	{
+	}
+	fn	check_inline( &self, _node: &Inline, _warnings: &mut Vec<Warning> )
+	{
+	}
+	// Written before and after the blocks, the html page is wrapped with them.
+	fn	begin( &self, _document: &Document ) -> String
+	{
+		String::new()
+	}
+	fn	end( &self, _document: &Document ) -> String
+	{
+		String::new()
+	}
+	// All the losses of the document in this format.
+	fn	check( &self, document: &Document ) -> Vec<Warning>
+	{
+		let	mut	warnings= Vec::new();
+		check_blocks( self, &document.element_list, &mut warnings );
+		return	warnings;
+	}
+	fn	encode_blocks( &self, list: &[Block] ) -> String
 	{
 		let	mut	buffer= String::new();
-		for element in &document.element_list {
+		for (i,element) in list.iter().enumerate() {
+			if i != 0 {
+				buffer+= "\n";
+			}
 			buffer+= &self.encode_single( element );
 		}
 		return	buffer;
 	}
-	fn	save( &self, file_name: &str, document: &Document ) -> io::Result<()>
+	fn	encode( &self, document: &Document ) -> String
 	{
-		let	mut fp= fs::File::create( file_name )?;
-		fp.write_all( &self.encode( document ).as_bytes() )?;
-		fp.flush()?;
+		self.begin( document ) + &self.encode_blocks( &document.element_list ) + &self.end( document )
+	}
+	// Streams block by block, the whole text is never held in memory.
+	fn	encode_to_writer( &self, document: &Document, mut writer: impl Write ) -> Result<(),Error> where Self: Sized
+	{
+		writer.write_all( self.begin( document ).as_bytes() )?;
+		for (i,element) in document.element_list.iter().enumerate() {
+			if i != 0 {
+				writer.write_all( b"\n" )?;
+			}
+			writer.write_all( self.encode_single( element ).as_bytes() )?;
+		}
+		writer.write_all( self.end( document ).as_bytes() )?;
+		writer.flush()?;
 		Ok(())
 	}
+	fn	save( &self, file_name: &str, document: &Document ) -> Result<(),Error> where Self: Sized
+	{
+		let	file= fs::File::create( file_name ).map_err( |err| Error::Io( file_name.to_string(), err ) )?;
+		self.encode_to_writer( document, io::BufWriter::new( file ) ).map_err( |err| err.with_file( file_name ) )
+	}
+}
+
+// Box<dyn TextEncoder> from Format::encoder() takes the generic methods too.
+impl<T:TextEncoder+?Sized> TextEncoder for Box<T> {
+	fn	encode_single( &self, element: &Block ) -> String
+	{
+		(**self).encode_single( element )
+	}
+	fn	check_block( &self, element: &Block, warnings: &mut Vec<Warning> )
+	{
+		(**self).check_block( element, warnings )
+	}
+	fn	check_inline( &self, node: &Inline, warnings: &mut Vec<Warning> )
+	{
+		(**self).check_inline( node, warnings )
+	}
+	fn	begin( &self, document: &Document ) -> String
+	{
+		(**self).begin( document )
+	}
+	fn	end( &self, document: &Document ) -> String
+	{
+		(**self).end( document )
+	}
 }
 
 impl Document {
 	pub fn	new() -> Self
 	{
-		Document{ element_list: Vec::new() }
+		Document{ element_list: Vec::new(), file_name: String::new() }
 	}
-	pub fn	push( &mut self, element: Box<dyn Element> )
+	pub fn	push( &mut self, element: Block )
 	{
+		if let Block::None= element {
+			return;
+		}
 		self.element_list.push( element );
 	}
 
+	// "readme.md:42:5", "42:5" for the document not loaded from a file.
+	pub fn	location( &self, span: Span ) -> String
+	{
+		if self.file_name.is_empty() {
+			return	span.to_string();
+		}
+		format!( "{}:{}", self.file_name, span )
+	}
+
 	pub	fn	dump( &self )
 	{
 		for element in &self.element_list {
-			element.dump();
+			element.dump( &self.file_name, 0 );
 		}
 	}
 /*
@@ -56,12 +357,9 @@ impl Document {
 		Ok(())
 	}
 */
-	pub	fn	save<T:TextEncoder>( &self, file_name: &str, encoder: &T ) -> io::Result<()>
+	pub	fn	save<T:TextEncoder>( &self, file_name: &str, encoder: &T ) -> Result<(),Error>
 	{
-		let	mut fp= fs::File::create( file_name )?;
-		fp.write_all( &encoder.encode( &self ).as_bytes() )?;
-		fp.flush()?;
-		Ok(())
+		encoder.save( file_name, self )
 	}
 }
 
@@ -1,124 +1,286 @@
 // 2022 Hiroyuki Ogasawara
 // vim:ts=4 sw=4 noet:
 
-use std::any::Any;
+use std::collections::HashMap;
+
+use super::span::Span;
+use super::inline::*;
 
 //-----------------------------------------------------------------------------
 
-#[allow(dead_code)]
-#[derive(Debug,Clone,Copy,PartialEq)]
-pub enum ElementType {
-	NONE,
-	DATA,
-	HTAG,	// <h#>
-	ULTAG,	// <ul><li>
-	OLTAG,	// <ol><li>
-	PRETAG,	// <pre> or <code>
-	TABLE,	// <table><tr><td>
-	HRTAG,	// <hr/>
+#[derive(Clone)]
+pub enum Block {
+	None,
+	Paragraph(ParagraphElement),
+	HTag(HTagElement),		// <h#>
+	List(ListElement),		// <ul><li> or <ol><li>
+	PRETag(PRETagElement),	// <pre> or <code>
+	Table(TABLEElement),	// <table><tr><td>
+	Quote(BlockQuoteElement),		// <blockquote>
+	HRTag(Span),			// <hr/>
+	TOC(TOCElement),		// table of contents
 }
 
-//-----------------------------------------------------------------------------
+impl Block {
+	// Where the block starts in the source.
+	pub fn	span( &self ) -> Span
+	{
+		match self {
+			Block::None => Span::default(),
+			Block::Paragraph(e) => e.span,
+			Block::HTag(e) => e.span,
+			Block::List(e) => e.span,
+			Block::PRETag(e) => e.span,
+			Block::Table(e) => e.span,
+			Block::Quote(e) => e.span,
+			Block::HRTag(span) => *span,
+			Block::TOC(e) => e.span,
+		}
+	}
+	pub fn	dump( &self, file: &str, nest: usize )
+	{
+		match self {
+			Block::None => {
+			},
+			Block::Paragraph(e) => {
+				e.dump( file, nest );
+			},
+			Block::HTag(e) => {
+				e.dump( file, nest );
+			},
+			Block::List(e) => {
+				e.dump( file, nest );
+			},
+			Block::PRETag(e) => {
+				e.dump( file, nest );
+			},
+			Block::Table(e) => {
+				e.dump( file, nest );
+			},
+			Block::Quote(e) => {
+				e.dump( file, nest );
+			},
+			Block::HRTag(span) => {
+				println!( "{}hr", dump_head( file, *span, nest ) );
+			},
+			Block::TOC(e) => {
+				e.dump( file, nest );
+			},
+		}
+	}
+}
 
-pub trait CastToAny : Any {
-	fn	as_any( &self ) -> &dyn Any;
+// "readme.md:42:5  " and the indent of the nest.
+fn	dump_head( file: &str, span: Span, nest: usize ) -> String
+{
+	format!( "{:<24}{}", format!( "{}:{} ", file, span ), "  ".repeat( nest ) )
 }
 
-#[allow(dead_code)]
-pub trait Element : CastToAny {
-	fn	get_type( &self ) -> ElementType;
-	fn	dump( &self );
+//-----------------------------------------------------------------------------
+
+#[derive(Clone)]
+pub struct HTagElement {
+	pub title	: Vec<Inline>,
+	pub level	: u32,	// 1, 2, 3,
+	pub span	: Span,
 }
 
-impl<T:Element>	CastToAny for T {
-	fn	as_any( &self ) -> &dyn Any
+impl HTagElement {
+	pub fn	dump( &self, file: &str, nest: usize )
 	{
-		self
+		println!( "{}h{} {}", dump_head( file, self.span, nest ), self.level, dump_inline( &self.title ) );
 	}
 }
 
-//=============================================================================
+//-----------------------------------------------------------------------------
 
-pub struct NONEElement {
+// The headings after it, the wikis with a toc macro don't use them.
+#[derive(Clone)]
+pub struct TOCElement {
+	pub entries	: Vec<(u32,Vec<Inline>)>,	// level, title
+	pub span	: Span,
 }
 
-impl Element for NONEElement {
-	fn	get_type( &self ) -> ElementType
+impl TOCElement {
+	pub fn	dump( &self, file: &str, nest: usize )
 	{
-		return	ElementType::NONE;
+		println!( "{}toc", dump_head( file, self.span, nest ) );
+		for (level,title) in &self.entries {
+			println!( "{}h{} {}", dump_head( file, self.span, nest+1 ), level, dump_inline( title ) );
+		}
 	}
-	fn	dump( &self )
-	{
+}
+
+// The headings of the blocks, only of the top level.
+pub fn	toc_entries( list: &[Block] ) -> Vec<(u32,Vec<Inline>)>
+{
+	list.iter().filter_map( |block| match block {
+			Block::HTag(e) => Some( (e.level, e.title.clone()) ),
+			_ => None,
+		}).collect()
+}
+
+// "Getting Started" to "#getting-started" like GitHub, the same titles get "-1", "-2".
+pub fn	anchor( title: &str, used: &mut HashMap<String,usize> ) -> String
+{
+	let	slug: String= title.trim().to_lowercase().chars().filter( |ch| ch.is_alphanumeric() || *ch == ' ' || *ch == '-' || *ch == '_' ).map( |ch| if ch == ' ' { '-' } else { ch } ).collect();
+	let	count= used.entry( slug.clone() ).or_insert( 0 );
+	*count+= 1;
+	if *count == 1 {
+		return	slug;
+	}
+	return	format!( "{}-{}", slug, *count - 1 );
+}
+
+// Fills the toc read from the wiki with the headings after it.
+pub fn	fill_toc( list: &mut [Block] )
+{
+	if let Some(index)= list.iter().position( |block| matches!( block, Block::TOC(_) ) ) {
+		let	entries= toc_entries( &list[index+1..] );
+		if let Block::TOC(e)= &mut list[index] {
+			e.entries= entries;
+		}
 	}
 }
 
 //-----------------------------------------------------------------------------
 
-pub struct HTagElement {
-	pub title	: String,
-	pub etype	: ElementType,
-	pub level	: u32,	// 1, 2, 3,
+#[derive(Clone)]
+pub struct ListItem {
+	pub blocks	: Vec<Block>,
+	pub span	: Span,
+}
+
+#[derive(Clone)]
+pub struct ListElement {
+	pub ordered	: bool,
+	pub start	: u32,
+	pub items	: Vec<ListItem>,
+	pub span	: Span,
 }
 
-impl Element for HTagElement {
-	fn	get_type( &self ) -> ElementType
+impl ListElement {
+	pub fn	new( ordered: bool, start: u32, span: Span ) -> Self
 	{
-		return	self.etype;
+		ListElement{ ordered: ordered, start: start, items: Vec::new(), span: span }
 	}
-	fn	dump( &self )
+	pub fn	dump( &self, file: &str, nest: usize )
 	{
-		println!( "h{} {}", self.level, self.title );
+		if self.ordered {
+			println!( "{}ol {}", dump_head( file, self.span, nest ), self.start );
+		}else{
+			println!( "{}ul", dump_head( file, self.span, nest ) );
+		}
+		for item in &self.items {
+			println!( "{}li", dump_head( file, item.span, nest+1 ) );
+			for block in &item.blocks {
+				block.dump( file, nest+2 );
+			}
+		}
+	}
+}
+
+// One line of a wiki list which marks the depth with its prefix.
+pub struct ListEntry {
+	pub depth	: u32,	// 0, 1, 2,
+	pub ordered	: bool,
+	pub text	: Vec<Inline>,
+	pub span	: Span,
+}
+
+// Builds the list tree from the flat list lines.
+pub fn	build_list_tree( entries: &mut [ListEntry] ) -> Vec<Block>
+{
+	let	Some(depth)= entries.iter().map( |e| e.depth ).min() else { return Vec::new() };
+	let	mut	blocks= Vec::new();
+	let	mut	index= 0;
+	while index < entries.len() {
+		let	mut	list= ListElement::new( entries[index].ordered, 1, entries[index].span );
+		while index < entries.len() {
+			let	entry= &mut entries[index];
+			let	mut	item= ListItem{ blocks: Vec::new(), span: entry.span };
+			if entry.depth == depth {
+				if entry.ordered != list.ordered && !list.items.is_empty() {
+					break;
+				}
+				let	span= entry.text.first().map_or( entry.span, |node| node.span );
+				item.blocks.push( Block::Paragraph( ParagraphElement{ text: std::mem::take( &mut entry.text ), span: span } ) );
+				index+= 1;
+			}
+			let	child_start= index;
+			while index < entries.len() && entries[index].depth > depth {
+				index+= 1;
+			}
+			item.blocks.extend( build_list_tree( &mut entries[child_start..index] ) );
+			list.items.push( item );
+		}
+		blocks.push( Block::List( list ) );
 	}
+	return	blocks;
 }
 
+
 //-----------------------------------------------------------------------------
 
-pub struct LITagElement {
-	pub text	: String,
-	pub etype	: ElementType,
-	pub indent	: u32,
-	pub nest	: u32,	// 0, 1, 2,
+// A nested quote is a BlockQuote inside the blocks.
+#[derive(Clone)]
+pub struct BlockQuoteElement {
+	pub blocks	: Vec<Block>,
+	pub span	: Span,
 }
 
-impl Element for LITagElement {
-	fn	get_type( &self ) -> ElementType
+impl BlockQuoteElement {
+	pub fn	dump( &self, file: &str, nest: usize )
 	{
-		return	self.etype;
+		println!( "{}quote", dump_head( file, self.span, nest ) );
+		for block in &self.blocks {
+			block.dump( file, nest+1 );
+		}
 	}
-	fn	dump( &self )
-	{
-		if self.etype == ElementType::ULTAG {
-			println!( "ul {} ({}) {}", self.nest, self.indent, self.text );
-		}else if self.etype == ElementType::OLTAG {
-			println!( "ol {} ({}) {}", self.nest, self.indent, self.text );
+}
+
+// Strips one level of the quote marker '>' from the line.
+pub fn	strip_quote_mark( line: &str ) -> Option<&str>
+{
+	let	text= line.strip_prefix( '>' )?;
+	return	Some( text.strip_prefix( ' ' ).unwrap_or( text ) );
+}
+
+// Prefixes every line of the quoted text with the marker.
+pub fn	prefix_quote_lines( text: &str, mark: &str ) -> String
+{
+	let	mut	buffer= String::new();
+	for line in text.lines() {
+		if line.is_empty() || line.starts_with( mark ) {
+			buffer+= &format!( "{}{}\n", mark, line );
+		}else{
+			buffer+= &format!( "{} {}\n", mark, line );
 		}
 	}
+	return	buffer;
 }
 
 
 //-----------------------------------------------------------------------------
 
+#[derive(Clone)]
 pub struct PRETagElement {
 	pub text	: String,
-	pub etype	: ElementType,
 	pub code	: String,
+	pub span	: Span,
 }
 
-impl Element for PRETagElement {
-	fn	get_type( &self ) -> ElementType
-	{
-		return	self.etype;
-	}
-	fn	dump( &self )
+impl PRETagElement {
+	pub fn	dump( &self, file: &str, nest: usize )
 	{
-		println!( "pre {} {}", self.code, self.text );
+		println!( "{}pre {} {}", dump_head( file, self.span, nest ), self.code, self.text );
 	}
 }
 
 
 //-----------------------------------------------------------------------------
 
-#[derive(Clone,Copy,Debug)]
+#[derive(Clone,Copy,Debug,PartialEq)]
 pub enum ETableAlign {
 	DEFAULT,
 	CENTER,
@@ -128,20 +290,26 @@ pub enum ETableAlign {
 
 #[derive(Clone)]
 pub struct TableAttr {
-	pub text	: String,
-	pub align   : ETableAlign,
-	pub header  : bool,
+	pub text	: Vec<Inline>,
+	pub align   : ETableAlign,	// DEFAULT follows the row and the column
+	pub header  : Option<bool>,	// None follows the row and the column
+	pub colspan	: u32,	// 1 for a single cell
+	pub rowspan	: u32,
 }
 
+// One row of the table.
 #[derive(Clone)]
 pub struct TableColumn {
 	pub data	: Vec<TableAttr>,
+	pub align	: ETableAlign,	// default of the row
+	pub header	: bool,
+	pub span	: Span,
 }
 
 impl TableColumn {
-	pub fn	new() -> Self
+	pub fn	new( span: Span ) -> Self
 	{
-		TableColumn{ data: Vec::new() }
+		TableColumn{ data: Vec::new(), align: ETableAlign::DEFAULT, header: false, span: span }
 	}
 	pub	fn	add( &mut self, attr: TableAttr )
 	{
@@ -149,68 +317,266 @@ impl TableColumn {
 	}
 }
 
+#[derive(Clone)]
 pub struct TABLEElement {
-	pub etype	: ElementType,
 	pub data	: Vec<TableColumn>,
+	pub align	: Vec<ETableAlign>,	// defaults of each column
+	pub header	: Vec<bool>,
+	pub span	: Span,
 }
 
-impl Element for TABLEElement {
-	fn	get_type( &self ) -> ElementType
+impl TABLEElement {
+	pub fn	new( data: Vec<TableColumn>, span: Span ) -> Self
 	{
-		return	self.etype;
+		TABLEElement{ data: data, align: Vec::new(), header: Vec::new(), span: span }
 	}
-	fn	dump( &self )
+	pub fn	dump( &self, file: &str, nest: usize )
 	{
-		println!( "table" );
-		for column in &self.data {
-			for attr in &column.data {
-				if attr.header {
-					print!( "^ {} ^", attr.text );
+		println!( "{}table", dump_head( file, self.span, nest ) );
+		for (row,line) in self.grid().iter().enumerate() {
+			print!( "{}", dump_head( file, self.data[row].span, nest ) );
+			for (col,cell) in line.iter().enumerate() {
+				let	TableGrid::Cell(attr)= cell else { continue };
+				let	span= if attr.colspan > 1 || attr.rowspan > 1 { format!( "{}x{} ", attr.colspan, attr.rowspan ) } else { String::new() };
+				let	align= match self.cell_align( row, col, attr ) {
+					ETableAlign::DEFAULT => "",
+					ETableAlign::CENTER => "center ",
+					ETableAlign::LEFT => "left ",
+					ETableAlign::RIGHT => "right ",
+				};
+				if self.cell_header( row, col, attr ) {
+					print!( "^ {}{}{} ^", span, align, dump_inline( &attr.text ) );
 				}else{
-					print!( "| {} |", attr.text );
+					print!( "| {}{}{} |", span, align, dump_inline( &attr.text ) );
 				}
 			}
-			println!( "" );
+			println!();
 		}
 	}
+	// The alignment of the cell at the grid position, the cell overrides the row and the column.
+	pub fn	cell_align( &self, row: usize, col: usize, attr: &TableAttr ) -> ETableAlign
+	{
+		if attr.align != ETableAlign::DEFAULT {
+			return	attr.align;
+		}
+		if let Some(line)= self.data.get( row ) {
+			if line.align != ETableAlign::DEFAULT {
+				return	line.align;
+			}
+		}
+		return	self.align.get( col ).copied().unwrap_or( ETableAlign::DEFAULT );
+	}
+	pub fn	cell_header( &self, row: usize, col: usize, attr: &TableAttr ) -> bool
+	{
+		if let Some(header)= attr.header {
+			return	header;
+		}
+		return	self.data.get( row ).is_some_and( |line| line.header ) || self.header.get( col ).copied().unwrap_or( false );
+	}
+	// The alignment shared by the column, the column default or the one of every body cell.
+	pub fn	column_align( &self, col: usize ) -> ETableAlign
+	{
+		let	align= self.align.get( col ).copied().unwrap_or( ETableAlign::DEFAULT );
+		if align != ETableAlign::DEFAULT {
+			return	align;
+		}
+		let	mut	shared= None;
+		for (row,line) in self.grid().iter().enumerate() {
+			if let Some(TableGrid::Cell(attr))= line.get( col ) {
+				if self.cell_header( row, col, attr ) {
+					continue;
+				}
+				let	cell= self.cell_align( row, col, attr );
+				if shared.is_some_and( |a| a != cell ) {
+					return	ETableAlign::DEFAULT;
+				}
+				shared= Some(cell);
+			}
+		}
+		return	shared.unwrap_or( ETableAlign::DEFAULT );
+	}
+	// True when every cell of the row is a header cell.
+	pub fn	is_header_row( &self, row: usize ) -> bool
+	{
+		let	grid= self.grid();
+		let	Some(line)= grid.get( row ) else { return false };
+		return	line.iter().enumerate().all( |(col,cell)| match cell {
+				TableGrid::Cell(attr) => self.cell_header( row, col, attr ),
+				_ => true,
+			} );
+	}
+	pub fn	has_span( &self ) -> bool
+	{
+		self.data.iter().any( |column| column.data.iter().any( |attr| attr.colspan > 1 || attr.rowspan > 1 ) )
+	}
+	// Lays out the cells on the grid, the cells covered by a span are marked.
+	pub fn	grid( &self ) -> Vec<Vec<TableGrid<&TableAttr>>>
+	{
+		let	mut	grid: Vec<Vec<Option<TableGrid<&TableAttr>>>>= self.data.iter().map( |_| Vec::new() ).collect();
+		for (row,column) in self.data.iter().enumerate() {
+			let	mut	col= 0;
+			for attr in &column.data {
+				while grid[row].get( col ).is_some_and( |g| g.is_some() ) {
+					col+= 1;
+				}
+				let	rows= (attr.rowspan.max( 1 ) as usize).min( self.data.len() - row );
+				for dr in 0..rows {
+					for dc in 0..attr.colspan.max( 1 ) as usize {
+						let	line= &mut grid[row+dr];
+						if line.len() <= col+dc {
+							line.resize_with( col+dc+1, || None );
+						}
+						line[col+dc]= Some( if dr != 0 {
+								TableGrid::SpanUp
+							}else if dc != 0 {
+								TableGrid::SpanLeft
+							}else{
+								TableGrid::Cell( attr )
+							} );
+					}
+				}
+				col+= attr.colspan.max( 1 ) as usize;
+			}
+		}
+		return	grid.into_iter().map( |line| line.into_iter().flatten().collect() ).collect();
+	}
+	// Expands the spans into empty cells for the formats without spans.
+	pub fn	flatten( &self ) -> TABLEElement
+	{
+		let	mut	data= Vec::new();
+		for (row,line) in self.grid().iter().enumerate() {
+			let	mut	column= TableColumn{ data: Vec::new(), ..self.data[row].clone() };
+			let	mut	header= None;
+			for (col,cell) in line.iter().enumerate() {
+				match cell {
+					TableGrid::Cell(attr) => {
+						header= Some( self.cell_header( row, col, attr ) );
+						column.add( TableAttr{ colspan: 1, rowspan: 1, header: header, ..(*attr).clone() } );
+					},
+					_ => {
+						column.add( TableAttr{ header: header, ..empty_cell() } );
+					},
+				}
+			}
+			data.push( column );
+		}
+		return	TABLEElement{ data: data, align: self.align.clone(), header: self.header.clone(), span: self.span };
+	}
 }
 
+// A table cell as the wikis write it, before the spans are resolved.
+pub enum TableGrid<T> {
+	Cell(T),
+	SpanLeft,	// joined to the left cell, doku '||'
+	SpanRight,	// joined to the right cell, puki '>'
+	SpanUp,		// joined to the upper cell, doku ':::' or puki '~'
+}
 
-//-----------------------------------------------------------------------------
-
-pub struct HRTagElement {
+fn	empty_cell() -> TableAttr
+{
+	TableAttr{ text: Vec::new(), align: ETableAlign::DEFAULT, header: None, colspan: 1, rowspan: 1 }
 }
 
-impl Element for HRTagElement {
-	fn	get_type( &self ) -> ElementType
-	{
-		return	ElementType::HRTAG;
-	}
-	fn	dump( &self )
-	{
-		println!( "hr" );
+// Resolves the span marks and builds the table from the rows and their source positions.
+pub fn	build_table( grid: Vec<(Span,Vec<TableGrid<TableAttr>>)> ) -> TABLEElement
+{
+	let	span= grid.first().map_or( Span::default(), |(span,_)| *span );
+	let	mut	data: Vec<TableColumn>= Vec::new();
+	let	mut	owner: Vec<Vec<Option<(usize,usize)>>>= Vec::new();	// (row, index) of the cell covering the grid
+	for (row,(line_span,line)) in grid.into_iter().enumerate() {
+		let	mut	column= TableColumn::new( line_span );
+		let	mut	owner_line: Vec<Option<(usize,usize)>>= Vec::new();
+		let	mut	pending: u32= 0;
+		for (col,cell) in line.into_iter().enumerate() {
+			let	cell= match cell {
+				TableGrid::SpanRight => {
+					pending+= 1;
+					owner_line.push( None );
+					continue;
+				},
+				TableGrid::SpanLeft => {
+					match owner_line.last().copied().flatten() {
+						Some((r,index)) if r == row => {
+							column.data[index].colspan+= 1;
+							owner_line.push( Some((r,index)) );
+							continue;
+						},
+						_ => empty_cell(),
+					}
+				},
+				TableGrid::SpanUp => {
+					match owner.last().and_then( |l| l.get( col ) ).copied().flatten() {
+						Some((r,index)) => {
+							let	attr= &mut data[r].data[index];
+							attr.rowspan= attr.rowspan.max( (row - r + 1) as u32 );
+							owner_line.push( Some((r,index)) );
+							continue;
+						},
+						None => empty_cell(),
+					}
+				},
+				TableGrid::Cell(attr) => attr,
+			};
+			let	index= column.data.len();
+			column.add( TableAttr{ colspan: cell.colspan + pending, ..cell } );
+			owner_line.truncate( owner_line.len() - pending as usize );
+			owner_line.resize( owner_line.len() + pending as usize + 1, Some((row,index)) );
+			pending= 0;
+		}
+		if pending != 0 {
+			column.add( TableAttr{ colspan: pending, ..empty_cell() } );
+		}
+		data.push( column );
+		owner.push( owner_line );
 	}
+	return	TABLEElement::new( data, span );
 }
 
 
 //-----------------------------------------------------------------------------
 
-pub struct DataElement {
-	pub text	: String,
+#[derive(Clone)]
+pub struct ParagraphElement {
+	pub text	: Vec<Inline>,	// lines are separated by SoftBreak
+	pub span	: Span,
 }
 
-impl Element for DataElement {
-	fn	get_type( &self ) -> ElementType
+impl ParagraphElement {
+	pub fn	new() -> Self
+	{
+		ParagraphElement{ text: Vec::new(), span: Span::default() }
+	}
+	pub fn	add_line( &mut self, line: Vec<Inline> )
+	{
+		match self.text.last().map( |node| &node.node ) {
+			None => {
+				self.span= line.first().map_or( Span::default(), |node| node.span );
+			},
+			Some(InlineNode::LineBreak) => {
+			},
+			Some(_) => {
+				let	span= line.first().map_or( Span::default(), |node| node.span );
+				self.text.push( Inline::at( InlineNode::SoftBreak, span ) );
+			},
+		}
+		for node in line {
+			push_inline( &mut self.text, node );
+		}
+	}
+	pub fn	finish( &mut self ) -> Option<Block>
 	{
-		return	ElementType::DATA;
+		while let Some(InlineNode::SoftBreak | InlineNode::LineBreak)= self.text.last().map( |node| &node.node ) {
+			self.text.pop();
+		}
+		if self.text.is_empty() {
+			return	None;
+		}
+		return	Some( Block::Paragraph( ParagraphElement{ text: std::mem::take( &mut self.text ), span: self.span } ) );
 	}
-	fn	dump( &self )
+	pub fn	dump( &self, file: &str, nest: usize )
 	{
-		println!( "data {}", self.text );
+		println!( "{}paragraph {}", dump_head( file, self.span, nest ), dump_inline( &self.text ) );
 	}
 }
 
 //-----------------------------------------------------------------------------
-
-
-
@@ -0,0 +1,64 @@
+// 2022 Hiroyuki Ogasawara
+// vim:ts=4 sw=4 noet:
+
+use std::fmt;
+use std::io;
+use super::warning::Warning;
+
+//-----------------------------------------------------------------------------
+
+#[derive(Debug)]
+pub enum Error {
+	Io(String,io::Error),		// the file name, empty for the reader and the writer
+	Parse(String,String),		// "readme.md:42:5", what is wrong
+	Unsupported(String),		// no decoder or encoder for the format
+	Encoding(String),			// "readme.md:42:5" where the text is not UTF-8
+	Lossy(Vec<Warning>),		// the strict conversion would lose them
+}
+
+impl Error {
+	// Names the file of the I/O error from the reader or the writer.
+	pub fn	with_file( self, file_name: &str ) -> Self
+	{
+		match self {
+			Error::Io(name,err) if name.is_empty() => Error::Io( file_name.to_string(), err ),
+			Error::Encoding(location) => Error::Encoding( format!( "{}:{}", file_name, location ) ),
+			_ => self,
+		}
+	}
+}
+
+impl fmt::Display for Error {
+	fn	fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+	{
+		match self {
+			Error::Io(name,err) if name.is_empty() => write!( f, "{}", err ),
+			Error::Io(name,err) => write!( f, "{}: {}", name, err ),
+			Error::Parse(location,message) => write!( f, "{}: {}", location, message ),
+			Error::Unsupported(what) => write!( f, "unsupported {}", what ),
+			Error::Encoding(location) => write!( f, "{}: invalid UTF-8", location ),
+			Error::Lossy(warnings) => write!( f, "{} lossy conversion(s)", warnings.len() ),
+		}
+	}
+}
+
+impl std::error::Error for Error {
+	fn	source( &self ) -> Option<&(dyn std::error::Error + 'static)>
+	{
+		match self {
+			Error::Io(_,err) => Some( err ),
+			_ => None,
+		}
+	}
+}
+
+impl From<io::Error> for Error {
+	fn	from( err: io::Error ) -> Self
+	{
+		Error::Io( String::new(), err )
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
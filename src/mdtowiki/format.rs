@@ -0,0 +1,260 @@
+// 2022 Hiroyuki Ogasawara
+// vim:ts=4 sw=4 noet:
+
+use regex;
+use	lazy_static::lazy_static;
+
+use super::document::*;
+use super::error::Error;
+use super::{w_md,w_doku,w_puki,w_red,w_conf,w_html,w_media};
+
+
+//-----------------------------------------------------------------------------
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Format {
+	Md,
+	Doku,
+	Puki,
+	Red,
+	Conf,
+	Html,
+	Media,
+}
+
+impl Format {
+	pub fn	from_name( name: &str ) -> Option<Self>
+	{
+		match name {
+			"md" => Some( Format::Md ),
+			"doku" => Some( Format::Doku ),
+			"puki" => Some( Format::Puki ),
+			"red" => Some( Format::Red ),
+			"conf" => Some( Format::Conf ),
+			"html" => Some( Format::Html ),
+			"media" => Some( Format::Media ),
+			_ => None,
+		}
+	}
+	pub fn	name( &self ) -> &'static str
+	{
+		match self {
+			Format::Md => "md",
+			Format::Doku => "doku",
+			Format::Puki => "puki",
+			Format::Red => "red",
+			Format::Conf => "conf",
+			Format::Html => "html",
+			Format::Media => "media",
+		}
+	}
+	// By the extension, ".txt" is DokuWiki only in its "data/pages" directory.
+	// ".conf" is mostly the config file, Confluence is read with -l conf.
+	pub fn	from_path( path: &str ) -> Option<Self>
+	{
+		let	path= path.replace( '\\', "/" );
+		let	(_,ext)= path.rsplit_once( '.' )?;
+		match ext.to_ascii_lowercase().as_str() {
+			"md" | "markdown" => Some( Format::Md ),
+			"doku" => Some( Format::Doku ),
+			"txt" if path.contains( "data/pages/" ) => Some( Format::Doku ),
+			"puki" => Some( Format::Puki ),
+			"textile" | "red" => Some( Format::Red ),
+			"html" | "htm" => Some( Format::Html ),
+			"mediawiki" | "media" => Some( Format::Media ),
+			_ => None,
+		}
+	}
+	// The root is the selector of the content in the html, the other formats don't use it.
+	pub fn	decoder( &self, root: Option<&str> ) -> Result<Box<dyn TextDecoder>,Error>
+	{
+		match self {
+			Format::Md => Ok( Box::new( w_md::Decoder::new() ) ),
+			Format::Doku => Ok( Box::new( w_doku::Decoder::new() ) ),
+			Format::Puki => Ok( Box::new( w_puki::Decoder::new() ) ),
+			Format::Red => Ok( Box::new( w_red::Decoder::new() ) ),
+			Format::Conf => Ok( Box::new( w_conf::Decoder::new() ) ),
+			Format::Html => {
+				let	mut	decoder= w_html::Decoder::new();
+				if let Some(root)= root {
+					decoder.set_root( root )?;
+				}
+				Ok( Box::new( decoder ) )
+			},
+			Format::Media => Ok( Box::new( w_media::Decoder::new() ) ),
+		}
+	}
+	// The paragraph mode overrides the default of the format, the page makes the html standalone.
+	pub fn	encoder( &self, paragraph: Option<ParagraphMode>, page: Option<&w_html::Page> ) -> Box<dyn TextEncoder>
+	{
+		match self {
+			Format::Md => {
+				let	mut	encoder= w_md::Encoder::new();
+				encoder.paragraph= paragraph.unwrap_or( encoder.paragraph );
+				Box::new( encoder )
+			},
+			Format::Doku => {
+				let	mut	encoder= w_doku::Encoder::new();
+				encoder.paragraph= paragraph.unwrap_or( encoder.paragraph );
+				Box::new( encoder )
+			},
+			Format::Puki => {
+				let	mut	encoder= w_puki::Encoder::new();
+				encoder.paragraph= paragraph.unwrap_or( encoder.paragraph );
+				Box::new( encoder )
+			},
+			Format::Red => {
+				let	mut	encoder= w_red::Encoder::new();
+				encoder.paragraph= paragraph.unwrap_or( encoder.paragraph );
+				Box::new( encoder )
+			},
+			Format::Conf => {
+				let	mut	encoder= w_conf::Encoder::new();
+				encoder.paragraph= paragraph.unwrap_or( encoder.paragraph );
+				Box::new( encoder )
+			},
+			Format::Html => {
+				let	mut	encoder= w_html::Encoder::new();
+				encoder.paragraph= paragraph.unwrap_or( encoder.paragraph );
+				encoder.page= page.cloned();
+				Box::new( encoder )
+			},
+			Format::Media => {
+				let	mut	encoder= w_media::Encoder::new();
+				encoder.paragraph= paragraph.unwrap_or( encoder.paragraph );
+				Box::new( encoder )
+			},
+		}
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct Detection {
+	pub format		: Format,
+	pub confidence	: u32,		// percent
+	pub reason		: String,	// "extension .md", "8 of 10 marked lines"
+}
+
+// The lines only one syntax writes this way, each line is a vote for the format.
+fn	line_patterns() -> &'static [(Format,Vec<regex::Regex>)]
+{
+	lazy_static! {
+		static ref	PATTERNS: Vec<(Format,Vec<regex::Regex>)>= [
+			(Format::Md, vec![
+				r"^#{1,6}\s",
+				r"^```",
+				r"^\|?\s*:?-{3,}:?\s*(\|\s*:?-{3,}:?\s*)*\|?\s*$",
+				r"\]\([^)]*\)",
+			]),
+			(Format::Doku, vec![
+				r"^\^.*\^\s*$",
+				r"^<(code|file)\b",
+				r"\[\[[^\]|>]+\|",
+			]),
+			(Format::Puki, vec![
+				r"^\*{1,3}[^\s*][^*]*$",
+				r"\|[hcf]\s*$",
+				r"&ref\(|^#(ref|contents|br)\b",
+				r"\[\[[^\]]+>",
+				r"^\|\s*(LEFT|CENTER|RIGHT):",
+			]),
+			(Format::Red, vec![
+				r"\|_\.",
+				r"^bq\.\s",
+				r"^<pre>",
+				r#""[^"]+":\S"#,
+			]),
+			(Format::Conf, vec![
+				r"^\{code",
+				r"^\{quote\}",
+				r"^\|\|",
+				r"\[[^\]|]+\|[a-z]+://",
+			]),
+			(Format::Html, vec![
+				r"(?i)^\s*<!doctype\s+html",
+				r"(?i)^\s*</?(html|head|body|div|p|h[1-6]|ul|ol|li|table|tr|td|th|blockquote)\b[^>]*>",
+			]),
+			(Format::Media, vec![
+				r"^\{\|",
+				r"^\|-",
+				r"'''",
+				r"<syntaxhighlight\b",
+				r"\[[a-z]+://[^\s\]]+\s[^\]]+\]",
+				r"\[\[(File|Image):",
+			]),
+		].into_iter().map( |(format,list)| (format, list.into_iter().map( |pat| regex::Regex::new( pat ).unwrap() ).collect()) ).collect();
+	}
+	return	&PATTERNS;
+}
+
+impl Format {
+	// Counts the lines in the syntax of each format, the confidence is the share of the best one.
+	pub fn	detect( text: &str ) -> Option<Detection>
+	{
+		lazy_static! {
+			static ref	HEADING_PAT: regex::Regex= regex::Regex::new( r"^h[1-6]\.\s" ).unwrap();
+			static ref	EQUAL_PAT: regex::Regex= regex::Regex::new( r"^=+\s.*\s=+\s*$" ).unwrap();
+		}
+		let	patterns= line_patterns();
+		let	mut	votes= vec![0u32; patterns.len()];
+		let	mut	headings= 0;
+		let	mut	equals= 0;
+		for line in text.lines() {
+			for (i,(_,list)) in patterns.iter().enumerate() {
+				if list.iter().any( |pat| pat.is_match( line ) ) {
+					votes[i]+= 1;
+				}
+			}
+			if HEADING_PAT.is_match( line ) {
+				headings+= 1;
+			}
+			if EQUAL_PAT.is_match( line ) {
+				equals+= 1;
+			}
+		}
+		// "h1." is both Textile and Confluence, it goes to the one with more other marks.
+		let	red= patterns.iter().position( |(format,_)| *format == Format::Red )?;
+		let	conf= patterns.iter().position( |(format,_)| *format == Format::Conf )?;
+		if votes[conf] > votes[red] {
+			votes[conf]+= headings;
+		}else{
+			votes[red]+= headings;
+		}
+		// "== h ==" is both DokuWiki and MediaWiki, the same way.
+		let	doku= patterns.iter().position( |(format,_)| *format == Format::Doku )?;
+		let	media= patterns.iter().position( |(format,_)| *format == Format::Media )?;
+		if votes[media] > votes[doku] {
+			votes[media]+= equals;
+		}else{
+			votes[doku]+= equals;
+		}
+		let	total: u32= votes.iter().sum();
+		let	best= *votes.iter().max()?;
+		if best == 0 || votes.iter().filter( |vote| **vote == best ).count() > 1 {
+			return	None;
+		}
+		let	index= votes.iter().position( |vote| *vote == best )?;
+		return	Some( Detection{
+				format:		patterns[index].0,
+				confidence:	best * 100 / total,
+				reason:		format!( "{} of {} marked lines", best, total ),
+			});
+	}
+}
+
+// The extension first, then the content.
+pub fn	detect( path: &str, text: &str ) -> Option<Detection>
+{
+	if let Some(format)= Format::from_path( path ) {
+		let	ext= path.rsplit_once( '.' ).map_or( "", |(_,ext)| ext );
+		return	Some( Detection{ format: format, confidence: 100, reason: format!( "extension .{}", ext ) } );
+	}
+	return	Format::detect( text );
+}
+
+
+//-----------------------------------------------------------------------------
+
@@ -0,0 +1,323 @@
+// 2022 Hiroyuki Ogasawara
+// vim:ts=4 sw=4 noet:
+
+use super::span::Span;
+
+//-----------------------------------------------------------------------------
+
+#[derive(Debug,Clone,PartialEq)]
+pub enum InlineNode {
+	Text(String),
+	Strong(Vec<Inline>),
+	Emphasis(Vec<Inline>),
+	Strike(Vec<Inline>),
+	Code(String),
+	Link{ url: String, text: Vec<Inline> },
+	Image(Image),
+	SoftBreak,	// line break in the source paragraph
+	LineBreak,	// forced line break
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct Inline {
+	pub node	: InlineNode,
+	pub span	: Span,
+}
+
+impl Inline {
+	pub fn	new( node: InlineNode ) -> Self
+	{
+		Inline{ node: node, span: Span::default() }
+	}
+	pub fn	at( node: InlineNode, span: Span ) -> Self
+	{
+		Inline{ node: node, span: span }
+	}
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct Image {
+	pub src		: String,
+	pub alt		: String,
+	pub title	: String,
+	pub width	: Option<u32>,
+	pub height	: Option<u32>,
+}
+
+impl Image {
+	pub fn	new( src: &str ) -> Self
+	{
+		Image{ src: src.to_string(), alt: String::new(), title: String::new(), width: None, height: None }
+	}
+	// Reads the size written as "200", "200x100" or "x100".
+	pub fn	set_size( &mut self, size: &str ) -> bool
+	{
+		let	(w,h)= size.split_once( 'x' ).unwrap_or( (size, "") );
+		let	width= w.parse::<u32>().ok();
+		let	height= h.parse::<u32>().ok();
+		if (width.is_none() && !w.is_empty()) || (height.is_none() && !h.is_empty()) || (width.is_none() && height.is_none()) {
+			return	false;
+		}
+		self.width= width;
+		self.height= height;
+		return	true;
+	}
+	// "200x100", "200" or "x100", empty without the size.
+	pub fn	size_text( &self ) -> String
+	{
+		match (self.width, self.height) {
+			(Some(w),Some(h)) => format!( "{}x{}", w, h ),
+			(Some(w),None) => w.to_string(),
+			(None,Some(h)) => format!( "x{}", h ),
+			(None,None) => String::new(),
+		}
+	}
+}
+
+pub fn	push_text( list: &mut Vec<Inline>, text: &str, span: Span )
+{
+	if text.is_empty() {
+		return;
+	}
+	if let Some(Inline{ node: InlineNode::Text(last), .. })= list.last_mut() {
+		last.push_str( text );
+		return;
+	}
+	list.push( Inline::at( InlineNode::Text( text.to_string() ), span ) );
+}
+
+pub fn	push_inline( list: &mut Vec<Inline>, node: Inline )
+{
+	match node.node {
+		InlineNode::Text(text) => {
+			push_text( list, &text, node.span );
+		},
+		_ => {
+			list.push( node );
+		},
+	}
+}
+
+// The text without the marks, for the anchors and the toc.
+pub fn	plain_text( list: &[Inline] ) -> String
+{
+	let	mut	buffer= String::new();
+	for node in list {
+		match &node.node {
+			InlineNode::Text(text) | InlineNode::Code(text) => {
+				buffer+= text;
+			},
+			InlineNode::Strong(child) | InlineNode::Emphasis(child) | InlineNode::Strike(child) | InlineNode::Link{ text: child, .. } => {
+				buffer+= &plain_text( child );
+			},
+			InlineNode::Image(image) => {
+				buffer+= &image.alt;
+			},
+			InlineNode::SoftBreak | InlineNode::LineBreak => {
+				buffer+= " ";
+			},
+		}
+	}
+	return	buffer;
+}
+
+pub fn	dump_inline( list: &[Inline] ) -> String
+{
+	let	mut	buffer= String::new();
+	for node in list {
+		match &node.node {
+			InlineNode::Text(text) => {
+				buffer+= text;
+			},
+			InlineNode::Strong(child) => {
+				buffer+= &format!( "strong({})", dump_inline( child ) );
+			},
+			InlineNode::Emphasis(child) => {
+				buffer+= &format!( "em({})", dump_inline( child ) );
+			},
+			InlineNode::Strike(child) => {
+				buffer+= &format!( "del({})", dump_inline( child ) );
+			},
+			InlineNode::Code(text) => {
+				buffer+= &format!( "code({})", text );
+			},
+			InlineNode::Link{ url, text } => {
+				buffer+= &format!( "link({},{})", url, dump_inline( text ) );
+			},
+			InlineNode::Image(image) => {
+				buffer+= &format!( "img({},{},{})", image.src, image.alt, image.size_text() );
+			},
+			InlineNode::SoftBreak => {
+				buffer+= " ";
+			},
+			InlineNode::LineBreak => {
+				buffer+= "br()";
+			},
+		}
+	}
+	return	buffer;
+}
+
+
+//=============================================================================
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum InlineKind {
+	Strong,
+	Emphasis,
+	StrongEmphasis,
+	Strike,
+	Code,
+}
+
+pub struct Delimiter {
+	pub open	: &'static str,
+	pub close	: &'static str,
+	pub kind	: InlineKind,
+	pub word	: bool,	// can't open or close inside a word (md '_')
+}
+
+// Returns the decoded nodes and the number of bytes consumed, at is the position of text.
+pub type SpecialFunc= fn( parser: &InlineParser, text: &str, prev: Option<char>, at: Span ) -> Option<(Vec<Inline>,usize)>;
+
+pub struct InlineParser {
+	pub delimiters	: Vec<Delimiter>,
+	pub tight		: bool,	// no whitespace just inside the delimiters
+	pub special		: SpecialFunc,
+}
+
+fn	run_length( text: &str, ch: char ) -> usize
+{
+	text.chars().take_while( |c| *c == ch ).count()
+}
+
+fn	is_run( mark: &str ) -> Option<char>
+{
+	let	first= mark.chars().next()?;
+	if mark.chars().all( |c| c == first ) {
+		return	Some(first);
+	}
+	return	None;
+}
+
+impl InlineParser {
+	// Parses the text starting at the source position.
+	pub fn	parse_at( &self, text: &str, span: Span ) -> Vec<Inline>
+	{
+		let	mut	list= Vec::new();
+		let	mut	pos= 0;
+		let	mut	at= span;
+		while pos < text.len() {
+			let	rest= &text[pos..];
+			let	prev= text[..pos].chars().next_back();
+			if let Some((nodes,len))= (self.special)( self, rest, prev, at ) {
+				for mut node in nodes {
+					if !node.span.is_known() {
+						node.span= at;
+					}
+					push_inline( &mut list, node );
+				}
+				at= at.advance( &rest[..len] );
+				pos+= len;
+				continue;
+			}
+			if let Some((node,len))= self.match_delimiter( text, pos, at ) {
+				push_inline( &mut list, node );
+				at= at.advance( &rest[..len] );
+				pos+= len;
+				continue;
+			}
+			let	Some(ch)= rest.chars().next() else { break };
+			let	mut	tmp= [0u8;4];
+			push_text( &mut list, ch.encode_utf8( &mut tmp ), at );
+			at= at.advance( &rest[..ch.len_utf8()] );
+			pos+= ch.len_utf8();
+		}
+		return	list;
+	}
+
+	fn	match_delimiter( &self, text: &str, pos: usize, at: Span ) -> Option<(Inline,usize)>
+	{
+		let	rest= &text[pos..];
+		let	prev= text[..pos].chars().next_back();
+		for delim in &self.delimiters {
+			if !rest.starts_with( delim.open ) {
+				continue;
+			}
+			if let Some(ch)= is_run( delim.open ) {
+				if run_length( rest, ch ) != delim.open.len() {
+					continue;
+				}
+			}
+			if delim.word && prev.is_some_and( |c| c.is_alphanumeric() ) {
+				continue;
+			}
+			let	body_start= pos + delim.open.len();
+			let	body= &text[body_start..];
+			if self.tight && body.starts_with( char::is_whitespace ) {
+				continue;
+			}
+			if let Some(body_len)= self.find_close( delim, body ) {
+				let	inner= &body[..body_len];
+				let	inner_at= at.advance( delim.open );
+				let	node= match delim.kind {
+					InlineKind::Code => InlineNode::Code( inner.to_string() ),
+					InlineKind::Strong => InlineNode::Strong( self.parse_at( inner, inner_at ) ),
+					InlineKind::Emphasis => InlineNode::Emphasis( self.parse_at( inner, inner_at ) ),
+					InlineKind::StrongEmphasis => InlineNode::Strong( vec![Inline::at( InlineNode::Emphasis( self.parse_at( inner, inner_at ) ), at )] ),
+					InlineKind::Strike => InlineNode::Strike( self.parse_at( inner, inner_at ) ),
+				};
+				return	Some( (Inline::at( node, at ), delim.open.len() + body_len + delim.close.len()) );
+			}
+		}
+		return	None;
+	}
+
+	fn	find_close( &self, delim: &Delimiter, body: &str ) -> Option<usize>
+	{
+		let	run= is_run( delim.close );
+		for (offset,_) in body.char_indices().skip( 1 ) {
+			let	tail= &body[offset..];
+			if !tail.starts_with( delim.close ) {
+				continue;
+			}
+			let	before= body[..offset].chars().next_back();
+			if let Some(ch)= run {
+				if before == Some(ch) || run_length( tail, ch ) != delim.close.len() {
+					continue;
+				}
+			}
+			if self.tight && before.is_some_and( |c| c.is_whitespace() ) {
+				continue;
+			}
+			if delim.word && tail[delim.close.len()..].starts_with( char::is_alphanumeric ) {
+				continue;
+			}
+			return	Some(offset);
+		}
+		return	None;
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+// Finds the position of the bracket closing the one at the head of text.
+pub fn	find_pair( text: &str, open: char, close: char ) -> Option<usize>
+{
+	let	mut	depth= 0;
+	for (pos,ch) in text.char_indices() {
+		if ch == open {
+			depth+= 1;
+		}else if ch == close {
+			depth-= 1;
+			if depth == 0 {
+				return	Some(pos);
+			}
+		}
+	}
+	return	None;
+}
+
+
+//-----------------------------------------------------------------------------
@@ -1,10 +1,18 @@
 // 2022 Hiroyuki Ogasawara
 // vim:ts=4 sw=4 noet:
 
+pub mod span;
+pub mod inline;
 pub mod element;
+pub mod warning;
+pub mod error;
 pub mod document;
+pub mod format;
+pub mod transform;
 pub mod w_md;
 pub mod w_doku;
 pub mod w_puki;
 pub mod w_red;
 pub mod w_conf;
+pub mod w_html;
+pub mod w_media;
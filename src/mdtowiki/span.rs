@@ -0,0 +1,75 @@
+// 2022 Hiroyuki Ogasawara
+// vim:ts=4 sw=4 noet:
+
+use std::fmt;
+
+//-----------------------------------------------------------------------------
+
+// Position in the source text, 1 based. 0:0 is unknown.
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub struct Span {
+	pub line	: u32,
+	pub column	: u32,
+}
+
+impl Span {
+	pub fn	new( line: u32, column: u32 ) -> Self
+	{
+		Span{ line: line, column: column }
+	}
+	pub fn	is_known( &self ) -> bool
+	{
+		self.line != 0
+	}
+	// The position after the text on the same line.
+	pub fn	advance( &self, text: &str ) -> Span
+	{
+		Span{ line: self.line, column: self.column + text.chars().count() as u32 }
+	}
+	// The position of part, which is a slice of line starting at this position.
+	pub fn	at( &self, line: &str, part: &str ) -> Span
+	{
+		let	offset= (part.as_ptr() as usize).wrapping_sub( line.as_ptr() as usize );
+		if offset > line.len() || !line.is_char_boundary( offset ) {
+			return	*self;
+		}
+		return	self.advance( &line[..offset] );
+	}
+}
+
+impl fmt::Display for Span {
+	fn	fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+	{
+		write!( f, "{}:{}", self.line, self.column )
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+// A source line with the position of its first character.
+#[derive(Debug,Clone)]
+pub struct SourceLine {
+	pub text	: String,
+	pub span	: Span,
+}
+
+impl SourceLine {
+	pub fn	new( text: &str, span: Span ) -> Self
+	{
+		SourceLine{ text: text.to_string(), span: span }
+	}
+	// The rest of the line from part, which is a slice of the text.
+	pub fn	slice( &self, part: &str ) -> Self
+	{
+		SourceLine{ text: part.to_string(), span: self.span.at( &self.text, part ) }
+	}
+	// Numbers the lines read from a file.
+	pub fn	number( lines: Vec<String> ) -> Vec<SourceLine>
+	{
+		lines.into_iter().enumerate().map( |(i,text)| SourceLine{ text: text, span: Span::new( i as u32 + 1, 1 ) } ).collect()
+	}
+}
+
+
+//-----------------------------------------------------------------------------
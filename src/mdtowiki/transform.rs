@@ -0,0 +1,129 @@
+// 2022 Hiroyuki Ogasawara
+// vim:ts=4 sw=4 noet:
+
+use std::collections::HashMap;
+use regex::Regex;
+
+use super::inline::*;
+use super::element::*;
+use super::document::Document;
+use super::error::Error;
+
+
+//-----------------------------------------------------------------------------
+
+// Changes of the document for one output format, before the encoder.
+#[derive(Debug,Clone,Default)]
+pub struct Transform {
+	pub code_language	: HashMap<String,String>,	// "js" to "javascript", "" is the code block without the language
+	pub heading_offset	: i32,						// 1 makes h1 to h2, the levels stay in 1 to 6
+	pub toc				: bool,						// after the title heading, or at the top
+	pub links			: Vec<(Regex,String)>,		// the first matched pattern replaces the url, "$1" is the group
+}
+
+impl Transform {
+	pub fn	is_empty( &self ) -> bool
+	{
+		self.code_language.is_empty() && self.heading_offset == 0 && !self.toc && self.links.is_empty()
+	}
+	// ex.  add_link( r"^(.*)\.md$", "$1" )
+	pub fn	add_link( &mut self, pattern: &str, replace: &str ) -> Result<(),Error>
+	{
+		let	regex= Regex::new( pattern ).map_err( |err| Error::Parse( format!( "link pattern \"{}\"", pattern ), err.to_string() ) )?;
+		self.links.push( (regex, replace.to_string()) );
+		return	Ok( () );
+	}
+	// The title is the leading h1 before the heading offset.
+	pub fn	apply( &self, doc: &mut Document )
+	{
+		let	title= matches!( doc.element_list.first(), Some(Block::HTag(e)) if e.level == 1 );
+		self.transform_blocks( &mut doc.element_list );
+		if self.toc {
+			insert_toc( &mut doc.element_list, title );
+		}
+	}
+
+	fn	rewrite_url( &self, url: &mut String )
+	{
+		for (regex,replace) in &self.links {
+			if regex.is_match( url ) {
+				*url= regex.replace( url, replace.as_str() ).to_string();
+				return;
+			}
+		}
+	}
+	fn	transform_inlines( &self, list: &mut [Inline] )
+	{
+		for node in list {
+			match &mut node.node {
+				InlineNode::Strong(child) | InlineNode::Emphasis(child) | InlineNode::Strike(child) => {
+					self.transform_inlines( child );
+				},
+				InlineNode::Link{ url, text } => {
+					self.rewrite_url( url );
+					self.transform_inlines( text );
+				},
+				_ => {
+				},
+			}
+		}
+	}
+	fn	transform_blocks( &self, list: &mut [Block] )
+	{
+		for block in list {
+			match block {
+				Block::Paragraph(e) => {
+					self.transform_inlines( &mut e.text );
+				},
+				Block::HTag(e) => {
+					e.level= (e.level as i32 + self.heading_offset).clamp( 1, 6 ) as u32;
+					self.transform_inlines( &mut e.title );
+				},
+				Block::List(e) => {
+					for item in &mut e.items {
+						self.transform_blocks( &mut item.blocks );
+					}
+				},
+				Block::PRETag(e) => {
+					if let Some(code)= self.code_language.get( &e.code ) {
+						e.code= code.clone();
+					}
+				},
+				Block::Table(e) => {
+					for row in &mut e.data {
+						for cell in &mut row.data {
+							self.transform_inlines( &mut cell.text );
+						}
+					}
+				},
+				Block::Quote(e) => {
+					self.transform_blocks( &mut e.blocks );
+				},
+				Block::None | Block::HRTag(_) | Block::TOC(_) => {
+				},
+			}
+		}
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+// The toc lists the headings after it, the leading h1 is the title of the page.
+fn	insert_toc( list: &mut Vec<Block>, title: bool )
+{
+	if list.iter().any( |block| matches!( block, Block::TOC(_) ) ) {
+		return;
+	}
+	let	index= if title { 1 } else { 0 };
+	let	entries= toc_entries( &list[index..] );
+	if entries.is_empty() {
+		return;
+	}
+	let	span= list.get( index ).map_or( Default::default(), |block| block.span() );
+	list.insert( index, Block::TOC( TOCElement{ entries: entries, span: span } ) );
+}
+
+
+//-----------------------------------------------------------------------------
+
@@ -3,8 +3,14 @@
 
 //use std::io;
 
+use regex;
+use	lazy_static::lazy_static;
+
+use super::span::*;
+use super::inline::*;
 use super::element::*;
 use super::document::*;
+use super::warning::*;
 
 
 #[allow(dead_code)]
@@ -18,191 +24,861 @@ fn print_type<T>( _: &T )
 
 //=============================================================================
 
-pub	fn	encode_to_conf( line: &str ) -> String
+// Reads "!img.png|width=200,alt=text!".
+fn	param_image( src: &str, params: &str ) -> Image
+{
+	let	mut	image= Image::new( src );
+	for param in params.split( ',' ) {
+		let	Some((key,value))= param.split_once( '=' ) else { continue };
+		match key.trim() {
+			"width" => image.width= value.trim().trim_end_matches( "px" ).parse().ok(),
+			"height" => image.height= value.trim().trim_end_matches( "px" ).parse().ok(),
+			"alt" => image.alt= value.trim().to_string(),
+			"title" => image.title= value.trim().to_string(),
+			_ => {},
+		}
+	}
+	return	image;
+}
+
+fn	conf_special( parser: &InlineParser, text: &str, _prev: Option<char>, at: Span ) -> Option<(Vec<Inline>,usize)>
+{
+	lazy_static! {
+		static ref	IMAGE: regex::Regex= regex::Regex::new( r"^!([^\s!|]+)(\|([^!]*))?!" ).unwrap();
+		static ref	MACRO: regex::Regex= regex::Regex::new( r"^\{(color|anchor)(:[^}]*)?\}" ).unwrap();
+	}
+	if let Some(rest)= text.strip_prefix( "\\\\" ) {
+		let	len= if rest.starts_with( ' ' ) { 3 } else { 2 };
+		return	Some( (vec![Inline::new( InlineNode::LineBreak )], len) );
+	}
+	if let Some(rest)= text.strip_prefix( '\\' ) {
+		let	ch= rest.chars().next()?;
+		return	Some( (vec![Inline::new( InlineNode::Text( ch.to_string() ) )], 1 + ch.len_utf8()) );
+	}
+	// {color:red} and {color} are dropped, {anchor:name} too.
+	if let Some(m)= MACRO.find( text ) {
+		return	Some( (Vec::new(), m.end()) );
+	}
+	if let Some(body)= text.strip_prefix( '[' ) {
+		let	epos= find_pair( text, '[', ']' )? - 1;
+		let	inner= &body[..epos];
+		let	mut	params= inner.splitn( 3, '|' );
+		let	first= params.next().unwrap_or( "" );
+		let	link= match params.next() {
+			Some(url) => InlineNode::Link{ url: url.trim().to_string(), text: parser.parse_at( first, at.at( text, first ) ) },
+			None => InlineNode::Link{ url: first.trim().to_string(), text: Vec::new() },
+		};
+		return	Some( (vec![Inline::new( link )], epos + 2) );
+	}
+	if let Some(v)= IMAGE.captures( text ) {
+		let	image= param_image( &v[1], v.get( 3 ).map_or( "", |m| m.as_str() ) );
+		return	Some( (vec![Inline::new( InlineNode::Image( image ) )], v[0].len()) );
+	}
+	return	None;
+}
+
+pub fn	decode_from_conf( line: &str, span: Span ) -> Vec<Inline>
+{
+	lazy_static! {
+		static ref	PARSER: InlineParser= InlineParser{
+				delimiters: vec![
+					Delimiter{ open: "{{",	close: "}}",	kind: InlineKind::Code,		word: false, },
+					Delimiter{ open: "*",	close: "*",		kind: InlineKind::Strong,	word: true, },
+					Delimiter{ open: "_",	close: "_",		kind: InlineKind::Emphasis,	word: true, },
+					Delimiter{ open: "??",	close: "??",	kind: InlineKind::Emphasis,	word: true, },
+					Delimiter{ open: "-",	close: "-",		kind: InlineKind::Strike,	word: true, },
+				],
+				tight: true,
+				special: conf_special,
+			};
+	}
+	return	PARSER.parse_at( line, span );
+}
+
+fn	decode_conf_line( line: &str, span: Span ) -> Vec<Inline>
+{
+	let	trimmed= line.trim();
+	return	decode_from_conf( trimmed, span.at( line, trimmed ) );
+}
+
+
+pub	fn	encode_to_conf( list: &[Inline] ) -> String
 {
 	let	mut	buffer= String::new();
-	let	mut	char_it= line.chars();
-	loop {
-		let	ch= char_it.next();
-		match ch {
-			Some('\x07') => {
-				let	cmd0= char_it.next().unwrap();
-				let	cmd1= char_it.next().unwrap();
-				match cmd0 {
-					'B'|'b' => {
-						match cmd1 {
-							'1' => {
-								buffer+= "_";
-							},
-							'2' => {
-								buffer+= "*";
-							},
-							_ => {
-								buffer+= "";
-							},
-						}
-					},
-					'D'|'d' => {
-						buffer+= "~";
-					},
-					'C'|'c' => {
-						buffer+= " ";
-					},
-					'L' => {
-						match cmd1 {
-							'0' => {
-								let	mut	url= "";
-								let	mut	text= "";
-								if let Some(upos)= char_it.as_str().find( '\x07' ) {
-									url= &char_it.as_str()[..upos];
-									for _ in 0..upos {
-										char_it.next();
-									}
-									char_it.next();
-									char_it.next();
-									char_it.next();
-									if let Some(tpos)= char_it.as_str().find( '\x07' ) {
-										text= &char_it.as_str()[..tpos];
-										for _ in 0..tpos {
-											char_it.next();
-										}
-										char_it.next();
-										char_it.next();
-										char_it.next();
-									}
-								}
-								buffer+= "[";
-								buffer+= text;
-								buffer+= "|";
-								buffer+= url;
-								buffer+= "]";
-							},
-							_ => {
-							},
-						}
-					},
-					_ => {
-					},
+	for node in list {
+		match &node.node {
+			InlineNode::Text(text) => {
+				buffer+= text;
+			},
+			InlineNode::Strong(child) => {
+				buffer+= &format!( "*{}*", encode_to_conf( child ) );
+			},
+			InlineNode::Emphasis(child) => {
+				buffer+= &format!( "_{}_", encode_to_conf( child ) );
+			},
+			InlineNode::Strike(child) => {
+				buffer+= &format!( "-{}-", encode_to_conf( child ) );
+			},
+			InlineNode::Code(text) => {
+				buffer+= &format!( "{{{{{}}}}}", text );
+			},
+			InlineNode::Link{ url, text } => {
+				if text.is_empty() {
+					buffer+= &format!( "[{}]", url );
+				}else{
+					buffer+= &format!( "[{}|{}]", encode_to_conf( text ), url );
 				}
 			},
-			Some(c) => {
-				buffer+= &format!( "{}", c );
+			InlineNode::Image(image) => {
+				let	mut	params= Vec::new();
+				if let Some(w)= image.width {
+					params.push( format!( "width={}", w ) );
+				}
+				if let Some(h)= image.height {
+					params.push( format!( "height={}", h ) );
+				}
+				if !image.alt.is_empty() {
+					params.push( format!( "alt={}", image.alt ) );
+				}
+				if !image.title.is_empty() {
+					params.push( format!( "title={}", image.title ) );
+				}
+				if params.is_empty() {
+					buffer+= &format!( "!{}!", image.src );
+				}else{
+					buffer+= &format!( "!{}|{}!", image.src, params.join( "," ) );
+				}
 			},
-			None => {
-				return	buffer;
+			InlineNode::SoftBreak => {
+				buffer+= "\n";
+			},
+			InlineNode::LineBreak => {
+				buffer+= "\\\\\n";
 			},
 		}
 	}
+	return	buffer;
 }
 
 
 //=============================================================================
 
 
-//=============================================================================
+//-----------------------------------------------------------------------------
 
-trait	EncodeElement {
-	fn	output( &self ) -> String;
+trait ElementGenerator {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>;
+}
+
+
+struct GenerateorContext {
+	// current line
+	span: Span,
+	// paragraph
+	para: ParagraphElement,
+	// list
+	list_block: bool,
+	list_entries: Vec<ListEntry>,
+	list_pat: regex::Regex,
+	// quote
+	quote_block: bool,
+	quote_closed: bool,
+	quote_end: String,
+	quote_title: String,
+	quote_lines: Vec<SourceLine>,
+	quote_span: Span,
+	// pre
+	pre_block: bool,
+	pre_end: String,
+	pre_span: Span,
+	pre_code: String,
+	pre_data: String,
+	// table
+	table_block: bool,
+	table_grid: Vec<(Span, Vec<TableGrid<TableAttr>>)>,
+	table_pat: regex::Regex,
+}
+
+impl GenerateorContext {
+	fn	new() -> Self
+	{
+		GenerateorContext{
+				span: Span::default(),
+				para: ParagraphElement::new(),
+				list_block: false,
+				list_entries: Vec::new(),
+				list_pat: regex::Regex::new( r"^\s*([*#-]+)\s+(.*)$" ).unwrap(),
+				quote_block: false,
+				quote_closed: false,
+				quote_end: String::new(),
+				quote_title: String::new(),
+				quote_lines: Vec::new(),
+				quote_span: Span::default(),
+				pre_block: false,
+				pre_end: String::new(),
+				pre_span: Span::default(),
+				pre_code: String::new(),
+				pre_data: String::new(),
+				table_block: false,
+				table_grid: Vec::new(),
+				table_pat: regex::Regex::new( r"^\s*\|.*\|\s*$" ).unwrap(),
+			}
+	}
+	//-------------------------------------------------------------------------
+	fn	is_list_block( &self ) -> bool
+	{
+		self.list_block
+	}
+	fn	add_list_block( &mut self, line: &str ) -> Option<Vec<Block>>
+	{
+		let	pat_result= self.list_pat.captures( line );
+		if let Some(v)= pat_result {
+			if !is_rule( line ) {
+				self.list_entries.push( list_entry( &v, line, self.span ) );
+				return	None;
+			}
+		}
+		self.list_block= false;
+		return	Some( build_list_tree( &mut self.list_entries ) );
+	}
+	//-------------------------------------------------------------------------
+	fn	is_quote_block( &self ) -> bool
+	{
+		self.quote_block
+	}
+	// The quote is closed by "{quote}" or "{panel}", the text in front of it is in the quote.
+	fn	add_quote_block( &mut self, line: &str )
+	{
+		let	text= match line.find( self.quote_end.as_str() ) {
+				Some(pos) => {
+					self.quote_closed= true;
+					&line[..pos]
+				},
+				None => line,
+			};
+		if !self.quote_closed || !text.trim().is_empty() {
+			self.quote_lines.push( SourceLine::new( text, self.span.at( line, text ) ) );
+		}
+	}
+	fn	is_quote_closed( &self ) -> bool
+	{
+		self.quote_closed
+	}
+	//-------------------------------------------------------------------------
+	fn	is_pre_block( &self ) -> bool
+	{
+		self.pre_block
+	}
+	fn	add_pre_block( &mut self, line: &str ) -> Option<Block>
+	{
+		if let Some(pos)= line.find( self.pre_end.as_str() ) {
+			self.pre_block= false;
+			if pos != 0 {
+				self.pre_data+= &line[..pos];
+				self.pre_data+= "\n";
+			}
+			return	Some( Block::PRETag( PRETagElement{
+							text: 	std::mem::take( &mut self.pre_data ),
+							code:	std::mem::take( &mut self.pre_code ),
+							span:	self.pre_span,
+						}));
+		}
+		self.pre_data+= line;
+		self.pre_data+= "\n";
+		return	None;
+	}
+	//-------------------------------------------------------------------------
+	fn	is_table_block( &self ) -> bool
+	{
+		self.table_block
+	}
+	// "||" in front of the cell is the header cell, "|" is the data cell.
+	fn	add_table_block( &mut self, line: &str ) -> Option<Block>
+	{
+		if self.table_pat.is_match( line ) {
+			let	mut	cells= Vec::new();
+			for (td,header) in split_cells( line.trim() ) {
+				let	text= td.trim();
+				cells.push( TableGrid::Cell( TableAttr{
+						text:		decode_from_conf( text, self.span.at( line, text ) ),
+						align:		ETableAlign::DEFAULT,
+						header:		Some(header),
+						colspan:	1,
+						rowspan:	1,
+					}));
+			}
+			self.table_grid.push( (self.span, cells) );
+		}else{
+			self.table_block= false;
+			return	Some( Block::Table( build_table( std::mem::take( &mut self.table_grid ) ) ) );
+		}
+		return	None;
+	}
+	//-------------------------------------------------------------------------
+}
+
+// Splits "||h1||h2||" and "|a|b|" to the cells, the '|' in the link, the macro and the image is not the separator.
+fn	split_cells( line: &str ) -> Vec<(&str,bool)>
+{
+	let	mut	cells= Vec::new();
+	let	mut	rest= line;
+	while let Some(tail)= rest.strip_prefix( '|' ) {
+		let	header= tail.starts_with( '|' );
+		let	tail= if header { &tail[1..] } else { tail };
+		let	mut	depth= 0;
+		let	mut	image= false;
+		let	mut	end= tail.len();
+		for (pos,ch) in tail.char_indices() {
+			match ch {
+				'[' | '{' => depth+= 1,
+				']' | '}' => depth-= 1,
+				'!' => image= !image && tail[pos+1..].find( '!' ).is_some_and( |len| !tail[pos+1..pos+1+len].contains( char::is_whitespace ) ),
+				'|' if depth <= 0 && !image => {
+					end= pos;
+					break;
+				},
+				_ => {},
+			}
+		}
+		if end == tail.len() && tail.trim().is_empty() {
+			break;
+		}
+		cells.push( (&tail[..end], header) );
+		rest= &tail[end..];
+	}
+	return	cells;
 }
 
 
 //-----------------------------------------------------------------------------
 
-impl	EncodeElement for NONEElement {
-	fn	output( &self ) -> String
+struct HTagGen {
+	pat : regex::Regex,
+}
+
+impl HTagGen {
+	fn	new() -> Self
 	{
-		return	String::new();
+		HTagGen{
+			pat: regex::Regex::new( r"^\s*h([1-6])\.\s+(.*)$" ).unwrap(),
+		}
+	}
+}
+
+impl ElementGenerator for HTagGen {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		let	pat_result= self.pat.captures( line );
+		if let Some(v)= pat_result {
+			let	title= v[2].trim();
+			return	Some( Block::HTag( HTagElement{
+							title: 	decode_from_conf( title, context.span.at( line, title ) ),
+							level:	v[1].parse().unwrap_or( 1 ),
+							span:	context.span,
+						}));
+		}
+		return	None;
 	}
 }
 
 
 //-----------------------------------------------------------------------------
 
-impl	EncodeElement for HTagElement {
-	fn	output( &self ) -> String
+// {toc} or {toc:maxLevel=3}, the headings are filled at the end of the page.
+struct TOCGen {
+	pat : regex::Regex,
+}
+
+impl TOCGen {
+	fn	new() -> Self
 	{
-		return	format!( "h{}. {}\n", self.level, self.title );
+		TOCGen{
+			pat: regex::Regex::new( r"^\s*\{toc(:[^}]*)?\}\s*$" ).unwrap(),
+		}
+	}
+}
+
+impl ElementGenerator for TOCGen {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		if self.pat.is_match( line ) {
+			return	Some( Block::TOC( TOCElement{ entries: Vec::new(), span: context.span } ) );
+		}
+		return	None;
 	}
 }
 
 
 //-----------------------------------------------------------------------------
 
-impl	EncodeElement for LITagElement {
-	fn	output( &self ) -> String
+// "*", "-" and "#" are the depth of the list, the last mark tells the ordered list.
+fn	list_entry( v: &regex::Captures, line: &str, span: Span ) -> ListEntry
+{
+	let	text= v[2].trim();
+	ListEntry{
+		depth:		v[1].len() as u32 - 1,
+		ordered:	v[1].ends_with( '#' ),
+		text:		decode_from_conf( text, span.at( line, text ) ),
+		span:		span.at( line, &v[1] ),
+	}
+}
+
+struct LITagGen {
+	pat: regex::Regex,
+}
+
+impl LITagGen {
+	fn	new() -> Self
 	{
-		let	indent= self.nest+1;
-		if self.etype == ElementType::ULTAG {
-			return	format!( "{} {}\n", '*'.to_string().repeat(indent as usize), encode_to_conf( &self.text ) );
-		}else{
-			return	format!( "{} {}\n", '#'.to_string().repeat(indent as usize), encode_to_conf( &self.text ) );
+		LITagGen{
+			pat: regex::Regex::new( r"^\s*([*#-]+)\s+(.*)$" ).unwrap(),
 		}
 	}
 }
 
+impl ElementGenerator for LITagGen {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		let	pat_result= self.pat.captures( line );
+		if let Some(v)= pat_result {
+			context.list_block= true;
+			context.list_entries= vec![list_entry( &v, line, context.span )];
+			return	Some( Block::None );
+		}
+		return	None;
+	}
+}
+
 
 //-----------------------------------------------------------------------------
 
-impl	EncodeElement for PRETagElement {
-	fn	output( &self ) -> String
+// {quote}, "bq." of the one line, and {panel:title=..} with the note macros.
+// The title of the panel is the first line of the quote.
+struct QuoteGen {
+	pat : regex::Regex,
+	pat_bq : regex::Regex,
+}
+
+impl QuoteGen {
+	fn	new() -> Self
 	{
-		if self.code.is_empty() {
-			return	format!( "{{code:linenumbers=false|collapse=false}}\n{}{{code}}\n", self.text );
+		QuoteGen{
+			pat: regex::Regex::new( r"^\s*\{(quote|panel|info|note|tip|warning)(?::([^}]*))?\}(.*)$" ).unwrap(),
+			pat_bq: regex::Regex::new( r"^\s*bq\.\s+(.*)$" ).unwrap(),
 		}
-		let mut lang_type= self.code.to_string();
-		if lang_type == "json" {
-			lang_type= "js".to_string();
+	}
+}
+
+impl ElementGenerator for QuoteGen {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		if let Some(v)= self.pat_bq.captures( line ) {
+			let	text= v.get( 1 ).map_or( "", |m| m.as_str() );
+			context.quote_block= true;
+			context.quote_closed= true;
+			context.quote_title= String::new();
+			context.quote_lines= vec![SourceLine::new( text, context.span.at( line, text ) )];
+			context.quote_span= context.span;
+			return	Some( Block::None );
 		}
-		return	format!( "{{code:language={}|linenumbers=false|collapse=false}}\n{}{{code}}\n", lang_type, self.text );
+		let	pat_result= self.pat.captures( line );
+		if let Some(v)= pat_result {
+			let	params= v.get( 2 ).map_or( "", |m| m.as_str() );
+			context.quote_block= true;
+			context.quote_closed= false;
+			context.quote_end= format!( "{{{}}}", &v[1] );
+			context.quote_title= params.split( '|' ).find_map( |param| param.strip_prefix( "title=" ) ).unwrap_or( "" ).trim().to_string();
+			context.quote_lines= Vec::new();
+			context.quote_span= context.span;
+			let	rest= v.get( 3 ).map_or( "", |m| m.as_str() );
+			if !rest.trim().is_empty() {
+				context.add_quote_block( rest );
+			}
+			return	Some( Block::None );
+		}
+		return	None;
 	}
 }
 
 
 //-----------------------------------------------------------------------------
 
-impl	EncodeElement for TABLEElement {
-	fn	output( &self ) -> String
+// {code}, {code:java}, {code:language=java|title=..} and {noformat}.
+struct PRETagGen {
+	pat : regex::Regex,
+}
+
+impl PRETagGen {
+	fn	new() -> Self
 	{
-		let	mut	buffer= String::new();
-		for column in &self.data {
-			let mut cindex= 0;
-			for attr in &column.data {
-				if attr.header {
-					if cindex == 0 {
-						buffer+= "||";
+		PRETagGen{
+			pat: regex::Regex::new( r"^\s*\{(code|noformat)(?::([^}]*))?\}(.*)$" ).unwrap(),
+		}
+	}
+}
+
+// The language is "language=x", or the first parameter without '='.
+fn	code_language( params: &str ) -> String
+{
+	for (index,param) in params.split( '|' ).enumerate() {
+		match param.split_once( '=' ) {
+			Some((key,value)) if key.trim() == "language" => return	value.trim().to_string(),
+			None if index == 0 => return	param.trim().to_string(),
+			_ => {},
+		}
+	}
+	return	String::new();
+}
+
+impl ElementGenerator for PRETagGen {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		let	pat_result= self.pat.captures( line );
+		if let Some(v)= pat_result {
+			context.pre_block= true;
+			context.pre_end= format!( "{{{}}}", &v[1] );
+			context.pre_span= context.span;
+			context.pre_code= if &v[1] == "code" { code_language( v.get( 2 ).map_or( "", |m| m.as_str() ) ) } else { String::new() };
+			context.pre_data= String::new();
+			let	rest= &v[3];
+			if !rest.is_empty() {
+				if let Some(e)= context.add_pre_block( rest ) {
+					return	Some(e);
+				}
+			}
+			return	Some( Block::None );
+		}
+		return	None;
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+struct TABLEGen {
+	pat : regex::Regex,
+}
+
+impl TABLEGen {
+	fn	new() -> Self
+	{
+		TABLEGen{
+			pat: regex::Regex::new( r"^\s*\|.*\|\s*$" ).unwrap(),
+		}
+	}
+}
+
+impl ElementGenerator for TABLEGen {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		let	pat_result= self.pat.captures( line );
+		if let Some(_)= pat_result {
+			context.table_block= true;
+			context.table_grid= Vec::new();
+			if let Some(e)= context.add_table_block( line ) {
+				return	Some(e);
+			}
+			return	Some( Block::None );
+		}
+		return	None;
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+fn	is_rule( line: &str ) -> bool
+{
+	let	line= line.trim();
+	line.len() >= 4 && line.chars().all( |ch| ch == '-' )
+}
+
+struct HRTagGen {
+}
+
+impl HRTagGen {
+	fn	new() -> Self
+	{
+		HRTagGen{
+		}
+	}
+}
+
+impl ElementGenerator for HRTagGen {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		if is_rule( line ) {
+			return	Some( Block::HRTag( context.span ) );
+		}
+		return	None;
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+pub struct Decoder {
+	gen_table: Vec<Box<dyn ElementGenerator>>,
+}
+
+impl Decoder {
+	pub fn new() -> Self
+	{
+		let	gen_table: Vec<Box<dyn ElementGenerator>>= vec![
+			Box::new( HRTagGen::new() ),
+			Box::new( HTagGen::new() ),
+			Box::new( TOCGen::new() ),
+			Box::new( LITagGen::new() ),
+			Box::new( QuoteGen::new() ),
+			Box::new( PRETagGen::new() ),
+			Box::new( TABLEGen::new() ),
+		];
+		Decoder{
+			gen_table: gen_table,
+		}
+	}
+	fn	find( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		for gen in &self.gen_table {
+			let	element= gen.generate( line, context );
+			if let Some(_)= element {
+				return	element;
+			}
+		}
+		return	None;
+	}
+	fn	build_quote( &self, context: &mut GenerateorContext ) -> Block
+	{
+		let	span= context.quote_span;
+		let	mut	blocks= self.decode_lines( &std::mem::take( &mut context.quote_lines ) ).element_list;
+		let	title= std::mem::take( &mut context.quote_title );
+		if !title.is_empty() {
+			let	text= vec![Inline::at( InlineNode::Strong( decode_from_conf( &title, span ) ), span )];
+			blocks.insert( 0, Block::Paragraph( ParagraphElement{ text: text, span: span } ) );
+		}
+		context.quote_block= false;
+		context.quote_closed= false;
+		Block::Quote( BlockQuoteElement{ blocks: blocks, span: span } )
+	}
+}
+
+impl TextDecoder for Decoder {
+	fn	decode_lines( &self, lines: &[SourceLine] ) -> Document
+	{
+		let	mut	page= Document::new();
+		let	mut	context= GenerateorContext::new();
+
+		for source in lines {
+			let	line= &source.text;
+			context.span= source.span;
+			if context.is_pre_block() {
+				if let Some(e)= context.add_pre_block( line ) {
+					page.push( e );
+				}
+				continue;
+			}else if context.is_quote_block() {
+				context.add_quote_block( line );
+				if context.is_quote_closed() {
+					page.push( self.build_quote( &mut context ) );
+				}
+				continue;
+			}else if context.is_table_block() {
+				if let Some(e)= context.add_table_block( line ) {
+					page.push( e );
+				}else{
+					continue;
+				}
+			}else if context.is_list_block() {
+				if let Some(list)= context.add_list_block( line ) {
+					for e in list {
+						page.push( e );
 					}
-					buffer+= &format!( " {} ||", encode_to_conf( &attr.text ) );
 				}else{
-					if cindex == 0 {
-						buffer+= "|";
+					continue;
+				}
+			}
+			{
+				if let Some(e)= self.find( line, &mut context ) {
+					if let Some(p)= context.para.finish() {
+						page.push( p );
 					}
-					buffer+= &format!( " {} |", encode_to_conf( &attr.text ) );
+					page.push( e );
+					if context.is_quote_closed() {
+						page.push( self.build_quote( &mut context ) );
+					}
+				}else if line.trim().is_empty() {
+					if let Some(p)= context.para.finish() {
+						page.push( p );
+					}
+				}else{
+					context.para.add_line( decode_conf_line( line, source.span ) );
 				}
-				cindex+= 1;
 			}
+		}
+		if context.is_list_block() {
+			for e in build_list_tree( &mut context.list_entries ) {
+				page.push( e );
+			}
+		}else if context.is_quote_block() {
+			page.push( self.build_quote( &mut context ) );
+		}else if context.is_table_block() {
+			page.push( Block::Table( build_table( std::mem::take( &mut context.table_grid ) ) ) );
+		}else if context.is_pre_block() {
+			page.push( Block::PRETag( PRETagElement{ text: context.pre_data, code: context.pre_code, span: context.pre_span } ) );
+		}
+		if let Some(p)= context.para.finish() {
+			page.push( p );
+		}
+		fill_toc( &mut page.element_list );
+		page
+	}
+}
+
+
+//=============================================================================
+
+trait	EncodeElement {
+	fn	output( &self, encoder: &Encoder ) -> String;
+}
+
+
+//-----------------------------------------------------------------------------
+
+impl	EncodeElement for HTagElement {
+	fn	output( &self, _encoder: &Encoder ) -> String
+	{
+		return	format!( "h{}. {}\n", self.level.clamp( 1, 6 ), encode_to_conf( &self.title ) );
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+impl	EncodeElement for TOCElement {
+	fn	output( &self, _encoder: &Encoder ) -> String
+	{
+		return	"{toc}\n".to_string();
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+// A list item is a single line, the other blocks follow the item.
+fn	output_list( list: &ListElement, encoder: &Encoder, prefix: &str ) -> String
+{
+	let	mut	buffer= String::new();
+	let	mark= format!( "{}{}", prefix, if list.ordered { '#' } else { '*' } );
+	for item in &list.items {
+		let	mut	text= String::new();
+		let	mut	children= String::new();
+		for block in &item.blocks {
+			match block {
+				Block::Paragraph(e) => {
+					if !text.is_empty() {
+						text+= " \\\\ ";
+					}
+					text+= &render_paragraph( &e.text, ParagraphMode::Join, encode_to_conf ).replace( '\n', " " );
+				},
+				Block::List(e) => {
+					children+= &output_list( e, encoder, &mark );
+				},
+				_ => {
+					children+= &encoder.encode_single( block );
+				},
+			}
+		}
+		buffer+= &format!( "{} {}\n", mark, text );
+		buffer+= &children;
+	}
+	return	buffer;
+}
+
+impl	EncodeElement for ListElement {
+	fn	output( &self, encoder: &Encoder ) -> String
+	{
+		output_list( self, encoder, "" )
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+// {quote} can't nest, the inner quotes are flattened.
+fn	output_quote( blocks: &[Block], encoder: &Encoder ) -> String
+{
+	let	mut	buffer= String::new();
+	for (i,block) in blocks.iter().enumerate() {
+		if i != 0 {
 			buffer+= "\n";
 		}
-		return	buffer;
+		match block {
+			Block::Quote(e) => {
+				buffer+= &output_quote( &e.blocks, encoder );
+			},
+			_ => {
+				buffer+= &encoder.encode_single( block );
+			},
+		}
+	}
+	return	buffer;
+}
+
+impl	EncodeElement for BlockQuoteElement {
+	fn	output( &self, encoder: &Encoder ) -> String
+	{
+		return	format!( "{{quote}}\n{}{{quote}}\n", output_quote( &self.blocks, encoder ) );
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+impl	EncodeElement for PRETagElement {
+	fn	output( &self, _encoder: &Encoder ) -> String
+	{
+		if self.code.is_empty() {
+			return	format!( "{{code:linenumbers=false|collapse=false}}\n{}{{code}}\n", self.text );
+		}
+		let mut lang_type= self.code.to_string();
+		if lang_type == "json" {
+			lang_type= "js".to_string();
+		}
+		return	format!( "{{code:language={}|linenumbers=false|collapse=false}}\n{}{{code}}\n", lang_type, self.text );
 	}
 }
 
 
 //-----------------------------------------------------------------------------
 
-impl	EncodeElement for HRTagElement {
-	fn	output( &self ) -> String
+impl	EncodeElement for TABLEElement {
+	fn	output( &self, _encoder: &Encoder ) -> String
 	{
-		return	"----\n".to_string();
+		if self.has_span() {
+			return	self.flatten().output( _encoder );
+		}
+		let	mut	buffer= String::new();
+		for (row,line) in self.grid().iter().enumerate() {
+			// The separator in front of the cell tells the header cell.
+			let	mut	sep= "|";
+			for (col,cell) in line.iter().enumerate() {
+				let	TableGrid::Cell(attr)= cell else { continue };
+				sep= if self.cell_header( row, col, attr ) { "||" } else { "|" };
+				buffer+= &format!( "{} {} ", sep, encode_to_conf( &attr.text ) );
+			}
+			buffer+= sep;
+			buffer+= "\n";
+		}
+		return	buffer;
 	}
 }
 
 
 //-----------------------------------------------------------------------------
 
-impl	EncodeElement for DataElement {
-	fn	output( &self ) -> String
+impl	EncodeElement for ParagraphElement {
+	fn	output( &self, encoder: &Encoder ) -> String
 	{
-		encode_to_conf( &self.text ) + "\n"
+		render_paragraph( &self.text, encoder.paragraph, encode_to_conf ) + "\n"
 	}
 }
 
@@ -210,41 +886,55 @@ impl	EncodeElement for DataElement {
 //-----------------------------------------------------------------------------
 
 pub struct	Encoder {
+	pub paragraph	: ParagraphMode,
 }
 
 impl	Encoder {
 	pub	fn	new() -> Self
 	{
-		Self{}
+		Self{
+			paragraph: ParagraphMode::Join,
+		}
 	}
 }
 
 impl	TextEncoder for Encoder {
-	fn	encode_single( &self, element: &Box<dyn Element> ) -> String
+	fn	encode_single( &self, element: &Block ) -> String
 	{
-		let	any= element.as_any();
-		if let Some(e)= any.downcast_ref::<NONEElement>() {
-			return	e.output();
+		match element {
+			Block::None => String::new(),
+			Block::Paragraph(e) => e.output( self ),
+			Block::HTag(e) => e.output( self ),
+			Block::List(e) => e.output( self ),
+			Block::PRETag(e) => e.output( self ),
+			Block::Table(e) => e.output( self ),
+			Block::Quote(e) => e.output( self ),
+			Block::HRTag(_) => "----\n".to_string(),
+			Block::TOC(e) => e.output( self ),
 		}
-		if let Some(e)= any.downcast_ref::<HTagElement>() {
-			return	e.output();
-		}
-		if let Some(e)= any.downcast_ref::<LITagElement>() {
-			return	e.output();
-		}
-		if let Some(e)= any.downcast_ref::<PRETagElement>() {
-			return	e.output();
-		}
-		if let Some(e)= any.downcast_ref::<TABLEElement>() {
-			return	e.output();
+	}
+	fn	check_block( &self, element: &Block, warnings: &mut Vec<Warning> )
+	{
+		if let Block::Quote(e)= element {
+			if e.blocks.iter().any( |block| matches!( block, Block::Quote(_) ) ) {
+				warnings.push( Warning::new( LossKind::Degraded, "nested quote", "flattened into one quote", e.span ) );
+			}
 		}
-		if let Some(e)= any.downcast_ref::<HRTagElement>() {
-			return	e.output();
+		if let Block::Table(e)= element {
+			if e.has_span() {
+				warnings.push( Warning::new( LossKind::Degraded, "table span", "expanded to empty cells", e.span ) );
+			}
+			for (row,line) in e.grid().iter().enumerate() {
+				let	aligned= line.iter().enumerate().any( |(col,cell)| matches!( cell, TableGrid::Cell(attr) if e.cell_align( row, col, attr ) != ETableAlign::DEFAULT ) );
+				if aligned {
+					warnings.push( Warning::new( LossKind::Dropped, "cell alignment", "confluence tables have no alignment", e.data[row].span ) );
+					break;
+				}
+			}
 		}
-		if let Some(e)= any.downcast_ref::<DataElement>() {
-			return	e.output();
+		if let Block::List(e)= element {
+			check_list_items( e, warnings );
 		}
-		return	String::new();
 	}
 }
 
@@ -1,13 +1,14 @@
 // 2022 Hiroyuki Ogasawara
 // vim:ts=4 sw=4 noet:
 
-use std::fs;
-use std::io::{self,BufRead};
-use regex::{self,Regex};
+use regex;
 use	lazy_static::lazy_static;
 
+use super::span::*;
+use super::inline::*;
 use super::element::*;
 use super::document::*;
+use super::warning::*;
 
 
 #[allow(dead_code)]
@@ -21,81 +22,77 @@ fn print_type<T>( _: &T )
 
 //=============================================================================
 
-struct EMMarkPat {
-	pat:  Regex,
-	cmd1: &'static str,
-	cmd2: &'static str,
+fn	doku_special( parser: &InlineParser, text: &str, _prev: Option<char>, at: Span ) -> Option<(Vec<Inline>,usize)>
+{
+	if let Some(rest)= text.strip_prefix( "\\\\" ) {
+		if rest.is_empty() {
+			return	Some( (vec![Inline::new( InlineNode::LineBreak )], 2) );
+		}
+		if rest.starts_with( ' ' ) {
+			return	Some( (vec![Inline::new( InlineNode::LineBreak )], 3) );
+		}
+	}
+	if text.starts_with( "://" ) {
+		return	Some( (vec![Inline::new( InlineNode::Text( "://".to_string() ) )], 3) );
+	}
+	if let Some(body)= text.strip_prefix( "''%%" ) {
+		let	epos= body.find( "%%''" )?;
+		return	Some( (vec![Inline::new( InlineNode::Code( body[..epos].to_string() ) )], epos + 8) );
+	}
+	for (open,close) in [("<nowiki>","</nowiki>"), ("%%","%%")] {
+		if let Some(body)= text.strip_prefix( open ) {
+			let	epos= body.find( close )?;
+			return	Some( (vec![Inline::new( InlineNode::Text( body[..epos].to_string() ) )], open.len() + epos + close.len()) );
+		}
+	}
+	if let Some(body)= text.strip_prefix( "{{" ) {
+		let	epos= body.find( "}}" )?;
+		let	(target,alt)= body[..epos].split_once( '|' ).unwrap_or( (&body[..epos], "") );
+		let	(src,params)= target.trim().split_once( '?' ).unwrap_or( (target.trim(), "") );
+		let	mut	image= Image::new( src );
+		for param in params.split( '&' ) {
+			image.set_size( param );
+		}
+		image.alt= alt.trim().to_string();
+		return	Some( (vec![Inline::new( InlineNode::Image( image ) )], epos + 4) );
+	}
+	if let Some(body)= text.strip_prefix( "[[" ) {
+		let	epos= body.find( "]]" )?;
+		let	inner= &body[..epos];
+		let	link= match inner.split_once( '|' ) {
+			Some((url,label)) => InlineNode::Link{ url: url.trim().to_string(), text: parser.parse_at( label, at.at( text, label ) ) },
+			None => InlineNode::Link{ url: inner.trim().to_string(), text: Vec::new() },
+		};
+		return	Some( (vec![Inline::new( link )], epos + 4) );
+	}
+	return	None;
 }
 
-//=============================================================================
-
-pub fn	replace_doku_tags( line: &str ) -> String
+pub fn	decode_from_doku( line: &str, span: Span ) -> Vec<Inline>
 {
 	lazy_static! {
-		static ref	PAT_EM_ARRAY: Vec<EMMarkPat>= vec![
-				EMMarkPat{ pat: Regex::new( r"^(.*)\*\*([^*]+)\*\*(.*)$" ).unwrap(),		cmd1: "\x07B2", cmd2: "\x07b2", },
-				EMMarkPat{ pat: Regex::new( r"^(.*)//([^/]+)//(.*)$" ).unwrap(),			cmd1: "\x07B1", cmd2: "\x07b1", },
-				EMMarkPat{ pat: Regex::new( r"^(.*)<del>([^/]+)</del>(.*)$" ).unwrap(),		cmd1: "\x07D0", cmd2: "\x07d0", },
-				EMMarkPat{ pat: Regex::new( r"^(.*)''([^/]+)''(.*)$" ).unwrap(),			cmd1: "\x07C0", cmd2: "\x07c0", },
-			];
-		static ref	PAT_LINK2: Regex= Regex::new( r"^(.*)\[\[([^|]+)\|(.*)\]\](.*)$" ).unwrap();
-		static ref	PAT_LINK1: Regex= Regex::new( r"^(.*)\[\[(.+)\]\](.*)$" ).unwrap();
-	}
-	let	mut	buffer= line.to_string();
-	loop {
-		let	mut	bfound= false;
-		for emmark in PAT_EM_ARRAY.iter() {
-			if let Some(v)= emmark.pat.captures( &buffer ) {
-				let	mut buffer2= String::new();
-				buffer2+= &v[1];
-				buffer2+= emmark.cmd1;
-				buffer2+= &v[2];
-				buffer2+= emmark.cmd2;
-				buffer2+= &v[3];
-				buffer= buffer2;
-				bfound= true;
-				break;
-			}
-		}
-		if bfound {
-			continue;
-		}
-		if let Some(v)= PAT_LINK2.captures( &buffer ) {
-			let	mut buffer2= String::new();
-			buffer2+= &v[1];
-			buffer2+= "\x07L0";
-			buffer2+= &v[2];
-			buffer2+= "\x07L1";
-			buffer2+= &v[3];
-			buffer2+= "\x07L2";
-			buffer2+= &v[4];
-			buffer= buffer2;
-			continue;
-		}
-		if let Some(v)= PAT_LINK1.captures( &buffer ) {
-			let	mut buffer2= String::new();
-			buffer2+= &v[1];
-			buffer2+= "\x07L0";
-			buffer2+= &v[2];
-			buffer2+= "\x07L1";
-			buffer2+= "\x07L2";
-			buffer2+= &v[3];
-			buffer= buffer2;
-			continue;
-		}
-		break;
+		static ref	PARSER: InlineParser= InlineParser{
+				delimiters: vec![
+					Delimiter{ open: "**",		close: "**",		kind: InlineKind::Strong,	word: false, },
+					Delimiter{ open: "//",		close: "//",		kind: InlineKind::Emphasis,	word: false, },
+					Delimiter{ open: "<del>",	close: "</del>",	kind: InlineKind::Strike,	word: false, },
+					Delimiter{ open: "''",		close: "''",		kind: InlineKind::Code,		word: false, },
+				],
+				tight: false,
+				special: doku_special,
+			};
 	}
-
-	return	buffer;
+	return	PARSER.parse_at( line, span );
 }
 
-pub fn	decode_from_doku( line0: &str ) -> String
+fn	decode_doku_line( line: &str, span: Span ) -> Vec<Inline>
 {
-	return	replace_doku_tags( line0 );
+	let	trimmed= line.trim();
+	return	decode_from_doku( trimmed, span.at( line, trimmed ) );
 }
 
 
-pub	fn	encode_to_doku( line: &str ) -> String
+fn	escape_doku( line: &str ) -> String
 {
 	let	mut	buffer= String::new();
 	let	mut	char_it= line.chars();
@@ -122,11 +119,7 @@ pub	fn	encode_to_doku( line: &str ) -> String
 			}
 			slash_count= 0;
 		}
-		if Some(':') == ch {
-			colon= true;
-		}else{
-			colon= false;
-		}
+		colon= Some(':') == ch;
 		if back_slash {
 			if Some('_') != ch {
 				buffer+= "\\";
@@ -134,57 +127,11 @@ pub	fn	encode_to_doku( line: &str ) -> String
 			back_slash= false;
 		}
 		match ch {
-			Some('\x07') => {
-				let	cmd0= char_it.next().unwrap();
-				let	cmd1= char_it.next().unwrap();
-				match cmd0 {
-					'B'|'b' => {
-						match cmd1 {
-							'1' => {
-								buffer+= "//";
-							},
-							'2' => {
-								buffer+= "**";
-							},
-							_ => {
-								buffer+= "//**";
-							},
-						}
-					},
-					'D' => {
-						buffer+= "<del>";
-					},
-					'd' => {
-						buffer+= "</del>";
-					},
-					'C' => {
-						buffer+= "''";
-					},
-					'c' => {
-						buffer+= "''";
-					},
-					'L' => {
-						match cmd1 {
-							'0' => {
-								buffer+= "[[";
-							},
-							'1' => {
-								buffer+= "|";
-							},
-							_ => {
-								buffer+= "]]";
-							},
-						}
-					},
-					_ => {
-					},
-				}
-			},
 			Some('\\') => {
 				back_slash= true;
 			},
 			Some(c) => {
-				buffer+= &format!( "{}", c );
+				buffer.push( c );
 			},
 			None => {
 				return	buffer;
@@ -193,6 +140,56 @@ pub	fn	encode_to_doku( line: &str ) -> String
 	}
 }
 
+pub	fn	encode_to_doku( list: &[Inline] ) -> String
+{
+	let	mut	buffer= String::new();
+	for node in list {
+		match &node.node {
+			InlineNode::Text(text) => {
+				buffer+= &escape_doku( text );
+			},
+			InlineNode::Strong(child) => {
+				buffer+= &format!( "**{}**", encode_to_doku( child ) );
+			},
+			InlineNode::Emphasis(child) => {
+				buffer+= &format!( "//{}//", encode_to_doku( child ) );
+			},
+			InlineNode::Strike(child) => {
+				buffer+= &format!( "<del>{}</del>", encode_to_doku( child ) );
+			},
+			InlineNode::Code(text) => {
+				buffer+= &format!( "''%%{}%%''", text );
+			},
+			InlineNode::Link{ url, text } => {
+				if text.is_empty() {
+					buffer+= &format!( "[[{}]]", url );
+				}else{
+					buffer+= &format!( "[[{}|{}]]", url, encode_to_doku( text ) );
+				}
+			},
+			InlineNode::Image(image) => {
+				let	mut	target= image.src.clone();
+				if image.width.is_some() || image.height.is_some() {
+					target+= &format!( "?{}", image.size_text() );
+				}
+				let	alt= if image.alt.is_empty() { &image.title } else { &image.alt };
+				if alt.is_empty() {
+					buffer+= &format!( "{{{{{}}}}}", target );
+				}else{
+					buffer+= &format!( "{{{{{}|{}}}}}", target, alt );
+				}
+			},
+			InlineNode::SoftBreak => {
+				buffer+= "\n";
+			},
+			InlineNode::LineBreak => {
+				buffer+= "\\\\\n";
+			},
+		}
+	}
+	return	buffer;
+}
+
 
 //=============================================================================
 
@@ -200,18 +197,31 @@ pub	fn	encode_to_doku( line: &str ) -> String
 //-----------------------------------------------------------------------------
 
 trait ElementGenerator {
-	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Box<dyn Element>>;
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>;
 }
 
 
 struct GenerateorContext {
+	// current line
+	span: Span,
+	// paragraph
+	para: ParagraphElement,
+	// list
+	list_block: bool,
+	list_entries: Vec<ListEntry>,
+	list_pat: regex::Regex,
+	// quote
+	quote_block: bool,
+	quote_lines: Vec<SourceLine>,
+	quote_span: Span,
 	// pre
 	pre_block: bool,
+	pre_span: Span,
 	pre_code: String,
 	pre_data: String,
 	// table
 	table_block: bool,
-	table_column: Vec<TableColumn>,
+	table_grid: Vec<(Span, Vec<TableGrid<TableAttr>>)>,
 	table_pat: regex::Regex,
 }
 
@@ -219,27 +229,66 @@ impl GenerateorContext {
 	fn	new() -> Self
 	{
 		GenerateorContext{
+				span: Span::default(),
+				para: ParagraphElement::new(),
+				list_block: false,
+				list_entries: Vec::new(),
+				list_pat: regex::Regex::new( r"^  (\s*)(\*|\-)\s+(.*)$" ).unwrap(),
+				quote_block: false,
+				quote_lines: Vec::new(),
+				quote_span: Span::default(),
 				pre_block: false,
+				pre_span: Span::default(),
 				pre_code: String::new(),
 				pre_data: String::new(),
 				table_block: false,
-				table_column: Vec::new(),
+				table_grid: Vec::new(),
 				table_pat: regex::Regex::new( r"^(\||\^)(.*)(\||\^)\s*$" ).unwrap(),
 			}
 	}
 	//-------------------------------------------------------------------------
+	fn	is_list_block( &self ) -> bool
+	{
+		self.list_block
+	}
+	fn	add_list_block( &mut self, line: &str ) -> Option<Vec<Block>>
+	{
+		let	pat_result= self.list_pat.captures( line );
+		if let Some(v)= pat_result {
+			self.list_entries.push( list_entry( &v, line, self.span ) );
+			return	None;
+		}
+		self.list_block= false;
+		return	Some( build_list_tree( &mut self.list_entries ) );
+	}
+	//-------------------------------------------------------------------------
+	fn	is_quote_block( &self ) -> bool
+	{
+		self.quote_block
+	}
+	// Returns false when the line is out of the quote.
+	fn	add_quote_block( &mut self, line: &str ) -> bool
+	{
+		if let Some(text)= strip_quote_mark( line ) {
+			self.quote_lines.push( SourceLine::new( text, self.span.at( line, text ) ) );
+			return	true;
+		}
+		self.quote_block= false;
+		return	false;
+	}
+	//-------------------------------------------------------------------------
 	fn	is_pre_block( &self ) -> bool
 	{
 		self.pre_block
 	}
-	fn	add_pre_block( &mut self, line: &str ) -> Option<Box<dyn Element>>
+	fn	add_pre_block( &mut self, line: &str ) -> Option<Block>
 	{
 		if line.starts_with( "</code>" ) || line.starts_with( "</file>" ) {
 			self.pre_block= false;
-			return	Some( Box::new( PRETagElement{
-							etype:	ElementType::PRETAG,
+			return	Some( Block::PRETag( PRETagElement{
 							text: 	(&self.pre_data).to_string(),
 							code:	(&self.pre_code).to_string(),
+							span:	self.pre_span,
 						}));
 		}
 		self.pre_data+= &line;
@@ -251,16 +300,31 @@ impl GenerateorContext {
 	{
 		self.table_block
 	}
-	fn	add_table_block( &mut self, line: &str ) -> Option<Box<dyn Element>>
+	fn	add_table_block( &mut self, line: &str ) -> Option<Block>
 	{
 		let	pat_result= self.table_pat.captures( line );
 		if let Some(v)= pat_result {
 			//println!( "TABLE={}", line );
-			let	header= &v[1] == "^";
-			let	tline= &v[2];
-			let	params= tline.split( &['|', '^'] );
-			let	mut	column= TableColumn::new();
-			for (_,td) in params.enumerate() {
+			// The separator in front of the cell tells the header cell.
+			let	mut	params= Vec::new();
+			let	mut	header= &v[1] == "^";
+			let	mut	tline= &v[2];
+			while let Some(pos)= tline.find( ['|', '^'] ) {
+				params.push( (&tline[..pos], header) );
+				header= tline[pos..].starts_with( '^' );
+				tline= &tline[pos+1..];
+			}
+			params.push( (tline, header) );
+			let	mut	cells= Vec::new();
+			for (td,header) in params {
+				if td.is_empty() {
+					cells.push( TableGrid::SpanLeft );
+					continue;
+				}
+				if td.trim() == ":::" {
+					cells.push( TableGrid::SpanUp );
+					continue;
+				}
 				let	mut	align= ETableAlign::DEFAULT;
 				let	mut	ls= 0;
 				let	mut	rs= 0;
@@ -284,19 +348,18 @@ impl GenerateorContext {
 					align= ETableAlign::RIGHT;
 				}
 				//println!( "[{}] {:?}", td, align );
-				column.add( TableAttr{
-						text: decode_from_doku( td.trim() ),
+				cells.push( TableGrid::Cell( TableAttr{
+						text: decode_from_doku( td.trim(), self.span.at( line, td.trim() ) ),
 						align: align,
-						header: header,
-					} );
+						header: Some(header),
+						colspan: 1,
+						rowspan: 1,
+					} ) );
 			}
-			self.table_column.push( column )
+			self.table_grid.push( (self.span, cells) );
 		}else{
 			self.table_block= false;
-			return	Some( Box::new( TABLEElement{
-							etype:	ElementType::TABLE,
-							data:	self.table_column.to_vec(),
-						}));
+			return	Some( Block::Table( build_table( std::mem::take( &mut self.table_grid ) ) ) );
 		}
 		return	None;
 	}
@@ -319,17 +382,17 @@ impl HTagGen {
 }
 
 impl ElementGenerator for HTagGen {
-	fn	generate( &self, line: &str, _context: &mut GenerateorContext ) -> Option<Box<dyn Element>>
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
 	{
 		let	pat_result= self.pat.captures( line );
 		if let Some(v)= pat_result {
 			//println!( "{} {} {}", &v[1], &v[2], v[1].len() );
 			let	hlen= v[1].len() as u32;
 			let	level= if hlen < 7 { 7 - hlen }else{ 1 };
-			return	Some( Box::new( HTagElement{
-							etype:	ElementType::HTAG,
-							title: 	v[2].to_string(),
+			return	Some( Block::HTag( HTagElement{
+							title: 	decode_from_doku( &v[2], context.span.at( line, &v[2] ) ),
 							level:	level,
+							span:	context.span,
 						}));
 		}
 		return	None;
@@ -339,6 +402,17 @@ impl ElementGenerator for HTagGen {
 
 //-----------------------------------------------------------------------------
 
+fn	list_entry( v: &regex::Captures, line: &str, span: Span ) -> ListEntry
+{
+	//println!( "ul {} {} {}", &v[2], &v[1], v[1].len() );
+	ListEntry{
+		depth:		v[1].len() as u32 / 2,
+		ordered:	&v[2] != "*",
+		text:		decode_from_doku( &v[3], span.at( line, &v[3] ) ),
+		span:		span.at( line, &v[2] ),
+	}
+}
+
 struct LITagGen {
 	pat: regex::Regex,
 }
@@ -353,20 +427,41 @@ impl LITagGen {
 }
 
 impl ElementGenerator for LITagGen {
-	fn	generate( &self, line: &str, _context: &mut GenerateorContext ) -> Option<Box<dyn Element>>
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
 	{
 		let	pat_result= self.pat.captures( line );
 		if let Some(v)= pat_result {
-			//println!( "ul {} {} {}", &v[2], &v[1], v[1].len() );
-			let	indent= v[1].len() as u32;
-			let nest= indent / 2;
-			let	tag= if &v[2] == "*" { ElementType::ULTAG } else { ElementType::OLTAG };
-			return	Some( Box::new( LITagElement{
-							etype:	tag,
-							text: 	decode_from_doku( &v[3] ),
-							indent:	indent,
-							nest:	nest,
-						}));
+			context.list_block= true;
+			context.list_entries= vec![list_entry( &v, line, context.span )];
+			return	Some( Block::None );
+		}
+		return	None;
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+// '>' '>>' '>>>' nest the quote.
+struct QuoteGen {
+}
+
+impl QuoteGen {
+	fn	new() -> Self
+	{
+		QuoteGen{
+		}
+	}
+}
+
+impl ElementGenerator for QuoteGen {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		if let Some(text)= strip_quote_mark( line ) {
+			context.quote_block= true;
+			context.quote_lines= vec![SourceLine::new( text, context.span.at( line, text ) )];
+			context.quote_span= context.span;
+			return	Some( Block::None );
 		}
 		return	None;
 	}
@@ -391,21 +486,23 @@ impl PRETagGen {
 }
 
 impl ElementGenerator for PRETagGen {
-	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Box<dyn Element>>
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
 	{
 		let	pat_result= self.pat_format.captures( line );
 		if let Some(v)= pat_result {
 			context.pre_block= true;
+			context.pre_span= context.span;
 			context.pre_code= v[2].to_string();
 			context.pre_data= String::new();
-			return	Some( Box::new( NONEElement{} ));
+			return	Some( Block::None );
 		}
 		let	pat_result= self.pat.captures( line );
 		if let Some(_)= pat_result {
 			context.pre_block= true;
+			context.pre_span= context.span;
 			context.pre_code= String::new();
 			context.pre_data= String::new();
-			return	Some( Box::new( NONEElement{} ));
+			return	Some( Block::None );
 		}
 		return	None;
 	}
@@ -428,16 +525,16 @@ impl TABLEGen {
 }
 
 impl ElementGenerator for TABLEGen {
-	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Box<dyn Element>>
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
 	{
 		let	pat_result= self.pat.captures( line );
 		if let Some(_)= pat_result {
 			context.table_block= true;
-			context.table_column= Vec::new();
+			context.table_grid= Vec::new();
 			if let Some(e)= context.add_table_block( line ) {
 				return	Some(e);
 			}
-			return	Some( Box::new( NONEElement{} ));
+			return	Some( Block::None );
 		}
 		return	None;
 	}
@@ -460,11 +557,11 @@ impl HRTagGen {
 }
 
 impl ElementGenerator for HRTagGen {
-	fn	generate( &self, line: &str, _context: &mut GenerateorContext ) -> Option<Box<dyn Element>>
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
 	{
 		let	pat_result= self.pat.captures( line );
 		if let Some(_)= pat_result {
-			return	Some( Box::new( HRTagElement{} ) );
+			return	Some( Block::HRTag( context.span ) );
 		}
 		return	None;
 	}
@@ -484,12 +581,13 @@ impl Decoder {
 			Box::new( HRTagGen::new() ),
 			Box::new( HTagGen::new() ),
 			Box::new( LITagGen::new() ),
+			Box::new( QuoteGen::new() ),
 			Box::new( PRETagGen::new() ),
 			Box::new( TABLEGen::new() ),
 		];
 		Decoder{ gen_table: gen_table }
 	}
-	fn	find( &self, line: &str, context: &mut GenerateorContext ) -> Option<Box<dyn Element>>
+	fn	find( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
 	{
 		for gen in &self.gen_table {
 			let	element= gen.generate( line, context );
@@ -499,43 +597,76 @@ impl Decoder {
 		}
 		return	None;
 	}
-	pub fn	load( &self, file_name: &str ) -> io::Result<Document>
+	fn	build_quote( &self, lines: &[SourceLine], span: Span ) -> Block
 	{
-		let	file= fs::File::open( file_name )?;
-		let	reader= io::BufReader::new( file );
+		Block::Quote( BlockQuoteElement{ blocks: self.decode_lines( lines ).element_list, span: span } )
+	}
+}
 
+impl TextDecoder for Decoder {
+	fn	decode_lines( &self, lines: &[SourceLine] ) -> Document
+	{
 		let	mut	page= Document::new();
 		let	mut	context= GenerateorContext::new();
 
-		for rline in reader.lines() {
-			match rline {
-				Ok(line) => {
-					if context.is_pre_block() {
-						if let Some(e)= context.add_pre_block( &line ) {
-							page.push( e );
-						}
-						continue;
-					}else if context.is_table_block() {
-						if let Some(e)= context.add_table_block( &line ) {
-							page.push( e );
-						}else{
-							continue;
-						}
+		for source in lines {
+			let	line= &source.text;
+			context.span= source.span;
+			if context.is_pre_block() {
+				if let Some(e)= context.add_pre_block( line ) {
+					page.push( e );
+				}
+				continue;
+			}else if context.is_table_block() {
+				if let Some(e)= context.add_table_block( line ) {
+					page.push( e );
+				}else{
+					continue;
+				}
+			}else if context.is_list_block() {
+				if let Some(list)= context.add_list_block( line ) {
+					for e in list {
+						page.push( e );
+					}
+				}else{
+					continue;
+				}
+			}else if context.is_quote_block() {
+				if context.add_quote_block( line ) {
+					continue;
+				}
+				page.push( self.build_quote( &std::mem::take( &mut context.quote_lines ), context.quote_span ) );
+			}
+			{
+				if let Some(e)= self.find( line, &mut context ) {
+					if let Some(p)= context.para.finish() {
+						page.push( p );
 					}
-					{
-						if let Some(e)= self.find( &line, &mut context ) {
-							page.push( e );
-						}else{
-							page.push( Box::new(DataElement{ text: decode_from_doku( &line ) }) );
-						}
+					page.push( e );
+				}else if line.trim().is_empty() {
+					if let Some(p)= context.para.finish() {
+						page.push( p );
 					}
-				},
-				Err(e) => {
-					return	Err(e);
-				},
+				}else{
+					context.para.add_line( decode_doku_line( line, source.span ) );
+				}
+			}
+		}
+		if context.is_list_block() {
+			for e in build_list_tree( &mut context.list_entries ) {
+				page.push( e );
 			}
+		}else if context.is_quote_block() {
+			page.push( self.build_quote( &context.quote_lines, context.quote_span ) );
+		}else if context.is_table_block() {
+			page.push( Block::Table( build_table( std::mem::take( &mut context.table_grid ) ) ) );
+		}else if context.is_pre_block() {
+			page.push( Block::PRETag( PRETagElement{ text: context.pre_data, code: context.pre_code, span: context.pre_span } ) );
+		}
+		if let Some(p)= context.para.finish() {
+			page.push( p );
 		}
-		Ok(page)
+		page
 	}
 }
 
@@ -543,45 +674,80 @@ impl Decoder {
 //=============================================================================
 
 trait	EncodeElement {
-	fn	output( &self ) -> String;
+	fn	output( &self, encoder: &Encoder ) -> String;
 }
 
 
 //-----------------------------------------------------------------------------
 
-impl	EncodeElement for NONEElement {
-	fn	output( &self ) -> String
+impl	EncodeElement for HTagElement {
+	fn	output( &self, _encoder: &Encoder ) -> String
 	{
-		return	String::new();
+		let	count= 7 - self.level.clamp( 1, 6 );
+		let	tag= '='.to_string().repeat( count as usize );
+		return	format!( "{} {} {}\n", tag, encode_to_doku( &self.title ), tag );
+		//return	format!( "{} {} {}\n", tag, self.title, tag );
 	}
 }
 
 
 //-----------------------------------------------------------------------------
 
-impl	EncodeElement for HTagElement {
-	fn	output( &self ) -> String
+// DokuWiki writes its own toc from the headings.
+impl	EncodeElement for TOCElement {
+	fn	output( &self, _encoder: &Encoder ) -> String
 	{
-		let	count= 7 - self.level;
-		let	tag= '='.to_string().repeat( count as usize );
-		return	format!( "{} {} {}\n", tag, encode_to_doku( &self.title ), tag );
-		//return	format!( "{} {} {}\n", tag, self.title, tag );
+		return	String::new();
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+// A list item is a single line, the other blocks follow the item.
+fn	output_list( list: &ListElement, encoder: &Encoder, nest: usize ) -> String
+{
+	let	mut	buffer= String::new();
+	let	spaces= ' '.to_string().repeat( (nest+1) * 2 );
+	let	mark= if list.ordered { "-" } else { "*" };
+	for item in &list.items {
+		let	mut	text= String::new();
+		let	mut	children= String::new();
+		for block in &item.blocks {
+			match block {
+				Block::Paragraph(e) => {
+					if !text.is_empty() {
+						text+= " \\\\ ";
+					}
+					text+= &render_paragraph( &e.text, ParagraphMode::Join, encode_to_doku ).replace( '\n', " " );
+				},
+				Block::List(e) => {
+					children+= &output_list( e, encoder, nest+1 );
+				},
+				_ => {
+					children+= &encoder.encode_single( block );
+				},
+			}
+		}
+		buffer+= &format!( "{}{} {}\n", spaces, mark, text );
+		buffer+= &children;
+	}
+	return	buffer;
+}
+
+impl	EncodeElement for ListElement {
+	fn	output( &self, encoder: &Encoder ) -> String
+	{
+		output_list( self, encoder, 0 )
 	}
 }
 
 
 //-----------------------------------------------------------------------------
 
-impl	EncodeElement for LITagElement {
-	fn	output( &self ) -> String
+impl	EncodeElement for BlockQuoteElement {
+	fn	output( &self, encoder: &Encoder ) -> String
 	{
-		let	indent= (self.nest+1) * 2;
-		let	spaces= ' '.to_string().repeat( indent as usize );
-		if self.etype == ElementType::ULTAG {
-			return	format!( "{}* {}\n", spaces, encode_to_doku( &self.text ) );
-		}else{
-			return	format!( "{}- {}\n", spaces, encode_to_doku( &self.text ) );
-		}
+		return	prefix_quote_lines( &encoder.encode_blocks( &self.blocks ), ">" );
 	}
 }
 
@@ -589,7 +755,7 @@ impl	EncodeElement for LITagElement {
 //-----------------------------------------------------------------------------
 
 impl	EncodeElement for PRETagElement {
-	fn	output( &self ) -> String
+	fn	output( &self, _encoder: &Encoder ) -> String
 	{
 		if self.code.is_empty() {
 			return	format!( "<code>\n{}</code>\n", self.text );
@@ -606,23 +772,28 @@ impl	EncodeElement for PRETagElement {
 //-----------------------------------------------------------------------------
 
 impl	EncodeElement for TABLEElement {
-	fn	output( &self ) -> String
+	fn	output( &self, _encoder: &Encoder ) -> String
 	{
 		let	mut	buffer= String::new();
-		for column in &self.data {
-			let	mut	bfirst= true;
-			for attr in &column.data {
-				if bfirst {
-					bfirst= false;
-					if attr.header {
-						buffer+= "^";
-					}else{
-						buffer+= "|";
-					}
-				}
+		for (row,line) in self.grid().iter().enumerate() {
+			// The separator in front of the cell tells the header cell.
+			let	mut	sep= "|";
+			for (col,cell) in line.iter().enumerate() {
+				let	attr= match cell {
+					TableGrid::Cell(attr) => attr,
+					TableGrid::SpanUp => {
+						sep= "|";
+						buffer+= "| ::: ";
+						continue;
+					},
+					_ => {
+						buffer+= sep;
+						continue;
+					},
+				};
 				let	mut	ls= " ";
 				let	mut	rs= " ";
-				match attr.align {
+				match self.cell_align( row, col, attr ) {
 					ETableAlign::CENTER => {
 						ls= "  ";
 						rs= "  ";
@@ -638,12 +809,10 @@ impl	EncodeElement for TABLEElement {
 					_ => {
 					},
 				}
-				if attr.header {
-					buffer+= &format!( "{}{}{}^", ls, encode_to_doku( &attr.text ), rs );
-				}else{
-					buffer+= &format!( "{}{}{}|", ls, encode_to_doku( &attr.text ), rs );
-				}
+				sep= if self.cell_header( row, col, attr ) { "^" } else { "|" };
+				buffer+= &format!( "{}{}{}{}", sep, ls, encode_to_doku( &attr.text ), rs );
 			}
+			buffer+= sep;
 			buffer+= "\n";
 		}
 		return	buffer;
@@ -653,20 +822,10 @@ impl	EncodeElement for TABLEElement {
 
 //-----------------------------------------------------------------------------
 
-impl	EncodeElement for HRTagElement {
-	fn	output( &self ) -> String
-	{
-		return	"----\n".to_string();
-	}
-}
-
-
-//-----------------------------------------------------------------------------
-
-impl	EncodeElement for DataElement {
-	fn	output( &self ) -> String
+impl	EncodeElement for ParagraphElement {
+	fn	output( &self, encoder: &Encoder ) -> String
 	{
-		encode_to_doku( &self.text ) + "\n"
+		render_paragraph( &self.text, encoder.paragraph, encode_to_doku ) + "\n"
 	}
 }
 
@@ -674,41 +833,46 @@ impl	EncodeElement for DataElement {
 //-----------------------------------------------------------------------------
 
 pub struct	Encoder {
+	pub paragraph	: ParagraphMode,
 }
 
 impl	Encoder {
 	pub	fn	new() -> Self
 	{
-		Self{}
+		Self{
+			paragraph: ParagraphMode::Join,
+		}
 	}
 }
 
 impl	TextEncoder for Encoder {
-	fn	encode_single( &self, element: &Box<dyn Element> ) -> String
+	fn	encode_single( &self, element: &Block ) -> String
 	{
-		let	any= element.as_any();
-		if let Some(e)= any.downcast_ref::<NONEElement>() {
-			return	e.output();
+		match element {
+			Block::None => String::new(),
+			Block::Paragraph(e) => e.output( self ),
+			Block::HTag(e) => e.output( self ),
+			Block::List(e) => e.output( self ),
+			Block::PRETag(e) => e.output( self ),
+			Block::Table(e) => e.output( self ),
+			Block::Quote(e) => e.output( self ),
+			Block::HRTag(_) => "----\n".to_string(),
+			Block::TOC(e) => e.output( self ),
 		}
-		if let Some(e)= any.downcast_ref::<HTagElement>() {
-			return	e.output();
-		}
-		if let Some(e)= any.downcast_ref::<LITagElement>() {
-			return	e.output();
-		}
-		if let Some(e)= any.downcast_ref::<PRETagElement>() {
-			return	e.output();
-		}
-		if let Some(e)= any.downcast_ref::<TABLEElement>() {
-			return	e.output();
-		}
-		if let Some(e)= any.downcast_ref::<HRTagElement>() {
-			return	e.output();
+	}
+	fn	check_block( &self, element: &Block, warnings: &mut Vec<Warning> )
+	{
+		if let Block::List(e)= element {
+			check_list_items( e, warnings );
 		}
-		if let Some(e)= any.downcast_ref::<DataElement>() {
-			return	e.output();
+	}
+	fn	check_inline( &self, node: &Inline, warnings: &mut Vec<Warning> )
+	{
+		if let InlineNode::Image(image)= &node.node {
+			if !image.alt.is_empty() && !image.title.is_empty() {
+				warnings.push( Warning::new( LossKind::Dropped, "image title", "only the alt text is written", node.span ) );
+			}
 		}
-		return	String::new();
 	}
 }
 
@@ -0,0 +1,1070 @@
+// 2022 Hiroyuki Ogasawara
+// vim:ts=4 sw=4 noet:
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use super::span::*;
+use super::inline::*;
+use super::element::*;
+use super::document::*;
+use super::error::Error;
+
+
+//=============================================================================
+
+// "<", ">", "&" and the quotes of the attribute.
+pub fn	escape_html( text: &str ) -> String
+{
+	let	mut	buffer= String::new();
+	for ch in text.chars() {
+		match ch {
+			'&' => buffer+= "&amp;",
+			'<' => buffer+= "&lt;",
+			'>' => buffer+= "&gt;",
+			'"' => buffer+= "&quot;",
+			_ => buffer.push( ch ),
+		}
+	}
+	return	buffer;
+}
+
+pub	fn	encode_to_html( list: &[Inline] ) -> String
+{
+	let	mut	buffer= String::new();
+	for node in list {
+		match &node.node {
+			InlineNode::Text(text) => {
+				buffer+= &escape_html( text );
+			},
+			InlineNode::Strong(child) => {
+				buffer+= &format!( "<strong>{}</strong>", encode_to_html( child ) );
+			},
+			InlineNode::Emphasis(child) => {
+				buffer+= &format!( "<em>{}</em>", encode_to_html( child ) );
+			},
+			InlineNode::Strike(child) => {
+				buffer+= &format!( "<del>{}</del>", encode_to_html( child ) );
+			},
+			InlineNode::Code(text) => {
+				buffer+= &format!( "<code>{}</code>", escape_html( text ) );
+			},
+			InlineNode::Link{ url, text } => {
+				let	label= if text.is_empty() { escape_html( url ) } else { encode_to_html( text ) };
+				buffer+= &format!( "<a href=\"{}\">{}</a>", escape_html( url ), label );
+			},
+			InlineNode::Image(image) => {
+				buffer+= &format!( "<img src=\"{}\" alt=\"{}\"", escape_html( &image.src ), escape_html( &image.alt ) );
+				if !image.title.is_empty() {
+					buffer+= &format!( " title=\"{}\"", escape_html( &image.title ) );
+				}
+				if let Some(w)= image.width {
+					buffer+= &format!( " width=\"{}\"", w );
+				}
+				if let Some(h)= image.height {
+					buffer+= &format!( " height=\"{}\"", h );
+				}
+				buffer+= ">";
+			},
+			InlineNode::SoftBreak => {
+				buffer+= "\n";
+			},
+			InlineNode::LineBreak => {
+				buffer+= "<br>\n";
+			},
+		}
+	}
+	return	buffer;
+}
+
+
+//=============================================================================
+
+// The tree of the page, the entities of the text and the attributes are decoded.
+enum Node {
+	Element(HtmlElement),
+	Text(String,Span),
+}
+
+struct HtmlElement {
+	name		: String,				// lowercase
+	attrs		: Vec<(String,String)>,
+	children	: Vec<Node>,
+	span		: Span,
+}
+
+impl HtmlElement {
+	fn	new( name: &str, span: Span ) -> Self
+	{
+		HtmlElement{ name: name.to_string(), attrs: Vec::new(), children: Vec::new(), span: span }
+	}
+	fn	attr( &self, name: &str ) -> Option<&str>
+	{
+		self.attrs.iter().find( |(key,_)| key == name ).map( |(_,value)| value.as_str() )
+	}
+	fn	classes( &self ) -> impl Iterator<Item=&str>
+	{
+		self.attr( "class" ).unwrap_or( "" ).split_whitespace()
+	}
+	fn	has_class( &self, class: &str ) -> bool
+	{
+		self.classes().any( |name| name == class )
+	}
+}
+
+const	VOID_TAGS: &[&str]= &["area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source", "track", "wbr"];
+const	RAW_TAGS: &[&str]= &["script", "style", "textarea", "title"];
+// The blocks which end the open <p>.
+const	BLOCK_TAGS: &[&str]= &["address", "article", "aside", "blockquote", "div", "dl", "fieldset", "figure", "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6", "header", "hr", "main", "nav", "ol", "p", "pre", "section", "table", "ul"];
+// Not the content of the page.
+const	SKIP_TAGS: &[&str]= &["head", "script", "style", "title", "template", "noscript", "form", "button", "input", "select", "textarea"];
+
+// "&amp;", "&#39;" and "&#x27;", the unknown names are left as they are.
+fn	decode_entities( text: &str ) -> String
+{
+	let	mut	buffer= String::new();
+	let	mut	rest= text;
+	while let Some(pos)= rest.find( '&' ) {
+		buffer+= &rest[..pos];
+		rest= &rest[pos..];
+		let	end= rest.find( ';' ).filter( |end| *end <= 10 );
+		let	ch= end.and_then( |end| {
+				let	name= &rest[1..end];
+				if let Some(num)= name.strip_prefix( "#x" ).or_else( || name.strip_prefix( "#X" ) ) {
+					return	u32::from_str_radix( num, 16 ).ok().and_then( char::from_u32 );
+				}
+				if let Some(num)= name.strip_prefix( '#' ) {
+					return	num.parse().ok().and_then( char::from_u32 );
+				}
+				match name {
+					"amp" => Some('&'),
+					"lt" => Some('<'),
+					"gt" => Some('>'),
+					"quot" => Some('"'),
+					"apos" => Some('\''),
+					"nbsp" => Some(' '),
+					"copy" => Some('©'),
+					"reg" => Some('®'),
+					"trade" => Some('™'),
+					"times" => Some('×'),
+					"hellip" => Some('…'),
+					"ndash" => Some('–'),
+					"mdash" => Some('—'),
+					"lsquo" => Some('‘'),
+					"rsquo" => Some('’'),
+					"ldquo" => Some('“'),
+					"rdquo" => Some('”'),
+					"laquo" => Some('«'),
+					"raquo" => Some('»'),
+					_ => None,
+				}
+			});
+		match (ch,end) {
+			(Some(ch),Some(end)) => {
+				buffer.push( ch );
+				rest= &rest[end+1..];
+			},
+			_ => {
+				buffer.push( '&' );
+				rest= &rest[1..];
+			},
+		}
+	}
+	buffer+= rest;
+	return	buffer;
+}
+
+// Builds the tree from the tags, the end tags left open are closed by the parent.
+struct TreeBuilder<'a> {
+	text	: &'a str,
+	lines	: Vec<(usize,Span)>,	// offset and position of each line
+	stack	: Vec<HtmlElement>,
+}
+
+impl<'a> TreeBuilder<'a> {
+	fn	span( &self, offset: usize ) -> Span
+	{
+		let	index= self.lines.partition_point( |(start,_)| *start <= offset ).saturating_sub( 1 );
+		let	Some((start,span))= self.lines.get( index ) else {
+			return	Span::default();
+		};
+		return	span.advance( &self.text[*start..offset] );
+	}
+	fn	pop( &mut self )
+	{
+		if self.stack.len() > 1 {
+			let	e= self.stack.pop().unwrap();
+			self.stack.last_mut().unwrap().children.push( Node::Element( e ) );
+		}
+	}
+	fn	top( &self ) -> &str
+	{
+		self.stack.last().map_or( "", |e| e.name.as_str() )
+	}
+	// <li> ends the open <li>, <td> the open cell, a block the open <p>.
+	fn	open( &mut self, e: HtmlElement )
+	{
+		loop {
+			let	top= self.top();
+			let	close= match e.name.as_str() {
+					"li" => top == "li" || top == "p",
+					"dt" | "dd" => top == "dt" || top == "dd" || top == "p",
+					"td" | "th" => top == "td" || top == "th",
+					"tr" => top == "td" || top == "th" || top == "tr",
+					"thead" | "tbody" | "tfoot" => ["td", "th", "tr", "thead", "tbody"].contains( &top ),
+					name => top == "p" && BLOCK_TAGS.contains( &name ),
+				};
+			if !close {
+				break;
+			}
+			self.pop();
+		}
+		if VOID_TAGS.contains( &e.name.as_str() ) {
+			self.stack.last_mut().unwrap().children.push( Node::Element( e ) );
+		}else{
+			self.stack.push( e );
+		}
+	}
+	fn	close( &mut self, name: &str )
+	{
+		if let Some(index)= self.stack.iter().rposition( |e| e.name == name ) {
+			while self.stack.len() > index.max( 1 ) {
+				self.pop();
+			}
+		}
+	}
+	fn	add_text( &mut self, text: &str, offset: usize )
+	{
+		if !text.is_empty() {
+			let	span= self.span( offset );
+			self.stack.last_mut().unwrap().children.push( Node::Text( decode_entities( text ), span ) );
+		}
+	}
+	// Reads the attributes after the name, returns the length to the end of the tag.
+	fn	read_attrs( tag: &str, e: &mut HtmlElement ) -> usize
+	{
+		let	mut	pos= 0;
+		while let Some(ch)= tag[pos..].chars().next() {
+			if ch == '>' {
+				return	pos + 1;
+			}
+			// the name stops at these, a stray '=' is skipped for the name to have one char at least
+			if ch.is_whitespace() || ch == '/' || ch == '=' {
+				pos+= ch.len_utf8();
+				continue;
+			}
+			let	name_len= tag[pos..].find( |c: char| c.is_whitespace() || c == '=' || c == '>' || c == '/' ).unwrap_or( tag.len() - pos );
+			let	name= tag[pos..pos+name_len].to_ascii_lowercase();
+			pos+= name_len;
+			let	mut	value= String::new();
+			let	rest= &tag[pos..];
+			let	trimmed= rest.trim_start();
+			if let Some(after)= trimmed.strip_prefix( '=' ) {
+				let	after= after.trim_start();
+				pos= tag.len() - after.len();
+				let	len= match after.chars().next() {
+						Some(quote @ ('"' | '\'')) => {
+							let	close= after[1..].find( quote ).map_or( after.len(), |end| end + 1 );
+							value= after[1..close].to_string();
+							(close + 1).min( after.len() )
+						},
+						_ => {
+							let	end= after.find( |c: char| c.is_whitespace() || c == '>' ).unwrap_or( after.len() );
+							value= after[..end].to_string();
+							end
+						},
+					};
+				pos+= len;
+			}
+			e.attrs.push( (name, decode_entities( &value )) );
+		}
+		return	pos;
+	}
+	fn	build( mut self ) -> Vec<Node>
+	{
+		let	text= self.text;
+		let	mut	pos= 0;
+		while pos < text.len() {
+			let	rest= &text[pos..];
+			let	Some(lt)= rest.find( '<' ) else {
+				self.add_text( rest, pos );
+				break;
+			};
+			self.add_text( &rest[..lt], pos );
+			pos+= lt;
+			let	rest= &text[pos..];
+			if rest.starts_with( "<!--" ) {
+				pos+= rest.find( "-->" ).map_or( rest.len(), |end| end + 3 );
+			}else if let Some(body)= rest.strip_prefix( "<![CDATA[" ) {
+				let	end= body.find( "]]>" ).unwrap_or( body.len() );
+				let	span= self.span( pos );
+				self.stack.last_mut().unwrap().children.push( Node::Text( body[..end].to_string(), span ) );
+				pos+= 9 + (end + 3).min( body.len() );
+			}else if rest.starts_with( "<!" ) || rest.starts_with( "<?" ) {
+				pos+= rest.find( '>' ).map_or( rest.len(), |end| end + 1 );
+			}else if let Some(body)= rest.strip_prefix( "</" ) {
+				let	name_len= body.find( |c: char| !c.is_ascii_alphanumeric() ).unwrap_or( body.len() );
+				self.close( &body[..name_len].to_ascii_lowercase() );
+				pos+= rest.find( '>' ).map_or( rest.len(), |end| end + 1 );
+			}else if rest[1..].starts_with( |c: char| c.is_ascii_alphabetic() ) {
+				let	body= &rest[1..];
+				let	name_len= body.find( |c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == ':') ).unwrap_or( body.len() );
+				let	name= body[..name_len].to_ascii_lowercase();
+				let	mut	e= HtmlElement::new( &name, self.span( pos ) );
+				let	len= Self::read_attrs( &body[name_len..], &mut e );
+				pos+= 1 + name_len + len;
+				if RAW_TAGS.contains( &name.as_str() ) {
+					// The text of <script> is not html, it ends at its end tag.
+					let	end_tag= format!( "</{}", name );
+					let	body= &text[pos..];
+					let	end= body.to_ascii_lowercase().find( &end_tag ).unwrap_or( body.len() );
+					e.children.push( Node::Text( decode_entities( &body[..end] ), self.span( pos ) ) );
+					self.stack.last_mut().unwrap().children.push( Node::Element( e ) );
+					pos+= end;
+					pos+= text[pos..].find( '>' ).map_or( text.len() - pos, |end| end + 1 );
+				}else{
+					self.open( e );
+				}
+			}else{
+				self.add_text( "<", pos );
+				pos+= 1;
+			}
+		}
+		while self.stack.len() > 1 {
+			self.pop();
+		}
+		return	self.stack.pop().map_or( Vec::new(), |root| root.children );
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+// "div#main", ".page" or "tag.class", the selectors separated by spaces are the descendants.
+#[derive(Debug,Clone)]
+struct Selector {
+	tag		: Option<String>,
+	id		: Option<String>,
+	classes	: Vec<String>,
+}
+
+impl Selector {
+	fn	parse( text: &str ) -> Option<Self>
+	{
+		let	mut	selector= Selector{ tag: None, id: None, classes: Vec::new() };
+		let	mut	rest= text;
+		let	name_len= rest.find( ['#', '.'] ).unwrap_or( rest.len() );
+		if name_len != 0 {
+			selector.tag= Some( rest[..name_len].to_ascii_lowercase() );
+		}
+		rest= &rest[name_len..];
+		while let Some(mark)= rest.chars().next() {
+			let	len= rest[1..].find( ['#', '.'] ).map_or( rest.len(), |len| len + 1 );
+			let	name= &rest[1..len];
+			if name.is_empty() || !name.chars().all( |c| c.is_alphanumeric() || c == '-' || c == '_' ) {
+				return	None;
+			}
+			if mark == '#' {
+				selector.id= Some( name.to_string() );
+			}else{
+				selector.classes.push( name.to_string() );
+			}
+			rest= &rest[len..];
+		}
+		if selector.tag.as_ref().is_some_and( |tag| !tag.chars().all( |c| c.is_ascii_alphanumeric() ) ) {
+			return	None;
+		}
+		return	Some( selector );
+	}
+	fn	matches( &self, e: &HtmlElement ) -> bool
+	{
+		self.tag.as_ref().is_none_or( |tag| *tag == e.name )
+			&& self.id.as_ref().is_none_or( |id| e.attr( "id" ) == Some( id.as_str() ) )
+			&& self.classes.iter().all( |class| e.has_class( class ) )
+	}
+}
+
+// The first element in the order of the page, matched is the count of the ancestors found.
+fn	find_root<'a>( nodes: &'a [Node], chain: &[Selector], matched: usize ) -> Option<&'a HtmlElement>
+{
+	for node in nodes {
+		let	Node::Element(e)= node else { continue };
+		if !chain[matched].matches( e ) {
+			if let Some(found)= find_root( &e.children, chain, matched ) {
+				return	Some(found);
+			}
+		}else if matched + 1 == chain.len() {
+			return	Some(e);
+		}else if let Some(found)= find_root( &e.children, chain, matched + 1 ) {
+			return	Some(found);
+		}
+	}
+	return	None;
+}
+
+
+//-----------------------------------------------------------------------------
+
+// The inline text of the paragraph, the runs of the white spaces are one space.
+struct InlineText {
+	list	: Vec<Inline>,
+	space	: bool,		// after a space or at the head, the next space is dropped
+}
+
+impl InlineText {
+	fn	new() -> Self
+	{
+		InlineText{ list: Vec::new(), space: true }
+	}
+	fn	child( &self ) -> Self
+	{
+		InlineText{ list: Vec::new(), space: self.space }
+	}
+	fn	add_text( &mut self, text: &str, span: Span )
+	{
+		let	mut	buffer= String::new();
+		for ch in text.chars() {
+			if ch.is_whitespace() {
+				if !self.space {
+					buffer.push( ' ' );
+					self.space= true;
+				}
+			}else{
+				buffer.push( ch );
+				self.space= false;
+			}
+		}
+		if !buffer.is_empty() {
+			push_text( &mut self.list, &buffer, span );
+		}
+	}
+	fn	add_nodes( &mut self, nodes: &[Node] )
+	{
+		for node in nodes {
+			match node {
+				Node::Text(text,span) => {
+					self.add_text( text, *span );
+				},
+				Node::Element(e) => {
+					self.add_element( e );
+				},
+			}
+		}
+	}
+	fn	add_child( &mut self, e: &HtmlElement, make: fn( Vec<Inline> ) -> InlineNode )
+	{
+		let	mut	child= self.child();
+		child.add_nodes( &e.children );
+		self.space= child.space;
+		if !child.list.is_empty() {
+			self.list.push( Inline::at( make( child.list ), e.span ) );
+		}
+	}
+	fn	add_element( &mut self, e: &HtmlElement )
+	{
+		match e.name.as_str() {
+			"strong" | "b" => {
+				self.add_child( e, InlineNode::Strong );
+			},
+			"em" | "i" | "cite" | "dfn" | "var" => {
+				self.add_child( e, InlineNode::Emphasis );
+			},
+			"del" | "s" | "strike" => {
+				self.add_child( e, InlineNode::Strike );
+			},
+			"code" | "tt" | "kbd" | "samp" => {
+				let	text= raw_text( &e.children );
+				if !text.is_empty() {
+					self.list.push( Inline::at( InlineNode::Code( text ), e.span ) );
+					self.space= false;
+				}
+			},
+			"a" => {
+				let	Some(url)= e.attr( "href" ) else {
+					self.add_nodes( &e.children );
+					return;
+				};
+				let	mut	child= self.child();
+				child.add_nodes( &e.children );
+				self.space= child.space;
+				let	text= if plain_text( &child.list ).trim() == url { Vec::new() } else { child.list };
+				self.list.push( Inline::at( InlineNode::Link{ url: url.to_string(), text: text }, e.span ) );
+			},
+			"img" => {
+				let	mut	image= Image::new( e.attr( "src" ).unwrap_or( "" ) );
+				image.alt= e.attr( "alt" ).unwrap_or( "" ).to_string();
+				image.title= e.attr( "title" ).unwrap_or( "" ).to_string();
+				image.width= e.attr( "width" ).and_then( |w| w.trim_end_matches( "px" ).parse().ok() );
+				image.height= e.attr( "height" ).and_then( |h| h.trim_end_matches( "px" ).parse().ok() );
+				self.list.push( Inline::at( InlineNode::Image( image ), e.span ) );
+				self.space= false;
+			},
+			"br" => {
+				self.trim_end();
+				self.list.push( Inline::at( InlineNode::LineBreak, e.span ) );
+				self.space= true;
+			},
+			name if SKIP_TAGS.contains( &name ) => {
+			},
+			name if BLOCK_TAGS.contains( &name ) || name == "li" => {
+				// The block in the inline text, the table cell with <p>.
+				self.add_text( " ", e.span );
+				self.add_nodes( &e.children );
+				self.add_text( " ", e.span );
+			},
+			_ => {
+				self.add_nodes( &e.children );
+			},
+		}
+	}
+	fn	trim_end( &mut self )
+	{
+		if let Some(Inline{ node: InlineNode::Text(text), .. })= self.list.last_mut() {
+			let	len= text.trim_end().len();
+			text.truncate( len );
+			if text.is_empty() {
+				self.list.pop();
+			}
+		}
+	}
+	fn	finish( mut self ) -> Vec<Inline>
+	{
+		self.trim_end();
+		while let Some(InlineNode::LineBreak)= self.list.last().map( |node| &node.node ) {
+			self.list.pop();
+			self.trim_end();
+		}
+		return	self.list;
+	}
+}
+
+// The text of <pre> and <code> as it is, <br> is the new line.
+fn	raw_text( nodes: &[Node] ) -> String
+{
+	let	mut	buffer= String::new();
+	for node in nodes {
+		match node {
+			Node::Text(text,_) => {
+				buffer+= text;
+			},
+			Node::Element(e) if e.name == "br" => {
+				buffer+= "\n";
+			},
+			Node::Element(e) => {
+				buffer+= &raw_text( &e.children );
+			},
+		}
+	}
+	return	buffer;
+}
+
+fn	cell_align( e: &HtmlElement ) -> ETableAlign
+{
+	let	style= e.attr( "style" ).unwrap_or( "" ).to_ascii_lowercase();
+	let	align= style.split( ';' ).find_map( |decl| decl.split_once( ':' ).filter( |(key,_)| key.trim() == "text-align" ).map( |(_,value)| value.trim().to_string() ) );
+	let	align= align.or_else( || e.attr( "align" ).map( |value| value.to_ascii_lowercase() ) );
+	match align.as_deref() {
+		Some("left") => ETableAlign::LEFT,
+		Some("center") => ETableAlign::CENTER,
+		Some("right") => ETableAlign::RIGHT,
+		_ => ETableAlign::DEFAULT,
+	}
+}
+
+// "language-rust" of <pre> or <code>, DokuWiki writes "code rust".
+fn	code_language( e: &HtmlElement ) -> String
+{
+	let	code= e.children.iter().find_map( |node| match node {
+			Node::Element(child) if child.name == "code" => Some(child),
+			_ => None,
+		});
+	for element in [Some(e), code].into_iter().flatten() {
+		for class in element.classes() {
+			if let Some(lang)= class.strip_prefix( "language-" ).or_else( || class.strip_prefix( "lang-" ) ) {
+				return	lang.to_string();
+			}
+		}
+		if element.has_class( "code" ) || element.has_class( "file" ) {
+			if let Some(lang)= element.classes().find( |class| *class != "code" && *class != "file" ) {
+				return	lang.to_string();
+			}
+		}
+	}
+	return	String::new();
+}
+
+
+//-----------------------------------------------------------------------------
+
+pub struct Decoder {
+	root	: Vec<Selector>,	// the content of the page, the whole page without it
+}
+
+impl Decoder {
+	pub fn new() -> Self
+	{
+		Decoder{
+			root: Vec::new(),
+		}
+	}
+	// "div.page", "#main-content" or "main article".
+	pub fn	set_root( &mut self, selector: &str ) -> Result<(),Error>
+	{
+		let	chain: Option<Vec<Selector>>= selector.split_whitespace().map( Selector::parse ).collect();
+		match chain {
+			Some(chain) if !chain.is_empty() => {
+				self.root= chain;
+				return	Ok( () );
+			},
+			_ => return	Err( Error::Parse( format!( "root selector \"{}\"", selector ), "only tag, #id and .class are supported".to_string() ) ),
+		}
+	}
+	fn	flush( para: &mut InlineText, page: &mut Vec<Block> )
+	{
+		let	text= std::mem::replace( para, InlineText::new() ).finish();
+		if !text.is_empty() {
+			let	span= text[0].span;
+			page.push( Block::Paragraph( ParagraphElement{ text: text, span: span } ) );
+		}
+	}
+	fn	decode_blocks( &self, nodes: &[Node], page: &mut Vec<Block> )
+	{
+		let	mut	para= InlineText::new();
+		for node in nodes {
+			let	e= match node {
+				Node::Text(text,span) => {
+					para.add_text( text, *span );
+					continue;
+				},
+				Node::Element(e) => e,
+			};
+			match e.name.as_str() {
+				"h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+					Self::flush( &mut para, page );
+					let	mut	title= InlineText::new();
+					title.add_nodes( &e.children );
+					page.push( Block::HTag( HTagElement{ title: title.finish(), level: e.name[1..].parse().unwrap_or( 1 ), span: e.span } ) );
+				},
+				"p" => {
+					Self::flush( &mut para, page );
+					para.add_nodes( &e.children );
+					Self::flush( &mut para, page );
+				},
+				"ul" | "ol" if e.has_class( "toc" ) => {
+					Self::flush( &mut para, page );
+					page.push( Block::TOC( TOCElement{ entries: Vec::new(), span: e.span } ) );
+				},
+				"ul" | "ol" => {
+					Self::flush( &mut para, page );
+					page.push( Block::List( self.decode_list( e ) ) );
+				},
+				"pre" => {
+					Self::flush( &mut para, page );
+					let	text= raw_text( &e.children );
+					let	text= text.strip_prefix( '\n' ).unwrap_or( &text );
+					let	text= if text.is_empty() || text.ends_with( '\n' ) { text.to_string() } else { format!( "{}\n", text ) };
+					page.push( Block::PRETag( PRETagElement{ text: text, code: code_language( e ), span: e.span } ) );
+				},
+				"table" => {
+					Self::flush( &mut para, page );
+					page.push( Block::Table( self.decode_table( e ) ) );
+				},
+				"hr" => {
+					Self::flush( &mut para, page );
+					page.push( Block::HRTag( e.span ) );
+				},
+				"blockquote" => {
+					Self::flush( &mut para, page );
+					let	mut	blocks= Vec::new();
+					self.decode_blocks( &e.children, &mut blocks );
+					page.push( Block::Quote( BlockQuoteElement{ blocks: blocks, span: e.span } ) );
+				},
+				name if SKIP_TAGS.contains( &name ) => {
+				},
+				// The toc of DokuWiki, PukiWiki and Confluence is written again from the headings.
+				_ if e.has_class( "toc" ) || e.has_class( "toc-macro" ) || e.has_class( "contents" ) || e.attr( "id" ) == Some( "dw__toc" ) => {
+					Self::flush( &mut para, page );
+					page.push( Block::TOC( TOCElement{ entries: Vec::new(), span: e.span } ) );
+				},
+				name if BLOCK_TAGS.contains( &name ) || ["html", "body", "center", "details", "summary", "figcaption", "dt", "dd", "li"].contains( &name ) => {
+					Self::flush( &mut para, page );
+					self.decode_blocks( &e.children, page );
+				},
+				_ => {
+					para.add_element( e );
+				},
+			}
+		}
+		Self::flush( &mut para, page );
+	}
+	// The list directly in the list is the child of the item before it.
+	fn	decode_list( &self, e: &HtmlElement ) -> ListElement
+	{
+		let	ordered= e.name == "ol";
+		let	start= e.attr( "start" ).and_then( |start| start.parse().ok() ).unwrap_or( 1 );
+		let	mut	list= ListElement::new( ordered, start, e.span );
+		for node in &e.children {
+			let	Node::Element(child)= node else { continue };
+			if child.name == "li" {
+				let	mut	blocks= Vec::new();
+				self.decode_blocks( &child.children, &mut blocks );
+				list.items.push( ListItem{ blocks: blocks, span: child.span } );
+			}else if child.name == "ul" || child.name == "ol" {
+				let	nested= Block::List( self.decode_list( child ) );
+				match list.items.last_mut() {
+					Some(item) => item.blocks.push( nested ),
+					None => list.items.push( ListItem{ blocks: vec![nested], span: child.span } ),
+				}
+			}
+		}
+		return	list;
+	}
+	// The rows of <thead>, <tbody> and <tfoot>, rowspan is the span mark under the cell.
+	fn	decode_table( &self, e: &HtmlElement ) -> TABLEElement
+	{
+		let	mut	rows= Vec::new();
+		for node in &e.children {
+			let	Node::Element(child)= node else { continue };
+			match child.name.as_str() {
+				"tr" => rows.push( child ),
+				"thead" | "tbody" | "tfoot" => {
+					for node in &child.children {
+						if let Node::Element(row)= node {
+							if row.name == "tr" {
+								rows.push( row );
+							}
+						}
+					}
+				},
+				_ => {},
+			}
+		}
+		let	mut	grid= Vec::new();
+		let	mut	rowspan: Vec<u32>= Vec::new();
+		for row in rows {
+			let	mut	cells= Vec::new();
+			for node in &row.children {
+				let	Node::Element(td)= node else { continue };
+				if td.name != "td" && td.name != "th" {
+					continue;
+				}
+				while rowspan.get( cells.len() ).is_some_and( |rows| *rows > 0 ) {
+					rowspan[cells.len()]-= 1;
+					cells.push( TableGrid::SpanUp );
+				}
+				let	mut	text= InlineText::new();
+				text.add_nodes( &td.children );
+				let	colspan= td.attr( "colspan" ).and_then( |span| span.parse().ok() ).unwrap_or( 1u32 ).max( 1 );
+				let	rows= td.attr( "rowspan" ).and_then( |span| span.parse().ok() ).unwrap_or( 1u32 ).max( 1 );
+				let	col= cells.len();
+				cells.push( TableGrid::Cell( TableAttr{
+						text:		text.finish(),
+						align:		cell_align( td ),
+						header:		Some( td.name == "th" ),
+						colspan:	1,
+						rowspan:	1,
+					}));
+				for _ in 1..colspan {
+					cells.push( TableGrid::SpanLeft );
+				}
+				if rows > 1 {
+					if rowspan.len() < cells.len() {
+						rowspan.resize( cells.len(), 0 );
+					}
+					for count in &mut rowspan[col..cells.len()] {
+						*count= rows - 1;
+					}
+				}
+			}
+			while rowspan.get( cells.len() ).is_some_and( |rows| *rows > 0 ) {
+				rowspan[cells.len()]-= 1;
+				cells.push( TableGrid::SpanUp );
+			}
+			grid.push( (row.span, cells) );
+		}
+		return	build_table( grid );
+	}
+}
+
+impl TextDecoder for Decoder {
+	fn	decode_lines( &self, lines: &[SourceLine] ) -> Document
+	{
+		let	mut	text= String::new();
+		let	mut	starts= Vec::new();
+		for source in lines {
+			starts.push( (text.len(), source.span) );
+			text+= &source.text;
+			text+= "\n";
+		}
+		let	builder= TreeBuilder{ text: &text, lines: starts, stack: vec![HtmlElement::new( "", Span::default() )] };
+		let	nodes= builder.build();
+		let	mut	page= Document::new();
+		// The whole page when the root is not found.
+		let	root= if self.root.is_empty() { None } else { find_root( &nodes, &self.root, 0 ) };
+		match root {
+			Some(root) => self.decode_blocks( &root.children, &mut page.element_list ),
+			None => self.decode_blocks( &nodes, &mut page.element_list ),
+		}
+		fill_toc( &mut page.element_list );
+		page
+	}
+}
+
+
+//=============================================================================
+
+trait	EncodeElement {
+	fn	output( &self, encoder: &Encoder ) -> String;
+}
+
+
+//-----------------------------------------------------------------------------
+
+// The id is the anchor of the markdown toc, "Getting Started" is "getting-started".
+impl	EncodeElement for HTagElement {
+	fn	output( &self, encoder: &Encoder ) -> String
+	{
+		let	id= anchor( &plain_text( &self.title ), &mut encoder.anchors.borrow_mut() );
+		let	level= self.level.clamp( 1, 6 );
+		return	format!( "<h{} id=\"{}\">{}</h{}>\n", level, escape_html( &id ), encode_to_html( &self.title ), level );
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+// The deeper headings are the nested list in the item of the upper one.
+impl	EncodeElement for TOCElement {
+	fn	output( &self, _encoder: &Encoder ) -> String
+	{
+		let	mut	used= HashMap::new();
+		let	mut	levels: Vec<u32>= Vec::new();
+		let	mut	buffer= String::new();
+		for (level,title) in &self.entries {
+			if levels.is_empty() {
+				buffer+= "<ul class=\"toc\">\n";
+				levels.push( *level );
+			}else if levels.last().is_some_and( |last| level > last ) {
+				buffer+= "\n<ul>\n";
+				levels.push( *level );
+			}else{
+				buffer+= "</li>\n";
+				while levels.len() > 1 && *level <= levels[levels.len()-2] {
+					buffer+= "</ul>\n</li>\n";
+					levels.pop();
+				}
+			}
+			let	text= plain_text( title );
+			buffer+= &format!( "<li><a href=\"#{}\">{}</a>", escape_html( &anchor( &text, &mut used ) ), escape_html( &text ) );
+		}
+		if levels.is_empty() {
+			return	String::new();
+		}
+		buffer+= "</li>\n";
+		while levels.len() > 1 {
+			buffer+= "</ul>\n</li>\n";
+			levels.pop();
+		}
+		buffer+= "</ul>\n";
+		return	buffer;
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+// The first paragraph is the text of the item, the other blocks follow it in the item.
+impl	EncodeElement for ListElement {
+	fn	output( &self, encoder: &Encoder ) -> String
+	{
+		let	mut	buffer= if !self.ordered {
+				"<ul>\n".to_string()
+			}else if self.start != 1 {
+				format!( "<ol start=\"{}\">\n", self.start )
+			}else{
+				"<ol>\n".to_string()
+			};
+		for item in &self.items {
+			buffer+= "<li>";
+			for (i,block) in item.blocks.iter().enumerate() {
+				match block {
+					Block::Paragraph(e) if i == 0 => {
+						buffer+= &render_paragraph( &e.text, encoder.paragraph, encode_to_html );
+					},
+					_ => {
+						buffer+= "\n";
+						buffer+= &encoder.encode_single( block );
+					},
+				}
+			}
+			buffer+= "</li>\n";
+		}
+		buffer+= if self.ordered { "</ol>\n" } else { "</ul>\n" };
+		return	buffer;
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+impl	EncodeElement for BlockQuoteElement {
+	fn	output( &self, encoder: &Encoder ) -> String
+	{
+		let	mut	buffer= "<blockquote>\n".to_string();
+		for block in &self.blocks {
+			buffer+= &encoder.encode_single( block );
+		}
+		buffer+= "</blockquote>\n";
+		return	buffer;
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+impl	EncodeElement for PRETagElement {
+	fn	output( &self, _encoder: &Encoder ) -> String
+	{
+		if self.code.is_empty() {
+			return	format!( "<pre><code>{}</code></pre>\n", escape_html( &self.text ) );
+		}
+		return	format!( "<pre><code class=\"language-{}\">{}</code></pre>\n", escape_html( &self.code ), escape_html( &self.text ) );
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+impl	EncodeElement for TABLEElement {
+	fn	output( &self, _encoder: &Encoder ) -> String
+	{
+		let	mut	buffer= "<table>\n".to_string();
+		for (row,line) in self.grid().iter().enumerate() {
+			buffer+= "<tr>\n";
+			for (col,cell) in line.iter().enumerate() {
+				let	TableGrid::Cell(attr)= cell else { continue };
+				let	tag= if self.cell_header( row, col, attr ) { "th" } else { "td" };
+				let	mut	params= String::new();
+				match self.cell_align( row, col, attr ) {
+					ETableAlign::CENTER => {
+						params+= " style=\"text-align: center\"";
+					},
+					ETableAlign::LEFT => {
+						params+= " style=\"text-align: left\"";
+					},
+					ETableAlign::RIGHT => {
+						params+= " style=\"text-align: right\"";
+					},
+					_ => {
+					},
+				}
+				if attr.colspan > 1 {
+					params+= &format!( " colspan=\"{}\"", attr.colspan );
+				}
+				if attr.rowspan > 1 {
+					params+= &format!( " rowspan=\"{}\"", attr.rowspan );
+				}
+				buffer+= &format!( "<{}{}>{}</{}>\n", tag, params, encode_to_html( &attr.text ), tag );
+			}
+			buffer+= "</tr>\n";
+		}
+		buffer+= "</table>\n";
+		return	buffer;
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+impl	EncodeElement for ParagraphElement {
+	fn	output( &self, encoder: &Encoder ) -> String
+	{
+		format!( "<p>{}</p>\n", render_paragraph( &self.text, encoder.paragraph, encode_to_html ) )
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+// The standalone page, the encoder writes the fragment without it.
+#[derive(Debug,Clone,Default)]
+pub struct Page {
+	pub title		: Option<String>,	// None is the leading h1, or the name of the file
+	pub stylesheet	: Option<String>,	// url of the <link rel="stylesheet">
+}
+
+impl Page {
+	fn	title( &self, document: &Document ) -> String
+	{
+		if let Some(title)= &self.title {
+			return	title.clone();
+		}
+		if let Some(Block::HTag(e))= document.element_list.iter().find( |block| matches!( block, Block::HTag(e) if e.level == 1 ) ) {
+			return	plain_text( &e.title );
+		}
+		let	name= document.file_name.rsplit( ['/', '\\'] ).next().unwrap_or( "" );
+		return	name.rsplit_once( '.' ).map_or( name, |(stem,_)| stem ).to_string();
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+pub struct	Encoder {
+	pub paragraph	: ParagraphMode,
+	pub page		: Option<Page>,
+	anchors			: RefCell<HashMap<String,usize>>,	// ids of the headings written
+}
+
+impl	Encoder {
+	pub	fn	new() -> Self
+	{
+		Self{
+			paragraph: ParagraphMode::Keep,
+			page: None,
+			anchors: RefCell::new( HashMap::new() ),
+		}
+	}
+}
+
+impl	TextEncoder for Encoder {
+	fn	encode_single( &self, element: &Block ) -> String
+	{
+		match element {
+			Block::None => String::new(),
+			Block::Paragraph(e) => e.output( self ),
+			Block::HTag(e) => e.output( self ),
+			Block::List(e) => e.output( self ),
+			Block::PRETag(e) => e.output( self ),
+			Block::Table(e) => e.output( self ),
+			Block::Quote(e) => e.output( self ),
+			Block::HRTag(_) => "<hr>\n".to_string(),
+			Block::TOC(e) => e.output( self ),
+		}
+	}
+	fn	begin( &self, document: &Document ) -> String
+	{
+		self.anchors.borrow_mut().clear();
+		let	Some(page)= &self.page else {
+			return	String::new();
+		};
+		let	mut	buffer= "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n".to_string();
+		buffer+= &format!( "<title>{}</title>\n", escape_html( &page.title( document ) ) );
+		if let Some(url)= &page.stylesheet {
+			buffer+= &format!( "<link rel=\"stylesheet\" href=\"{}\">\n", escape_html( url ) );
+		}
+		buffer+= "</head>\n<body>\n";
+		return	buffer;
+	}
+	fn	end( &self, _document: &Document ) -> String
+	{
+		if self.page.is_none() {
+			return	String::new();
+		}
+		return	"</body>\n</html>\n".to_string();
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
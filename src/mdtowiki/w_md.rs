@@ -1,13 +1,15 @@
 // 2022 Hiroyuki Ogasawara
 // vim:ts=4 sw=4 noet:
 
-use std::fs;
-use std::io::{self,BufRead};
-use regex::{self,Regex};
+use std::collections::HashMap;
+use regex;
 use	lazy_static::lazy_static;
 
+use super::span::*;
+use super::inline::*;
 use super::element::*;
 use super::document::*;
+use super::warning::*;
 
 
 #[allow(dead_code)]
@@ -21,206 +23,173 @@ fn print_type<T>( _: &T )
 
 //=============================================================================
 
-struct EMMarkPat {
-	pat:  Regex,
-	cmd1: &'static str,
-	cmd2: &'static str,
+fn	md_special( parser: &InlineParser, text: &str, _prev: Option<char>, at: Span ) -> Option<(Vec<Inline>,usize)>
+{
+	let	ch= text.chars().next()?;
+	match ch {
+		'&' => {
+			for (entity,decoded) in [("&lt;","<"), ("&gt;",">"), ("&amp;","&")] {
+				if text.starts_with( entity ) {
+					return	Some( (vec![Inline::new( InlineNode::Text( decoded.to_string() ) )], entity.len()) );
+				}
+			}
+		},
+		'\\' => {
+			if let Some(c)= text[1..].chars().next() {
+				match c {
+					'*'|'_'|'\\'|'`'|'#'|'+'|'-'|'.'|'!'|'{'|'}'|'['|']'|'('|')'|'<'|'>' => {
+						return	Some( (vec![Inline::new( InlineNode::Text( c.to_string() ) )], 1 + c.len_utf8()) );
+					},
+					_ => {
+					},
+				}
+			}
+		},
+		'!' => {
+			if !text[1..].starts_with( '[' ) {
+				return	None;
+			}
+			let	tpos= 1 + find_pair( &text[1..], '[', ']' )?;
+			if !text[tpos+1..].starts_with( '(' ) {
+				return	None;
+			}
+			let	upos= tpos + 1 + find_pair( &text[tpos+1..], '(', ')' )?;
+			let	target= text[tpos+2..upos].trim();
+			let	(src,title)= match target.split_once( char::is_whitespace ) {
+				Some((src,title)) => (src, title.trim().trim_matches( |c| c == '"' || c == '\'' )),
+				None => (target, ""),
+			};
+			let	mut	image= Image::new( src );
+			image.alt= text[2..tpos].to_string();
+			image.title= title.to_string();
+			return	Some( (vec![Inline::new( InlineNode::Image( image ) )], upos + 1) );
+		},
+		'[' => {
+			let	tpos= find_pair( text, '[', ']' )?;
+			if !text[tpos+1..].starts_with( '(' ) {
+				return	None;
+			}
+			let	upos= tpos + 1 + find_pair( &text[tpos+1..], '(', ')' )?;
+			let	url= text[tpos+2..upos].trim().to_string();
+			let	label= &text[1..tpos];
+			let	link= InlineNode::Link{ url: url, text: parser.parse_at( label, at.at( text, label ) ) };
+			return	Some( (vec![Inline::new( link )], upos + 1) );
+		},
+		_ => {
+		},
+	}
+	return	None;
 }
 
-pub fn	replace_md_tags( line: &str ) -> String
+pub fn	decode_from_md( line: &str, span: Span ) -> Vec<Inline>
 {
 	lazy_static! {
-		static ref	PAT_EM_ARRAY: Vec<EMMarkPat>= vec![
-				EMMarkPat{ pat: Regex::new( r"^(.*\b)___([^_]+)___(\b.*)$" ).unwrap(),			cmd1: "\x07B3", cmd2: "\x07b3", },
-				EMMarkPat{ pat: Regex::new( r"^(.*\b)__([^_]+)__(\b.*)$" ).unwrap(),			cmd1: "\x07B2", cmd2: "\x07b2", },
-				EMMarkPat{ pat: Regex::new( r"^(.*\b)_([^_]+)_(\b.*)$" ).unwrap(),				cmd1: "\x07B2", cmd2: "\x07b1", },
-				EMMarkPat{ pat: Regex::new( r"(^.*[^\\]|^)\*\*\*([^*]+)\*\*\*(.*)$" ).unwrap(),	cmd1: "\x07B3", cmd2: "\x07b3", },
-				EMMarkPat{ pat: Regex::new( r"(^.*[^\\]|^)\*\*([^*]+)\*\*(.*)$" ).unwrap(),		cmd1: "\x07B2", cmd2: "\x07b2", },
-				EMMarkPat{ pat: Regex::new( r"(^.*[^\\]|^)\*([^*]+)\*(.*)$" ).unwrap(),			cmd1: "\x07B1", cmd2: "\x07b1", },
-				EMMarkPat{ pat: Regex::new( r"^(.*)~~([^~]+)~~(.*)$" ).unwrap(),				cmd1: "\x07D0", cmd2: "\x07d0", },
-				EMMarkPat{ pat: Regex::new( r"^(.*)`([^`]+)`(.*)$" ).unwrap(),					cmd1: "\x07C0", cmd2: "\x07c0", },
-			];
-		static ref	PAT_LINK: Regex= Regex::new( r"^(.*)\[(.+)\]\((.+)\)(.*)$" ).unwrap();
-	}
-	let	mut	buffer= line.to_string();
-	loop {
-		let	mut	bfound= false;
-		for emmark in PAT_EM_ARRAY.iter() {
-			if let Some(v)= emmark.pat.captures( &buffer ) {
-				let	mut buffer2= String::new();
-				buffer2+= &v[1];
-				buffer2+= emmark.cmd1;
-				buffer2+= &v[2];
-				buffer2+= emmark.cmd2;
-				buffer2+= &v[3];
-				buffer= buffer2;
-				bfound= true;
-				break;
-			}
-		}
-		if let Some(v)= PAT_LINK.captures( &buffer ) {
-			let	mut buffer2= String::new();
-			buffer2+= &v[1];
-			buffer2+= "\x07L0";
-			buffer2+= &v[3];
-			buffer2+= "\x07L1";
-			buffer2+= &v[2];
-			buffer2+= "\x07L2";
-			buffer2+= &v[4];
-			buffer= buffer2;
-			bfound= true;
-		}
-		if !bfound {
-			break;
-		}
+		static ref	PARSER: InlineParser= InlineParser{
+				delimiters: vec![
+					Delimiter{ open: "___", close: "___",	kind: InlineKind::StrongEmphasis,	word: true, },
+					Delimiter{ open: "__",	close: "__",	kind: InlineKind::Strong,			word: true, },
+					Delimiter{ open: "_",	close: "_",		kind: InlineKind::Emphasis,			word: true, },
+					Delimiter{ open: "***", close: "***",	kind: InlineKind::StrongEmphasis,	word: false, },
+					Delimiter{ open: "**",	close: "**",	kind: InlineKind::Strong,			word: false, },
+					Delimiter{ open: "*",	close: "*",		kind: InlineKind::Emphasis,			word: false, },
+					Delimiter{ open: "~~",	close: "~~",	kind: InlineKind::Strike,			word: false, },
+					Delimiter{ open: "`",	close: "`",		kind: InlineKind::Code,				word: false, },
+				],
+				tight: true,
+				special: md_special,
+			};
+	}
+	return	PARSER.parse_at( line, span );
+}
+
+// A trailing backslash or two spaces force a line break.
+fn	decode_md_line( line: &str, span: Span ) -> Vec<Inline>
+{
+	let	body= line.trim_start();
+	let	trimmed= body.trim_end();
+	let	at= span.at( line, body );
+	if let Some(text)= trimmed.strip_suffix( '\\' ) {
+		let	mut	list= decode_from_md( text, at );
+		list.push( Inline::at( InlineNode::LineBreak, at.advance( text ) ) );
+		return	list;
 	}
-
-	return	buffer;
+	let	mut	list= decode_from_md( trimmed, at );
+	if body.ends_with( "  " ) {
+		list.push( Inline::at( InlineNode::LineBreak, at.advance( trimmed ) ) );
+	}
+	return	list;
 }
 
-pub fn	decode_from_md( line0: &str ) -> String
+fn	escape_md( text: &str ) -> String
 {
-	let	line= &replace_md_tags( line0 );
 	let	mut	buffer= String::new();
-	let	mut	char_it= line.chars();
-	loop {
-		let	ch= char_it.next();
+	for ch in text.chars() {
 		match ch {
-			Some('&') => {
-				if char_it.as_str().starts_with( "lt;" ) {
-					buffer+= "<";
-					char_it.next();
-					char_it.next();
-					char_it.next();
-				}else if char_it.as_str().starts_with( "gt;" ) {
-					buffer+= ">";
-					char_it.next();
-					char_it.next();
-					char_it.next();
-				}else if char_it.as_str().starts_with( "amp;" ) {
-					buffer+= "&";
-					char_it.next();
-					char_it.next();
-					char_it.next();
-					char_it.next();
-				}else{
-					buffer+= "&";
-				}
+			'<' => {
+				//buffer+= "&lt;";
+				buffer+= "\\<";
 			},
-			Some('\\') => {
-				if let	Some(c)= char_it.next() {
-					match c {
-						'*'|'_'|'\\'|'`'|'#'|'+'|'-'|'.'|'!'|'{'|'}'|'['|']'|'('|')'|'<'|'>' => {
-							buffer+= &format!( "{}", c );
-						},
-						_ => {
-							buffer+= &format!( "\\{}", c );
-						},
-					}
-				}else{
-					buffer+= "\\";
-					return	buffer;
-				}
+			'>' => {
+				//buffer+= "&gt;";
+				buffer+= "\\>";
 			},
-			Some(c) => {
-				buffer+= &format!( "{}", c );
+			/*'_' => {
+				buffer+= "\\_";
+			},*/
+			'*' => {
+				buffer+= "\\*";
 			},
-			None => {
-				return	buffer;
+			c => {
+				buffer.push( c );
 			},
 		}
 	}
+	return	buffer;
 }
 
-pub fn	encode_to_md( line: &str ) -> String
+pub fn	encode_to_md( list: &[Inline] ) -> String
 {
 	let	mut	buffer= String::new();
-	let	mut	char_it= line.chars();
-	loop {
-		let	ch= char_it.next();
-		match ch {
-			Some('<') => {
-				//buffer+= "&lt;";
-				buffer+= "\\<";
+	for node in list {
+		match &node.node {
+			InlineNode::Text(text) => {
+				buffer+= &escape_md( text );
 			},
-			Some('>') => {
-				//buffer+= "&gt;";
-				buffer+= "\\>";
+			InlineNode::Strong(child) => {
+				buffer+= &format!( "**{}**", encode_to_md( child ) );
 			},
-			/*Some('_') => {
-				buffer+= "\\_";
-			},*/
-			Some('*') => {
-				buffer+= "\\*";
+			InlineNode::Emphasis(child) => {
+				buffer+= &format!( "*{}*", encode_to_md( child ) );
 			},
-			Some('\x07') => {
-				let	cmd0= char_it.next().unwrap();
-				let	cmd1= char_it.next().unwrap();
-				match cmd0 {
-					'B'|'b' => {
-						match cmd1 {
-							'1' => {
-								buffer+= "*";
-							},
-							'2' => {
-								buffer+= "**";
-							},
-							_ => {
-								buffer+= "***";
-							},
-						}
-					},
-					'D'|'d' => {
-						buffer+= "~~";
-					},
-					'C'|'c' => {
-						buffer+= "`";
-					},
-					'L' => {
-						match cmd1 {
-							'0' => {
-								let	mut	url= "";
-								let	mut	text= "";
-								if let Some(upos)= char_it.as_str().find( '\x07' ) {
-									url= &char_it.as_str()[..upos];
-									for _ in 0..upos {
-										char_it.next();
-									}
-									char_it.next();
-									char_it.next();
-									char_it.next();
-									if let Some(tpos)= char_it.as_str().find( '\x07' ) {
-										text= &char_it.as_str()[..tpos];
-										for _ in 0..tpos {
-											char_it.next();
-										}
-										char_it.next();
-										char_it.next();
-										char_it.next();
-									}
-								}
-								buffer+= "[";
-								buffer+= text;
-								buffer+= "](";
-								buffer+= url;
-								buffer+= ")";
-							},
-							_ => {
-							},
-						}
-					},
-					_ => {
-					},
+			InlineNode::Strike(child) => {
+				buffer+= &format!( "~~{}~~", encode_to_md( child ) );
+			},
+			InlineNode::Code(text) => {
+				buffer+= &format!( "`{}`", text );
+			},
+			InlineNode::Link{ url, text } => {
+				if text.is_empty() {
+					buffer+= &format!( "[{}]({})", url, url );
+				}else{
+					buffer+= &format!( "[{}]({})", encode_to_md( text ), url );
+				}
+			},
+			InlineNode::Image(image) => {
+				if image.title.is_empty() {
+					buffer+= &format!( "![{}]({})", image.alt, image.src );
+				}else{
+					buffer+= &format!( "![{}]({} \"{}\")", image.alt, image.src, image.title );
 				}
 			},
-			Some(c) => {
-				buffer+= &format!( "{}", c );
+			InlineNode::SoftBreak => {
+				buffer+= "\n";
 			},
-			None => {
-				return	buffer;
+			InlineNode::LineBreak => {
+				buffer+= "\\\n";
 			},
 		}
 	}
+	return	buffer;
 }
 
 
@@ -229,86 +198,100 @@ pub fn	encode_to_md( line: &str ) -> String
 //=============================================================================
 
 trait ElementGenerator {
-	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Box<dyn Element>>;
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>;
 }
 
 
 struct GenerateorContext {
+	// position of the current line
+	span: Span,
+	// paragraph
+	para: ParagraphElement,
 	// list
-	list_prev_indent: u32,
-	list_nest: u32,
-	list_indent: Vec<u32>,
+	list_block: bool,
+	list_lines: Vec<SourceLine>,
+	// quote
+	quote_block: bool,
+	quote_lines: Vec<SourceLine>,
+	quote_span: Span,
 	// pre
 	pre_block: bool,
+	pre_span: Span,
 	pre_code: String,
 	pre_data: String,
 	// table
 	table_block: bool,
 	table_column: Vec<TableColumn>,
-	table_pat: regex::Regex,
-	table_alpat: regex::Regex,
+	table_align: Vec<ETableAlign>,
 }
 
 impl GenerateorContext {
 	fn	new() -> Self
 	{
 		GenerateorContext{
-				list_prev_indent: 0,
-				list_nest: 0,
-				list_indent: vec![0;32],
+				span: Span::default(),
+				para: ParagraphElement::new(),
+				list_block: false,
+				list_lines: Vec::new(),
+				quote_block: false,
+				quote_lines: Vec::new(),
+				quote_span: Span::default(),
 				pre_block: false,
+				pre_span: Span::default(),
 				pre_code: String::new(),
 				pre_data: String::new(),
 				table_block: false,
 				table_column: Vec::new(),
-				table_pat: regex::Regex::new( r"^\|(.*)\|\s*$" ).unwrap(),
-				table_alpat: regex::Regex::new( r"^\s*([-:]+)\s*$" ).unwrap(),
+				table_align: Vec::new(),
 			}
 	}
 	//-------------------------------------------------------------------------
-	fn	indent_to_nest( &mut self, indent: u32 ) -> u32
-	{
-		let mut	nest;
-		if indent == 0 {
-			self.list_prev_indent= 0;
-			self.list_nest= 0;
-			self.list_indent[0]= 0;
-			nest= 0;
-		}else{
-			if indent > self.list_prev_indent {
-				self.list_nest+= 1;
-				self.list_indent[self.list_nest as usize]= indent;
-				self.list_prev_indent= indent;
-				nest= self.list_nest;
-			}else if indent < self.list_prev_indent {
-				nest= 0;
-				for ni in (1..self.list_nest+1).rev() {
-					if indent >= self.list_indent[ni as usize] {
-						nest= ni;
-						self.list_nest= ni;
-						break;
-					}
-				}
-				self.list_prev_indent= indent;
-			}else{
-				nest= self.list_nest;
-			}
+	fn	is_list_block( &self ) -> bool
+	{
+		self.list_block
+	}
+	// Returns false when the line is out of the list.
+	fn	add_list_block( &mut self, line: &str ) -> bool
+	{
+		if line.trim().is_empty() {
+			self.list_lines.push( SourceLine::new( "", self.span ) );
+			return	true;
 		}
-		return	nest;
+		if line.starts_with( char::is_whitespace ) || match_list_item( line ).is_some() {
+			self.list_lines.push( SourceLine::new( line, self.span ) );
+			return	true;
+		}
+		self.list_block= false;
+		return	false;
+	}
+	//-------------------------------------------------------------------------
+	fn	is_quote_block( &self ) -> bool
+	{
+		self.quote_block
+	}
+	// Returns false when the line is out of the quote.
+	fn	add_quote_block( &mut self, line: &str ) -> bool
+	{
+		if let Some(text)= strip_quote_mark( line ) {
+			self.quote_lines.push( SourceLine::new( text, self.span.at( line, text ) ) );
+			return	true;
+		}
+		self.quote_block= false;
+		return	false;
 	}
 	//-------------------------------------------------------------------------
 	fn	is_pre_block( &self ) -> bool
 	{
 		self.pre_block
 	}
-	fn	add_pre_block( &mut self, line: &str ) -> Option<Box<dyn Element>>
+	fn	add_pre_block( &mut self, line: &str ) -> Option<Block>
 	{
 		if line.starts_with( "```" ) {
 			self.pre_block= false;
-			return	Some( Box::new( PRETagElement{
-							etype:	ElementType::PRETAG,
+			return	Some( Block::PRETag( PRETagElement{
 							text: 	(&self.pre_data).to_string(),
 							code:	(&self.pre_code).to_string(),
+							span:	self.pre_span,
 						}));
 		}
 		//self.pre_data+= &decode_from_md_pre( &line );
@@ -321,17 +304,21 @@ impl GenerateorContext {
 	{
 		self.table_block
 	}
-	fn	add_table_block( &mut self, line: &str ) -> Option<Box<dyn Element>>
+	fn	add_table_block( &mut self, line: &str ) -> Option<Block>
 	{
-		let	pat_result= self.table_pat.captures( line );
+		lazy_static! {
+			static ref	TABLE_PAT: regex::Regex= regex::Regex::new( r"^\|(.*)\|\s*$" ).unwrap();
+			static ref	TABLE_ALPAT: regex::Regex= regex::Regex::new( r"^\s*([-:]+)\s*$" ).unwrap();
+		}
+		let	pat_result= TABLE_PAT.captures( line );
 		if let Some(v)= pat_result {
 			//println!( "TABLE={}", line );
 			let	tline= &v[1];
 			let	mut	params= tline.split( '|' ).peekable();
-			let	alpat_result= self.table_alpat.captures( params.peek().unwrap() );
+			let	alpat_result= TABLE_ALPAT.captures( params.peek().unwrap() );
 			if let Some(_)= alpat_result {
 				//println!( "AlignLine" );
-				for (i,param) in params.enumerate() {
+				for param in params {
 					let	mut	align= ETableAlign::DEFAULT;
 					let	tparam= &param.trim();
 					if tparam.starts_with( ":" ) {
@@ -343,34 +330,30 @@ impl GenerateorContext {
 					}else if tparam.ends_with( ":" ) {
 						align= ETableAlign::RIGHT;
 					}
-					if i < self.table_column[0].data.len() {
-						self.table_column[0].data[i].align= align;
-					}
+					self.table_align.push( align );
 				}
-				for attr in &mut self.table_column[0].data {
-					attr.header= true;
+				if let Some(column)= self.table_column.first_mut() {
+					column.header= true;
 				}
 			}else{
-				let	mut	column= TableColumn::new();
-				for (i,td) in params.enumerate() {
-					let	mut	align= ETableAlign::DEFAULT;
-					if self.table_column.len() > 0 && i < self.table_column[0].data.len() {
-						align= self.table_column[0].data[i].align;
-					}
+				let	mut	column= TableColumn::new( self.span );
+				for td in params {
 					column.add( TableAttr{
-							text: decode_from_md( td.trim() ),
-							align: align,
-							header: false,
+							text: decode_from_md( td.trim(), self.span.at( line, td.trim() ) ),
+							align: ETableAlign::DEFAULT,
+							header: None,
+							colspan: 1,
+							rowspan: 1,
 						} );
 				}
 				self.table_column.push( column )
 			}
 		}else{
 			self.table_block= false;
-			return	Some( Box::new( TABLEElement{
-							etype:	ElementType::TABLE,
-							data:	self.table_column.to_vec(),
-						}));
+			let	span= self.table_column.first().map_or( self.span, |column| column.span );
+			let	mut	table= TABLEElement::new( std::mem::take( &mut self.table_column ), span );
+			table.align= std::mem::take( &mut self.table_align );
+			return	Some( Block::Table( table ) );
 		}
 		return	None;
 	}
@@ -387,21 +370,21 @@ impl HTagGen {
 	fn	new() -> Self
 	{
 		HTagGen{
-			pat: regex::Regex::new( r"^(#+)\s+(.*)$" ).unwrap(),
+			pat: regex::Regex::new( r"^(#{1,6})\s+(.*)$" ).unwrap(),
 		}
 	}
 }
 
 impl ElementGenerator for HTagGen {
-	fn	generate( &self, line: &str, _context: &mut GenerateorContext ) -> Option<Box<dyn Element>>
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
 	{
 		let	pat_result= self.pat.captures( line );
 		if let Some(v)= pat_result {
 			//println!( "{} {} {}", &v[1], &v[2], v[1].len() );
-			return	Some( Box::new( HTagElement{
-							etype:	ElementType::HTAG,
-							title: 	v[2].to_string(),
+			return	Some( Block::HTag( HTagElement{
+							title: 	decode_from_md( &v[2], context.span.at( line, &v[2] ) ),
 							level:	v[1].len() as u32,
+							span:	context.span,
 						}));
 		}
 		return	None;
@@ -411,47 +394,73 @@ impl ElementGenerator for HTagGen {
 
 //-----------------------------------------------------------------------------
 
+struct ListMark {
+	indent	: usize,	// position of the mark
+	content	: usize,	// position of the item text
+	ordered	: bool,
+	number	: u32,
+}
+
+fn	match_list_item( line: &str ) -> Option<ListMark>
+{
+	lazy_static! {
+		static ref	PAT_ITEM: regex::Regex= regex::Regex::new( r"^(\s*)([-+*]|([0-9]+)\.)(\s+|$)" ).unwrap();
+	}
+	let	v= PAT_ITEM.captures( line )?;
+	let	number= v.get( 3 ).map_or( 1, |n| n.as_str().parse().unwrap_or( 1 ) );
+	return	Some( ListMark{
+					indent:		v[1].len(),
+					content:	v[0].len(),
+					ordered:	v.get( 3 ).is_some(),
+					number:		number,
+				} );
+}
+
 struct LITagGen {
-	pat_ul : regex::Regex,
-	pat_ol : regex::Regex,
 }
 
 impl LITagGen {
 	fn	new() -> Self
 	{
 		LITagGen{
-			pat_ul: regex::Regex::new( r"^(\s*)[-+*]\s+(.*)$" ).unwrap(),
-			pat_ol: regex::Regex::new( r"^(\s*)[0-9]+\.\s+(.*)$" ).unwrap(),
 		}
 	}
 }
 
 impl ElementGenerator for LITagGen {
-	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Box<dyn Element>>
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
 	{
-		let	pat_result= self.pat_ul.captures( line );
-		if let Some(v)= pat_result {
-			//println!( "ul {} {} {}", &v[2], &v[1], v[1].len() );
-			let	indent= v[1].len() as u32;
-			let nest= context.indent_to_nest( indent );
-			return	Some( Box::new( LITagElement{
-							etype:	ElementType::ULTAG,
-							text: 	decode_from_md( &v[2] ),
-							indent:	indent,
-							nest:	nest,
-						}));
+		if match_list_item( line ).is_some() {
+			context.list_block= true;
+			context.list_lines= vec![SourceLine::new( line, context.span )];
+			return	Some( Block::None );
 		}
-		let	pat_result= self.pat_ol.captures( line );
-		if let Some(v)= pat_result {
-			//println!( "ol {} {} {}", &v[2], &v[1], v[1].len() );
-			let	indent= v[1].len() as u32;
-			let nest= context.indent_to_nest( indent );
-			return	Some( Box::new( LITagElement{
-							etype:	ElementType::OLTAG,
-							text: 	decode_from_md( &v[2] ),
-							indent:	indent,
-							nest:	nest,
-						}));
+		return	None;
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+struct QuoteGen {
+}
+
+impl QuoteGen {
+	fn	new() -> Self
+	{
+		QuoteGen{
+		}
+	}
+}
+
+impl ElementGenerator for QuoteGen {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		if let Some(text)= strip_quote_mark( line ) {
+			context.quote_block= true;
+			context.quote_lines= vec![SourceLine::new( text, context.span.at( line, text ) )];
+			context.quote_span= context.span;
+			return	Some( Block::None );
 		}
 		return	None;
 	}
@@ -474,15 +483,16 @@ impl PRETagGen {
 }
 
 impl ElementGenerator for PRETagGen {
-	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Box<dyn Element>>
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
 	{
 		let	pat_result= self.pat.captures( line );
 		if let Some(v)= pat_result {
 			//println!( "pre {}", &v[1] );
 			context.pre_block= true;
 			context.pre_code= v[1].to_string();
+			context.pre_span= context.span;
 			context.pre_data= String::new();
-			return	Some( Box::new( NONEElement{} ));
+			return	Some( Block::None );
 		}
 		return	None;
 	}
@@ -505,16 +515,17 @@ impl TABLEGen {
 }
 
 impl ElementGenerator for TABLEGen {
-	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Box<dyn Element>>
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
 	{
 		let	pat_result= self.pat.captures( line );
 		if let Some(_)= pat_result {
 			context.table_block= true;
 			context.table_column= Vec::new();
+			context.table_align= Vec::new();
 			if let Some(e)= context.add_table_block( line ) {
 				return	Some(e);
 			}
-			return	Some( Box::new( NONEElement{} ));
+			return	Some( Block::None );
 		}
 		return	None;
 	}
@@ -537,11 +548,11 @@ impl HRTagGen {
 }
 
 impl ElementGenerator for HRTagGen {
-	fn	generate( &self, line: &str, _context: &mut GenerateorContext ) -> Option<Box<dyn Element>>
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
 	{
 		let	pat_result= self.pat.captures( line );
 		if let Some(_)= pat_result {
-			return	Some( Box::new( HRTagElement{} ) );
+			return	Some( Block::HRTag( context.span ) );
 		}
 		return	None;
 	}
@@ -562,12 +573,13 @@ impl Decoder {
 			Box::new( HRTagGen::new() ),
 			Box::new( HTagGen::new() ),
 			Box::new( LITagGen::new() ),
+			Box::new( QuoteGen::new() ),
 			Box::new( PRETagGen::new() ),
 			Box::new( TABLEGen::new() ),
 		];
 		Decoder{ gen_table: gen_table }
 	}
-	fn	find( &self, line: &str, context: &mut GenerateorContext ) -> Option<Box<dyn Element>>
+	fn	find( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
 	{
 		for gen in &self.gen_table {
 			let	element= gen.generate( line, context );
@@ -577,41 +589,97 @@ impl Decoder {
 		}
 		return	None;
 	}
-	pub fn	load( &self, file_name: &str ) -> io::Result<Document>
+	fn	build_list( &self, lines: &[SourceLine] ) -> Vec<Block>
+	{
+		let	mut	blocks= Vec::new();
+		let	mut	list: Option<ListElement>= None;
+		let	mut	item_lines: Vec<SourceLine>= Vec::new();
+		let	mut	item_span= Span::default();
+		let	mut	item_indent= 0;
+		let	mut	content_indent= 0;
+		for source in lines {
+			let	line= &source.text;
+			match match_list_item( line ) {
+				Some(mark) if list.is_none() || mark.indent <= item_indent => {
+					let	mark_span= source.span.at( line, &line[mark.indent..] );
+					if let Some(l)= &mut list {
+						l.items.push( ListItem{ blocks: self.decode_lines( &item_lines ).element_list, span: item_span } );
+						if l.ordered != mark.ordered {
+							blocks.extend( list.take().map( Block::List ) );
+						}
+					}
+					if list.is_none() {
+						list= Some( ListElement::new( mark.ordered, mark.number, mark_span ) );
+					}
+					item_span= mark_span;
+					item_indent= mark.indent;
+					content_indent= mark.content;
+					item_lines= vec![source.slice( &line[mark.content..] )];
+				},
+				_ => {
+					let	indent= line.len() - line.trim_start_matches( [' ', '\t'] ).len();
+					item_lines.push( source.slice( &line[indent.min( content_indent )..] ) );
+				},
+			}
+		}
+		if let Some(mut l)= list {
+			l.items.push( ListItem{ blocks: self.decode_lines( &item_lines ).element_list, span: item_span } );
+			blocks.push( Block::List( l ) );
+		}
+		return	blocks;
+	}
+	fn	build_quote( &self, lines: &[SourceLine], span: Span ) -> Block
 	{
-		let	file= fs::File::open( file_name )?;
-		let	reader= io::BufReader::new( file );
+		Block::Quote( BlockQuoteElement{ blocks: self.decode_lines( lines ).element_list, span: span } )
+	}
+}
 
+impl TextDecoder for Decoder {
+	fn	decode_lines( &self, lines: &[SourceLine] ) -> Document
+	{
 		let	mut	page= Document::new();
 		let	mut	context= GenerateorContext::new();
 
-		for rline in reader.lines() {
-			match rline {
-				Ok(line) => {
-					//let	line= decode_from_md( &line0 );
-					if context.is_pre_block() {
-						if let Some(e)= context.add_pre_block( &line ) {
-							page.push( e );
-						}
-						continue;
-					}else if context.is_table_block() {
-						if let Some(e)= context.add_table_block( &line ) {
-							page.push( e );
-						}else{
-							continue;
-						}
+		for source in lines {
+			let	line= &source.text;
+			context.span= source.span;
+			if context.is_pre_block() {
+				if let Some(e)= context.add_pre_block( line ) {
+					page.push( e );
+				}
+				continue;
+			}else if context.is_table_block() {
+				if let Some(e)= context.add_table_block( line ) {
+					page.push( e );
+				}else{
+					continue;
+				}
+			}else if context.is_list_block() {
+				if context.add_list_block( line ) {
+					continue;
+				}
+				for e in self.build_list( &std::mem::take( &mut context.list_lines ) ) {
+					page.push( e );
+				}
+			}else if context.is_quote_block() {
+				if context.add_quote_block( line ) {
+					continue;
+				}
+				page.push( self.build_quote( &std::mem::take( &mut context.quote_lines ), context.quote_span ) );
+			}
+			{
+				if let Some(e)= self.find( line, &mut context ) {
+					if let Some(p)= context.para.finish() {
+						page.push( p );
 					}
-					{
-						if let Some(e)= self.find( &line, &mut context ) {
-							page.push( e );
-						}else{
-							page.push( Box::new(DataElement{ text: decode_from_md( &line ) }) );
-						}
+					page.push( e );
+				}else if line.trim().is_empty() {
+					if let Some(p)= context.para.finish() {
+						page.push( p );
 					}
-				},
-				Err(e) => {
-					return	Err(e);
-				},
+				}else{
+					context.para.add_line( decode_md_line( line, source.span ) );
+				}
 			}
 		}
 		if context.is_pre_block() {
@@ -622,8 +690,17 @@ impl Decoder {
 			if let Some(e)= context.add_table_block( "" ) {
 				page.push( e );
 			}
+		}else if context.is_list_block() {
+			for e in self.build_list( &context.list_lines ) {
+				page.push( e );
+			}
+		}else if context.is_quote_block() {
+			page.push( self.build_quote( &context.quote_lines, context.quote_span ) );
+		}
+		if let Some(p)= context.para.finish() {
+			page.push( p );
 		}
-		Ok(page)
+		page
 	}
 }
 
@@ -631,46 +708,78 @@ impl Decoder {
 //=============================================================================
 
 trait	EncodeElement {
-	fn	output( &self ) -> String;
+	fn	output( &self, encoder: &Encoder ) -> String;
 }
 
 
 //-----------------------------------------------------------------------------
 
-impl	EncodeElement for NONEElement {
-	fn	output( &self ) -> String
+impl	EncodeElement for HTagElement {
+	fn	output( &self, _encoder: &Encoder ) -> String
 	{
-		return	String::new();
+		let	tag= '#'.to_string().repeat( self.level.clamp( 1, 6 ) as usize );
+		return	format!( "{} {}\n", tag, encode_to_md( &self.title ) );
 	}
 }
 
 
 //-----------------------------------------------------------------------------
 
-impl	EncodeElement for HTagElement {
-	fn	output( &self ) -> String
+impl	EncodeElement for TOCElement {
+	fn	output( &self, _encoder: &Encoder ) -> String
+	{
+		let	top= self.entries.iter().map( |(level,_)| *level ).min().unwrap_or( 1 );
+		let	mut	used= HashMap::new();
+		let	mut	buffer= String::new();
+		for (level,title) in &self.entries {
+			let	text= plain_text( title );
+			let	indent= "  ".repeat( (*level - top) as usize );
+			buffer+= &format!( "{}- [{}](#{})\n", indent, escape_md( &text ), anchor( &text, &mut used ) );
+		}
+		return	buffer;
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+impl	EncodeElement for ListElement {
+	fn	output( &self, encoder: &Encoder ) -> String
 	{
-		let	tag= '#'.to_string().repeat( self.level as usize );
-		return	format!( "{} {}\n", tag, self.title );
+		let	mut	buffer= String::new();
+		for (i,item) in self.items.iter().enumerate() {
+			let	mark= if self.ordered { format!( "{}.", self.start + i as u32 ) }else{ "-".to_string() };
+			let	spaces= ' '.to_string().repeat( mark.len() + 1 );
+			let	mut	text= String::new();
+			for (bi,block) in item.blocks.iter().enumerate() {
+				if bi != 0 && !matches!( block, Block::List(_) ) {
+					text+= "\n";
+				}
+				text+= &encoder.encode_single( block );
+			}
+			if text.is_empty() {
+				buffer+= &format!( "{}\n", mark );
+			}
+			for (li,line) in text.lines().enumerate() {
+				if li == 0 {
+					buffer+= &format!( "{} {}\n", mark, line );
+				}else if line.is_empty() {
+					buffer+= "\n";
+				}else{
+					buffer+= &format!( "{}{}\n", spaces, line );
+				}
+			}
+		}
+		return	buffer;
 	}
 }
 
 
 //-----------------------------------------------------------------------------
 
-impl	EncodeElement for LITagElement {
-	fn	output( &self ) -> String
+impl	EncodeElement for BlockQuoteElement {
+	fn	output( &self, encoder: &Encoder ) -> String
 	{
-		let	mut	spaces= "".to_string();
-		let	indent= (self.nest) * 2;
-		if indent > 0 {
-			spaces= ' '.to_string().repeat( indent as usize );
-		}
-		if self.etype == ElementType::ULTAG {
-			return	format!( "{}- {}\n", spaces, encode_to_md( &self.text ) );
-		}else{
-			return	format!( "{}1. {}\n", spaces, encode_to_md( &self.text ) );
-		}
+		return	prefix_quote_lines( &encoder.encode_blocks( &self.blocks ), ">" );
 	}
 }
 
@@ -678,7 +787,7 @@ impl	EncodeElement for LITagElement {
 //-----------------------------------------------------------------------------
 
 impl	EncodeElement for PRETagElement {
-	fn	output( &self ) -> String
+	fn	output( &self, _encoder: &Encoder ) -> String
 	{
 		return	format!( "```{}\n{}```\n", self.code, &self.text );
 	}
@@ -688,37 +797,61 @@ impl	EncodeElement for PRETagElement {
 //-----------------------------------------------------------------------------
 
 impl	EncodeElement for TABLEElement {
-	fn	output( &self ) -> String
+	fn	output( &self, _encoder: &Encoder ) -> String
 	{
+		if self.has_span() {
+			return	self.flatten().output( _encoder );
+		}
+		// Markdown has only the first header row and the column alignment,
+		// the other header cells are written in bold.
+		let	grid= self.grid();
+		let	width= grid.iter().map( |line| line.len() ).max().unwrap_or( 0 );
 		let	mut	buffer= String::new();
-		for column in &self.data {
+		let	mut	body= 0;
+		if self.is_header_row( 0 ) {
 			buffer+= "|";
-			let mut	bheader= false;
-			for attr in &column.data {
-				buffer+= &format!( " {} |", encode_to_md( &attr.text ) );
-				bheader= attr.header;
+			for cell in &grid[0] {
+				if let TableGrid::Cell(attr)= cell {
+					buffer+= &format!( " {} |", encode_to_md( &attr.text ) );
+				}
 			}
 			buffer+= "\n";
-			if bheader {
-				buffer+= "|";
-				for attr in &column.data {
-					match attr.align {
-						ETableAlign::CENTER => {
-							buffer+= ":----:|";
-						},
-						ETableAlign::LEFT => {
-							buffer+= ":----|";
-						},
-						ETableAlign::RIGHT => {
-							buffer+= "----:|";
-						},
-						_ => {
-							buffer+= "-----|";
-						},
+			body= 1;
+		}else{
+			buffer+= &"|  ".repeat( width );
+			buffer+= "|\n";
+		}
+		buffer+= "|";
+		for col in 0..width {
+			match self.column_align( col ) {
+				ETableAlign::CENTER => {
+					buffer+= ":----:|";
+				},
+				ETableAlign::LEFT => {
+					buffer+= ":----|";
+				},
+				ETableAlign::RIGHT => {
+					buffer+= "----:|";
+				},
+				_ => {
+					buffer+= "-----|";
+				},
+			}
+		}
+		buffer+= "\n";
+		for (row,line) in grid.iter().enumerate().skip( body ) {
+			buffer+= "|";
+			for (col,cell) in line.iter().enumerate() {
+				if let TableGrid::Cell(attr)= cell {
+					let	text= encode_to_md( &attr.text );
+					if self.cell_header( row, col, attr ) && !text.is_empty() {
+						buffer+= &format!( " **{}** |", text );
+					}else{
+						buffer+= &format!( " {} |", text );
 					}
 				}
-				buffer+= "\n";
 			}
+			buffer+= "\n";
 		}
 		return	buffer;
 	}
@@ -727,20 +860,42 @@ impl	EncodeElement for TABLEElement {
 
 //-----------------------------------------------------------------------------
 
-impl	EncodeElement for HRTagElement {
-	fn	output( &self ) -> String
+impl	EncodeElement for ParagraphElement {
+	fn	output( &self, encoder: &Encoder ) -> String
 	{
-		return	"----\n".to_string();
+		render_paragraph( &self.text, encoder.paragraph, encode_to_md ) + "\n"
 	}
 }
 
 
 //-----------------------------------------------------------------------------
 
-impl	EncodeElement for DataElement {
-	fn	output( &self ) -> String
-	{
-		encode_to_md( &self.text ) + "\n"
+// The same rules as TABLEElement::output(), one warning for each kind of loss.
+fn	check_table( table: &TABLEElement, warnings: &mut Vec<Warning> )
+{
+	if table.has_span() {
+		warnings.push( Warning::new( LossKind::Degraded, "table span", "expanded to empty cells", table.span ) );
+	}
+	let	body= if table.is_header_row( 0 ) { 1 } else { 0 };
+	let	mut	header= None;
+	let	mut	align= None;
+	for (row,line) in table.grid().iter().enumerate() {
+		for (col,cell) in line.iter().enumerate() {
+			let	TableGrid::Cell(attr)= cell else { continue };
+			let	span= table.data[row].span;
+			if row >= body && table.cell_header( row, col, attr ) && !attr.text.is_empty() {
+				header.get_or_insert( span );
+			}
+			if table.cell_align( row, col, attr ) != table.column_align( col ) {
+				align.get_or_insert( span );
+			}
+		}
+	}
+	if let Some(span)= header {
+		warnings.push( Warning::new( LossKind::Degraded, "header cell", "written in bold out of the first row", span ) );
+	}
+	if let Some(span)= align {
+		warnings.push( Warning::new( LossKind::Dropped, "cell alignment", "markdown has only the column alignment", span ) );
 	}
 }
 
@@ -748,41 +903,46 @@ impl	EncodeElement for DataElement {
 //-----------------------------------------------------------------------------
 
 pub struct	Encoder {
+	pub paragraph	: ParagraphMode,
 }
 
 impl	Encoder {
 	pub	fn	new() -> Self
 	{
-		Self{}
+		Self{
+			paragraph: ParagraphMode::Keep,
+		}
 	}
 }
 
 impl	TextEncoder for Encoder {
-	fn	encode_single( &self, element: &Box<dyn Element> ) -> String
+	fn	encode_single( &self, element: &Block ) -> String
 	{
-		let	any= element.as_any();
-		if let Some(e)= any.downcast_ref::<NONEElement>() {
-			return	e.output();
-		}
-		if let Some(e)= any.downcast_ref::<HTagElement>() {
-			return	e.output();
-		}
-		if let Some(e)= any.downcast_ref::<LITagElement>() {
-			return	e.output();
-		}
-		if let Some(e)= any.downcast_ref::<PRETagElement>() {
-			return	e.output();
-		}
-		if let Some(e)= any.downcast_ref::<TABLEElement>() {
-			return	e.output();
-		}
-		if let Some(e)= any.downcast_ref::<HRTagElement>() {
-			return	e.output();
+		match element {
+			Block::None => String::new(),
+			Block::Paragraph(e) => e.output( self ),
+			Block::HTag(e) => e.output( self ),
+			Block::List(e) => e.output( self ),
+			Block::PRETag(e) => e.output( self ),
+			Block::Table(e) => e.output( self ),
+			Block::Quote(e) => e.output( self ),
+			Block::HRTag(_) => "----\n".to_string(),
+			Block::TOC(e) => e.output( self ),
+		}
+	}
+	fn	check_block( &self, element: &Block, warnings: &mut Vec<Warning> )
+	{
+		if let Block::Table(e)= element {
+			check_table( e, warnings );
 		}
-		if let Some(e)= any.downcast_ref::<DataElement>() {
-			return	e.output();
+	}
+	fn	check_inline( &self, node: &Inline, warnings: &mut Vec<Warning> )
+	{
+		if let InlineNode::Image(image)= &node.node {
+			if image.width.is_some() || image.height.is_some() {
+				warnings.push( Warning::new( LossKind::Dropped, "image size", "markdown images have no size", node.span ) );
+			}
 		}
-		return	String::new();
 	}
 }
 
@@ -0,0 +1,1151 @@
+// 2022 Hiroyuki Ogasawara
+// vim:ts=4 sw=4 noet:
+
+use regex;
+use	lazy_static::lazy_static;
+
+use super::span::*;
+use super::inline::*;
+use super::element::*;
+use super::document::*;
+use super::warning::*;
+
+
+//=============================================================================
+
+fn	is_external( url: &str ) -> bool
+{
+	lazy_static! {
+		static ref	SCHEME: regex::Regex= regex::Regex::new( r"^([a-zA-Z][a-zA-Z0-9+.-]*://|mailto:)" ).unwrap();
+	}
+	SCHEME.is_match( url )
+}
+
+// "[[File:a.png|thumb|200px|alt=text|caption]]", the caption is the title.
+fn	file_image( params: &str ) -> Image
+{
+	let	mut	list= params.split( '|' );
+	let	mut	image= Image::new( list.next().unwrap_or( "" ).trim() );
+	for param in list {
+		let	param= param.trim();
+		if param.strip_suffix( "px" ).is_some_and( |size| image.set_size( size ) ) {
+			continue;
+		}else if let Some(alt)= param.strip_prefix( "alt=" ) {
+			image.alt= alt.trim().to_string();
+		}else if param.contains( '=' ) || ["thumb", "thumbnail", "frame", "frameless", "border", "left", "right", "center", "none", "upright", "baseline", "middle", "top", "bottom"].contains( &param ) {
+			continue;
+		}else{
+			image.title= param.to_string();
+		}
+	}
+	return	image;
+}
+
+fn	media_special( parser: &InlineParser, text: &str, _prev: Option<char>, at: Span ) -> Option<(Vec<Inline>,usize)>
+{
+	lazy_static! {
+		static ref	ENTITY: regex::Regex= regex::Regex::new( r"^&(#\d+|#x[0-9a-fA-F]+|lt|gt|amp|quot|nbsp);" ).unwrap();
+		static ref	BREAK: regex::Regex= regex::Regex::new( r"^<br\s*/?>" ).unwrap();
+		static ref	FILE: regex::Regex= regex::Regex::new( r"^\[\[\s*(?:File|Image):" ).unwrap();
+	}
+	if text.starts_with( "<nowiki/>" ) {
+		return	Some( (Vec::new(), 9) );
+	}
+	if let Some(body)= text.strip_prefix( "<code><nowiki>" ) {
+		let	epos= body.find( "</nowiki></code>" )?;
+		return	Some( (vec![Inline::new( InlineNode::Code( body[..epos].to_string() ) )], epos + 30) );
+	}
+	if let Some(body)= text.strip_prefix( "<nowiki>" ) {
+		let	epos= body.find( "</nowiki>" )?;
+		return	Some( (vec![Inline::new( InlineNode::Text( body[..epos].to_string() ) )], epos + 17) );
+	}
+	if let Some(m)= BREAK.find( text ) {
+		return	Some( (vec![Inline::new( InlineNode::LineBreak )], m.end()) );
+	}
+	if let Some(v)= ENTITY.captures( text ) {
+		let	ch= match &v[1] {
+			"lt" => '<',
+			"gt" => '>',
+			"amp" => '&',
+			"quot" => '"',
+			"nbsp" => ' ',
+			num => {
+				let	code= match num.strip_prefix( "#x" ) {
+					Some(hex) => u32::from_str_radix( hex, 16 ).ok(),
+					None => num[1..].parse().ok(),
+				};
+				code.and_then( char::from_u32 )?
+			},
+		};
+		return	Some( (vec![Inline::new( InlineNode::Text( ch.to_string() ) )], v[0].len()) );
+	}
+	if let Some(m)= FILE.find( text ) {
+		let	epos= text.find( "]]" )?;
+		let	image= file_image( &text[m.end()..epos] );
+		return	Some( (vec![Inline::new( InlineNode::Image( image ) )], epos + 2) );
+	}
+	if let Some(body)= text.strip_prefix( "[[" ) {
+		let	epos= body.find( "]]" )?;
+		let	inner= &body[..epos];
+		let	link= match inner.split_once( '|' ) {
+			Some((url,label)) => InlineNode::Link{ url: url.trim().to_string(), text: parser.parse_at( label, at.at( text, label ) ) },
+			None => InlineNode::Link{ url: inner.trim().to_string(), text: Vec::new() },
+		};
+		return	Some( (vec![Inline::new( link )], epos + 4) );
+	}
+	// "[https://example.com text]", the url without the brackets is the plain text.
+	if let Some(body)= text.strip_prefix( '[' ) {
+		if !is_external( body ) {
+			return	None;
+		}
+		let	epos= body.find( ']' )?;
+		let	inner= &body[..epos];
+		let	link= match inner.split_once( char::is_whitespace ) {
+			Some((url,label)) => InlineNode::Link{ url: url.to_string(), text: parser.parse_at( label.trim(), at.at( text, label.trim() ) ) },
+			None => InlineNode::Link{ url: inner.to_string(), text: Vec::new() },
+		};
+		return	Some( (vec![Inline::new( link )], epos + 2) );
+	}
+	return	None;
+}
+
+pub fn	decode_from_media( line: &str, span: Span ) -> Vec<Inline>
+{
+	lazy_static! {
+		static ref	PARSER: InlineParser= InlineParser{
+				delimiters: vec![
+					Delimiter{ open: "'''''",		close: "'''''",		kind: InlineKind::StrongEmphasis,	word: false, },
+					Delimiter{ open: "'''",			close: "'''",		kind: InlineKind::Strong,	word: false, },
+					Delimiter{ open: "''",			close: "''",		kind: InlineKind::Emphasis,	word: false, },
+					Delimiter{ open: "<b>",			close: "</b>",		kind: InlineKind::Strong,	word: false, },
+					Delimiter{ open: "<strong>",	close: "</strong>",	kind: InlineKind::Strong,	word: false, },
+					Delimiter{ open: "<i>",			close: "</i>",		kind: InlineKind::Emphasis,	word: false, },
+					Delimiter{ open: "<em>",		close: "</em>",		kind: InlineKind::Emphasis,	word: false, },
+					Delimiter{ open: "<s>",			close: "</s>",		kind: InlineKind::Strike,	word: false, },
+					Delimiter{ open: "<del>",		close: "</del>",	kind: InlineKind::Strike,	word: false, },
+					Delimiter{ open: "<strike>",	close: "</strike>",	kind: InlineKind::Strike,	word: false, },
+					Delimiter{ open: "<code>",		close: "</code>",	kind: InlineKind::Code,		word: false, },
+					Delimiter{ open: "<tt>",		close: "</tt>",		kind: InlineKind::Code,		word: false, },
+				],
+				tight: false,
+				special: media_special,
+			};
+	}
+	return	PARSER.parse_at( line, span );
+}
+
+fn	decode_media_line( line: &str, span: Span ) -> Vec<Inline>
+{
+	let	trimmed= line.trim();
+	return	decode_from_media( trimmed, span.at( line, trimmed ) );
+}
+
+
+// The text with the marks of MediaWiki is written in <nowiki>.
+fn	escape_media( text: &str ) -> String
+{
+	if ["''", "[", "]", "{{", "<", "~~~", "__"].iter().any( |mark| text.contains( mark ) ) {
+		return	format!( "<nowiki>{}</nowiki>", text.replace( "</nowiki>", "&lt;/nowiki>" ) );
+	}
+	return	text.to_string();
+}
+
+pub	fn	encode_to_media( list: &[Inline] ) -> String
+{
+	let	mut	buffer= String::new();
+	for node in list {
+		match &node.node {
+			InlineNode::Text(text) => {
+				buffer+= &escape_media( text );
+			},
+			InlineNode::Strong(child) => {
+				buffer+= &format!( "'''{}'''", encode_to_media( child ) );
+			},
+			InlineNode::Emphasis(child) => {
+				buffer+= &format!( "''{}''", encode_to_media( child ) );
+			},
+			InlineNode::Strike(child) => {
+				buffer+= &format!( "<s>{}</s>", encode_to_media( child ) );
+			},
+			InlineNode::Code(text) => {
+				if escape_media( text ) != *text || text.contains( '&' ) {
+					buffer+= &format!( "<code><nowiki>{}</nowiki></code>", text );
+				}else{
+					buffer+= &format!( "<code>{}</code>", text );
+				}
+			},
+			InlineNode::Link{ url, text } => {
+				if is_external( url ) {
+					if text.is_empty() {
+						buffer+= &format!( "[{}]", url );
+					}else{
+						buffer+= &format!( "[{} {}]", url, encode_to_media( text ) );
+					}
+				}else if text.is_empty() {
+					buffer+= &format!( "[[{}]]", url );
+				}else{
+					buffer+= &format!( "[[{}|{}]]", url, encode_to_media( text ) );
+				}
+			},
+			InlineNode::Image(image) => {
+				// The external image is shown by its bare url on the wiki allowing it.
+				if is_external( &image.src ) {
+					buffer+= &image.src;
+					continue;
+				}
+				let	mut	params= vec![format!( "File:{}", image.src )];
+				if image.width.is_some() || image.height.is_some() {
+					params.push( format!( "{}px", image.size_text() ) );
+				}
+				if !image.alt.is_empty() {
+					params.push( format!( "alt={}", image.alt ) );
+				}
+				if !image.title.is_empty() {
+					params.push( image.title.clone() );
+				}
+				buffer+= &format!( "[[{}]]", params.join( "|" ) );
+			},
+			InlineNode::SoftBreak => {
+				buffer+= "\n";
+			},
+			InlineNode::LineBreak => {
+				buffer+= "<br />\n";
+			},
+		}
+	}
+	return	buffer;
+}
+
+
+//=============================================================================
+
+
+//-----------------------------------------------------------------------------
+
+trait ElementGenerator {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>;
+}
+
+// The cell before the end of the row, the lines after "|" are the text of the cell.
+struct TableCell {
+	attr	: TableAttr,
+	colspan	: u32,
+	rowspan	: u32,
+	text	: String,
+	span	: Span,
+}
+
+struct GenerateorContext {
+	// current line
+	span: Span,
+	// paragraph
+	para: ParagraphElement,
+	// list
+	list_block: bool,
+	list_entries: Vec<ListEntry>,
+	list_pat: regex::Regex,
+	// quote
+	quote_block: bool,
+	quote_tag: bool,
+	quote_depth: usize,
+	quote_closed: bool,
+	quote_lines: Vec<SourceLine>,
+	quote_span: Span,
+	// pre
+	pre_block: bool,
+	pre_indent: bool,
+	pre_end: String,
+	pre_span: Span,
+	pre_code: String,
+	pre_data: String,
+	// table
+	table_block: bool,
+	table_depth: usize,
+	table_grid: Vec<(Span, Vec<TableGrid<TableAttr>>)>,
+	table_row: Vec<TableCell>,
+	table_row_span: Span,
+	table_rowspan: Vec<u32>,
+}
+
+impl GenerateorContext {
+	fn	new() -> Self
+	{
+		GenerateorContext{
+				span: Span::default(),
+				para: ParagraphElement::new(),
+				list_block: false,
+				list_entries: Vec::new(),
+				list_pat: regex::Regex::new( r"^([*#]+)(:)?\s*(.*)$" ).unwrap(),
+				quote_block: false,
+				quote_tag: false,
+				quote_depth: 0,
+				quote_closed: false,
+				quote_lines: Vec::new(),
+				quote_span: Span::default(),
+				pre_block: false,
+				pre_indent: false,
+				pre_end: String::new(),
+				pre_span: Span::default(),
+				pre_code: String::new(),
+				pre_data: String::new(),
+				table_block: false,
+				table_depth: 0,
+				table_grid: Vec::new(),
+				table_row: Vec::new(),
+				table_row_span: Span::default(),
+				table_rowspan: Vec::new(),
+			}
+	}
+	//-------------------------------------------------------------------------
+	fn	is_list_block( &self ) -> bool
+	{
+		self.list_block
+	}
+	fn	add_list_block( &mut self, line: &str ) -> Option<Vec<Block>>
+	{
+		let	pat_result= self.list_pat.captures( line );
+		if let Some(v)= pat_result {
+			// "#:" continues the text of the item in the next line
+			if let (Some(_),Some(entry))= (v.get( 2 ), self.list_entries.last_mut()) {
+				let	text= v[3].trim();
+				entry.text.push( Inline::at( InlineNode::LineBreak, self.span.at( line, &v[1] ) ) );
+				entry.text.extend( decode_from_media( text, self.span.at( line, text ) ) );
+				return	None;
+			}
+			self.list_entries.push( list_entry( &v, line, self.span ) );
+			return	None;
+		}
+		self.list_block= false;
+		return	Some( build_list_tree( &mut self.list_entries ) );
+	}
+	//-------------------------------------------------------------------------
+	fn	is_quote_block( &self ) -> bool
+	{
+		self.quote_block
+	}
+	// ':' quotes the line, <blockquote> quotes to its end tag, the inner ones are counted.
+	fn	add_quote_block( &mut self, line: &str ) -> bool
+	{
+		lazy_static! {
+			static ref	TAG: regex::Regex= regex::Regex::new( r"</?blockquote>" ).unwrap();
+		}
+		if !self.quote_tag {
+			if let Some(text)= line.strip_prefix( ':' ) {
+				let	text= text.trim_start();
+				self.quote_lines.push( SourceLine::new( text, self.span.at( line, text ) ) );
+				return	true;
+			}
+			self.quote_block= false;
+			return	false;
+		}
+		for m in TAG.find_iter( line ) {
+			if m.as_str().starts_with( "</" ) {
+				self.quote_depth-= 1;
+			}else{
+				self.quote_depth+= 1;
+			}
+			if self.quote_depth == 0 {
+				let	text= &line[..m.start()];
+				if !text.trim().is_empty() {
+					self.quote_lines.push( SourceLine::new( text, self.span ) );
+				}
+				self.quote_closed= true;
+				return	true;
+			}
+		}
+		self.quote_lines.push( SourceLine::new( line, self.span ) );
+		return	true;
+	}
+	fn	is_quote_closed( &self ) -> bool
+	{
+		self.quote_closed
+	}
+	//-------------------------------------------------------------------------
+	fn	is_pre_block( &self ) -> bool
+	{
+		self.pre_block
+	}
+	fn	finish_pre( &mut self ) -> Block
+	{
+		self.pre_block= false;
+		Block::PRETag( PRETagElement{
+				text: 	std::mem::take( &mut self.pre_data ),
+				code:	std::mem::take( &mut self.pre_code ),
+				span:	self.pre_span,
+			})
+	}
+	fn	add_pre_block( &mut self, line: &str ) -> Option<Block>
+	{
+		if let Some(pos)= line.find( self.pre_end.as_str() ) {
+			if pos != 0 {
+				self.pre_data+= &line[..pos];
+				self.pre_data+= "\n";
+			}
+			return	Some( self.finish_pre() );
+		}
+		self.pre_data+= line;
+		self.pre_data+= "\n";
+		return	None;
+	}
+	// The lines starting with a space, the other line is out of the block.
+	fn	add_indent_block( &mut self, line: &str ) -> Option<Block>
+	{
+		match line.strip_prefix( ' ' ) {
+			Some(text) if !line.trim().is_empty() => {
+				self.pre_data+= text;
+				self.pre_data+= "\n";
+				return	None;
+			},
+			_ => {
+				return	Some( self.finish_pre() );
+			},
+		}
+	}
+	//-------------------------------------------------------------------------
+	fn	is_table_block( &self ) -> bool
+	{
+		self.table_block
+	}
+	// The cells of the row go to the grid, rowspan puts the span marks under the cell.
+	fn	finish_row( &mut self )
+	{
+		if self.table_row.is_empty() {
+			return;
+		}
+		let	mut	cells= Vec::new();
+		for cell in std::mem::take( &mut self.table_row ) {
+			while self.table_rowspan.get( cells.len() ).is_some_and( |rows| *rows > 0 ) {
+				self.table_rowspan[cells.len()]-= 1;
+				cells.push( TableGrid::SpanUp );
+			}
+			let	text= cell.text.trim();
+			let	mut	attr= cell.attr;
+			attr.text= decode_from_media( text, cell.span );
+			let	col= cells.len();
+			cells.push( TableGrid::Cell( attr ) );
+			for _ in 1..cell.colspan {
+				cells.push( TableGrid::SpanLeft );
+			}
+			if cell.rowspan > 1 {
+				if self.table_rowspan.len() < cells.len() {
+					self.table_rowspan.resize( cells.len(), 0 );
+				}
+				for rows in &mut self.table_rowspan[col..cells.len()] {
+					*rows= cell.rowspan - 1;
+				}
+			}
+		}
+		while self.table_rowspan.get( cells.len() ).is_some_and( |rows| *rows > 0 ) {
+			self.table_rowspan[cells.len()]-= 1;
+			cells.push( TableGrid::SpanUp );
+		}
+		self.table_grid.push( (self.table_row_span, cells) );
+	}
+	fn	finish_table( &mut self ) -> Block
+	{
+		self.finish_row();
+		self.table_block= false;
+		self.table_rowspan.clear();
+		Block::Table( build_table( std::mem::take( &mut self.table_grid ) ) )
+	}
+	// "{|" to "|}", "|-" is the row, "!" the header cells, "|" the data cells.
+	fn	add_table_block( &mut self, line: &str ) -> Option<Block>
+	{
+		let	trimmed= line.trim_start();
+		if self.table_depth == 0 {
+			if trimmed.starts_with( "|}" ) {
+				return	Some( self.finish_table() );
+			}
+			if trimmed.starts_with( "|-" ) {
+				self.finish_row();
+				return	None;
+			}
+			if trimmed.starts_with( "|+" ) {
+				return	None;
+			}
+			if let Some(cells)= trimmed.strip_prefix( '!' ) {
+				self.add_cells( line, cells, true );
+				return	None;
+			}
+			if let Some(cells)= trimmed.strip_prefix( '|' ) {
+				self.add_cells( line, cells, false );
+				return	None;
+			}
+		}
+		// The other lines continue the text of the cell, the nested table is the text too.
+		if trimmed.starts_with( "{|" ) {
+			self.table_depth+= 1;
+		}else if trimmed.starts_with( "|}" ) {
+			self.table_depth= self.table_depth.saturating_sub( 1 );
+		}
+		if let Some(cell)= self.table_row.last_mut() {
+			cell.text+= " ";
+			cell.text+= line.trim();
+		}
+		return	None;
+	}
+	fn	add_cells( &mut self, line: &str, cells: &str, header: bool )
+	{
+		if self.table_row.is_empty() {
+			self.table_row_span= self.span;
+		}
+		let	separator= if header { "!!" } else { "||" };
+		for td in split_outside( cells, separator ) {
+			let	(attrs,text)= match split_outside( td, "|" ).as_slice() {
+					[attrs,text] if attrs.contains( '=' ) => (*attrs, *text),
+					_ => ("", td),
+				};
+			let	(align,colspan,rowspan)= cell_attrs( attrs );
+			self.table_row.push( TableCell{
+					attr:		TableAttr{ text: Vec::new(), align: align, header: Some(header), colspan: 1, rowspan: 1 },
+					colspan:	colspan,
+					rowspan:	rowspan,
+					text:		text.to_string(),
+					span:		self.span.at( line, text.trim_start() ),
+				});
+		}
+	}
+	//-------------------------------------------------------------------------
+}
+
+// Splits at the separator outside of "[[ ]]" and "{{ }}".
+fn	split_outside<'a>( text: &'a str, separator: &str ) -> Vec<&'a str>
+{
+	let	mut	list= Vec::new();
+	let	mut	depth= 0;
+	let	mut	start= 0;
+	let	mut	pos= 0;
+	while pos < text.len() {
+		let	rest= &text[pos..];
+		if rest.starts_with( "[[" ) || rest.starts_with( "{{" ) {
+			depth+= 1;
+			pos+= 2;
+		}else if rest.starts_with( "]]" ) || rest.starts_with( "}}" ) {
+			depth-= 1;
+			pos+= 2;
+		}else if depth <= 0 && rest.starts_with( separator ) && (separator != "|" || !rest.starts_with( "||" )) {
+			list.push( &text[start..pos] );
+			pos+= separator.len();
+			start= pos;
+		}else{
+			pos+= rest.chars().next().map_or( 1, |ch| ch.len_utf8() );
+		}
+	}
+	list.push( &text[start..] );
+	return	list;
+}
+
+// style="text-align:right", align="center", colspan="2" and rowspan="2".
+fn	cell_attrs( attrs: &str ) -> (ETableAlign,u32,u32)
+{
+	lazy_static! {
+		static ref	ATTR: regex::Regex= regex::Regex::new( r#"(\w+)\s*=\s*(?:"([^"]*)"|'([^']*)'|(\S+))"# ).unwrap();
+	}
+	let	mut	align= None;
+	let	mut	colspan= 1;
+	let	mut	rowspan= 1;
+	for v in ATTR.captures_iter( attrs ) {
+		let	value= v.get( 2 ).or( v.get( 3 ) ).or( v.get( 4 ) ).map_or( "", |m| m.as_str() );
+		match v[1].to_ascii_lowercase().as_str() {
+			"style" => {
+				for decl in value.split( ';' ) {
+					if let Some((key,value))= decl.split_once( ':' ) {
+						if key.trim().eq_ignore_ascii_case( "text-align" ) {
+							align= Some( value.trim().to_ascii_lowercase() );
+						}
+					}
+				}
+			},
+			"align" => align= align.or( Some( value.to_ascii_lowercase() ) ),
+			"colspan" => colspan= value.parse().unwrap_or( 1u32 ).max( 1 ),
+			"rowspan" => rowspan= value.parse().unwrap_or( 1u32 ).max( 1 ),
+			_ => {},
+		}
+	}
+	let	align= match align.as_deref() {
+			Some("left") => ETableAlign::LEFT,
+			Some("center") => ETableAlign::CENTER,
+			Some("right") => ETableAlign::RIGHT,
+			_ => ETableAlign::DEFAULT,
+		};
+	return	(align, colspan, rowspan);
+}
+
+//-----------------------------------------------------------------------------
+
+// "= h1 =" to "====== h6 ======", the level is the count of '='.
+struct HTagGen {
+	pat : regex::Regex,
+}
+
+impl HTagGen {
+	fn	new() -> Self
+	{
+		HTagGen{
+			pat: regex::Regex::new( r"^(={1,6})\s*(.*?)\s*(={1,6})\s*$" ).unwrap(),
+		}
+	}
+}
+
+impl ElementGenerator for HTagGen {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		let	pat_result= self.pat.captures( line );
+		if let Some(v)= pat_result {
+			let	level= v[1].len().min( v[3].len() );
+			// "=== a ==" is "== =a ==", the rest of the marks are the title.
+			let	head= &v[1][level..];
+			let	tail= &v[3][..v[3].len()-level];
+			let	title= &line[v.get( 2 ).unwrap().start()-head.len()..v.get( 2 ).unwrap().end()+tail.len()];
+			if title.trim().is_empty() {
+				return	None;
+			}
+			return	Some( Block::HTag( HTagElement{
+							title: 	decode_from_media( title, context.span.at( line, title ) ),
+							level:	level as u32,
+							span:	context.span,
+						}));
+		}
+		return	None;
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+// __TOC__ is the place of the toc, the headings are filled at the end of the page.
+struct TOCGen {
+	pat : regex::Regex,
+}
+
+impl TOCGen {
+	fn	new() -> Self
+	{
+		TOCGen{
+			pat: regex::Regex::new( r"^__TOC__\s*$" ).unwrap(),
+		}
+	}
+}
+
+impl ElementGenerator for TOCGen {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		if self.pat.is_match( line ) {
+			return	Some( Block::TOC( TOCElement{ entries: Vec::new(), span: context.span } ) );
+		}
+		return	None;
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+// "*" and "#" are the depth of the list, the last mark tells the ordered list.
+fn	list_entry( v: &regex::Captures, line: &str, span: Span ) -> ListEntry
+{
+	let	text= v[3].trim();
+	ListEntry{
+		depth:		v[1].len() as u32 - 1,
+		ordered:	v[1].ends_with( '#' ),
+		text:		decode_from_media( text, span.at( line, text ) ),
+		span:		span.at( line, &v[1] ),
+	}
+}
+
+struct LITagGen {
+	pat: regex::Regex,
+}
+
+impl LITagGen {
+	fn	new() -> Self
+	{
+		LITagGen{
+			pat: regex::Regex::new( r"^([*#]+)(:)?\s*(.*)$" ).unwrap(),
+		}
+	}
+}
+
+impl ElementGenerator for LITagGen {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		let	pat_result= self.pat.captures( line );
+		if let Some(v)= pat_result {
+			context.list_block= true;
+			context.list_entries= vec![list_entry( &v, line, context.span )];
+			return	Some( Block::None );
+		}
+		return	None;
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+// <blockquote> and the indent of ':', "::" nests the quote.
+struct QuoteGen {
+}
+
+impl QuoteGen {
+	fn	new() -> Self
+	{
+		QuoteGen{
+		}
+	}
+}
+
+impl ElementGenerator for QuoteGen {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		if let Some(rest)= line.trim_start().strip_prefix( "<blockquote>" ) {
+			context.quote_block= true;
+			context.quote_tag= true;
+			context.quote_depth= 1;
+			context.quote_closed= false;
+			context.quote_lines= Vec::new();
+			context.quote_span= context.span;
+			if !rest.trim().is_empty() {
+				context.add_quote_block( rest );
+			}
+			return	Some( Block::None );
+		}
+		if let Some(text)= line.strip_prefix( ':' ) {
+			let	text= text.trim_start();
+			context.quote_block= true;
+			context.quote_tag= false;
+			context.quote_closed= false;
+			context.quote_lines= vec![SourceLine::new( text, context.span.at( line, text ) )];
+			context.quote_span= context.span;
+			return	Some( Block::None );
+		}
+		return	None;
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+// <syntaxhighlight lang="rust">, <source lang="rust">, <pre> and the lines starting with a space.
+struct PRETagGen {
+	pat : regex::Regex,
+}
+
+impl PRETagGen {
+	fn	new() -> Self
+	{
+		PRETagGen{
+			pat: regex::Regex::new( r#"^\s*<(syntaxhighlight|source|pre)(?:\s[^>]*?lang\s*=\s*"?([\w+#.-]*)"?)?[^>]*>(.*)$"# ).unwrap(),
+		}
+	}
+}
+
+impl ElementGenerator for PRETagGen {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		let	pat_result= self.pat.captures( line );
+		if let Some(v)= pat_result {
+			context.pre_block= true;
+			context.pre_indent= false;
+			context.pre_end= format!( "</{}>", &v[1] );
+			context.pre_span= context.span;
+			context.pre_code= v.get( 2 ).map_or( String::new(), |m| m.as_str().to_string() );
+			context.pre_data= String::new();
+			let	rest= &v[3];
+			if !rest.is_empty() {
+				if let Some(e)= context.add_pre_block( rest ) {
+					return	Some(e);
+				}
+			}
+			return	Some( Block::None );
+		}
+		if let Some(text)= line.strip_prefix( ' ' ) {
+			if !text.trim().is_empty() {
+				context.pre_block= true;
+				context.pre_indent= true;
+				context.pre_span= context.span;
+				context.pre_code= String::new();
+				context.pre_data= format!( "{}\n", text );
+				return	Some( Block::None );
+			}
+		}
+		return	None;
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+struct TABLEGen {
+}
+
+impl TABLEGen {
+	fn	new() -> Self
+	{
+		TABLEGen{
+		}
+	}
+}
+
+impl ElementGenerator for TABLEGen {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		if line.trim_start().starts_with( "{|" ) {
+			context.table_block= true;
+			context.table_depth= 0;
+			context.table_grid= Vec::new();
+			context.table_row= Vec::new();
+			context.table_rowspan= Vec::new();
+			return	Some( Block::None );
+		}
+		return	None;
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+struct HRTagGen {
+	pat : regex::Regex,
+}
+
+impl HRTagGen {
+	fn	new() -> Self
+	{
+		HRTagGen{
+			pat: regex::Regex::new( r"^-{4,}\s*$" ).unwrap(),
+		}
+	}
+}
+
+impl ElementGenerator for HRTagGen {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		let	pat_result= self.pat.captures( line );
+		if let Some(_)= pat_result {
+			return	Some( Block::HRTag( context.span ) );
+		}
+		return	None;
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+pub struct Decoder {
+	gen_table: Vec<Box<dyn ElementGenerator>>,
+}
+
+impl Decoder {
+	pub fn new() -> Self
+	{
+		let	gen_table: Vec<Box<dyn ElementGenerator>>= vec![
+			Box::new( HRTagGen::new() ),
+			Box::new( HTagGen::new() ),
+			Box::new( TOCGen::new() ),
+			Box::new( LITagGen::new() ),
+			Box::new( QuoteGen::new() ),
+			Box::new( PRETagGen::new() ),
+			Box::new( TABLEGen::new() ),
+		];
+		Decoder{ gen_table: gen_table }
+	}
+	fn	find( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		for gen in &self.gen_table {
+			let	element= gen.generate( line, context );
+			if let Some(_)= element {
+				return	element;
+			}
+		}
+		return	None;
+	}
+	fn	build_quote( &self, context: &mut GenerateorContext ) -> Block
+	{
+		context.quote_block= false;
+		context.quote_closed= false;
+		Block::Quote( BlockQuoteElement{ blocks: self.decode_lines( &std::mem::take( &mut context.quote_lines ) ).element_list, span: context.quote_span } )
+	}
+}
+
+impl TextDecoder for Decoder {
+	fn	decode_lines( &self, lines: &[SourceLine] ) -> Document
+	{
+		let	mut	page= Document::new();
+		let	mut	context= GenerateorContext::new();
+
+		for source in lines {
+			let	line= &source.text;
+			context.span= source.span;
+			if context.is_pre_block() && context.pre_indent {
+				if let Some(e)= context.add_indent_block( line ) {
+					page.push( e );
+				}else{
+					continue;
+				}
+			}else if context.is_pre_block() {
+				if let Some(e)= context.add_pre_block( line ) {
+					page.push( e );
+				}
+				continue;
+			}else if context.is_table_block() {
+				if let Some(e)= context.add_table_block( line ) {
+					page.push( e );
+				}
+				continue;
+			}else if context.is_list_block() {
+				if let Some(list)= context.add_list_block( line ) {
+					for e in list {
+						page.push( e );
+					}
+				}else{
+					continue;
+				}
+			}else if context.is_quote_block() {
+				if context.add_quote_block( line ) {
+					if context.is_quote_closed() {
+						page.push( self.build_quote( &mut context ) );
+					}
+					continue;
+				}
+				page.push( self.build_quote( &mut context ) );
+			}
+			{
+				if let Some(e)= self.find( line, &mut context ) {
+					if let Some(p)= context.para.finish() {
+						page.push( p );
+					}
+					page.push( e );
+					if context.is_quote_closed() {
+						page.push( self.build_quote( &mut context ) );
+					}
+				}else if line.trim().is_empty() || line.trim().starts_with( "__" ) && line.trim().ends_with( "__" ) {
+					// __NOTOC__ and the other magic words are not the text.
+					if let Some(p)= context.para.finish() {
+						page.push( p );
+					}
+				}else{
+					context.para.add_line( decode_media_line( line, source.span ) );
+				}
+			}
+		}
+		if context.is_list_block() {
+			for e in build_list_tree( &mut context.list_entries ) {
+				page.push( e );
+			}
+		}else if context.is_quote_block() {
+			page.push( self.build_quote( &mut context ) );
+		}else if context.is_table_block() {
+			page.push( context.finish_table() );
+		}else if context.is_pre_block() {
+			page.push( context.finish_pre() );
+		}
+		if let Some(p)= context.para.finish() {
+			page.push( p );
+		}
+		fill_toc( &mut page.element_list );
+		page
+	}
+}
+
+
+//=============================================================================
+
+trait	EncodeElement {
+	fn	output( &self, encoder: &Encoder ) -> String;
+}
+
+
+//-----------------------------------------------------------------------------
+
+impl	EncodeElement for HTagElement {
+	fn	output( &self, _encoder: &Encoder ) -> String
+	{
+		let	tag= '='.to_string().repeat( self.level.clamp( 1, 6 ) as usize );
+		return	format!( "{} {} {}\n", tag, encode_to_media( &self.title ), tag );
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+impl	EncodeElement for TOCElement {
+	fn	output( &self, _encoder: &Encoder ) -> String
+	{
+		return	"__TOC__\n".to_string();
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+// A list item is a single line, the paragraphs are joined with <br />, the other blocks follow the item.
+fn	output_list( list: &ListElement, encoder: &Encoder, prefix: &str ) -> String
+{
+	let	mut	buffer= String::new();
+	let	mark= format!( "{}{}", prefix, if list.ordered { '#' } else { '*' } );
+	for item in &list.items {
+		let	mut	text= String::new();
+		let	mut	children= String::new();
+		for block in &item.blocks {
+			match block {
+				Block::Paragraph(e) => {
+					if !text.is_empty() {
+						text+= "<br />";
+					}
+					text+= &render_paragraph( &e.text, ParagraphMode::Join, encode_to_media ).replace( '\n', " " );
+				},
+				Block::List(e) => {
+					children+= &output_list( e, encoder, &mark );
+				},
+				_ => {
+					children+= &encoder.encode_single( block );
+				},
+			}
+		}
+		buffer+= &format!( "{} {}\n", mark, text );
+		buffer+= &children;
+	}
+	return	buffer;
+}
+
+impl	EncodeElement for ListElement {
+	fn	output( &self, encoder: &Encoder ) -> String
+	{
+		output_list( self, encoder, "" )
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+impl	EncodeElement for BlockQuoteElement {
+	fn	output( &self, encoder: &Encoder ) -> String
+	{
+		return	format!( "<blockquote>\n{}</blockquote>\n", encoder.encode_blocks( &self.blocks ) );
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+impl	EncodeElement for PRETagElement {
+	fn	output( &self, _encoder: &Encoder ) -> String
+	{
+		if self.code.is_empty() {
+			return	format!( "<pre>\n{}</pre>\n", self.text );
+		}
+		return	format!( "<syntaxhighlight lang=\"{}\">\n{}</syntaxhighlight>\n", self.code, self.text );
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+// A row is a line while the cells are the same kind, "!!" and "||" separate them.
+impl	EncodeElement for TABLEElement {
+	fn	output( &self, _encoder: &Encoder ) -> String
+	{
+		let	mut	buffer= "{| class=\"wikitable\"\n".to_string();
+		for (row,line) in self.grid().iter().enumerate() {
+			buffer+= "|-";
+			let	mut	prev= None;
+			for (col,cell) in line.iter().enumerate() {
+				let	TableGrid::Cell(attr)= cell else { continue };
+				let	mark= if self.cell_header( row, col, attr ) { "!" } else { "|" };
+				if prev == Some(mark) {
+					buffer+= &format!( " {}{} ", mark, mark );
+				}else{
+					buffer+= &format!( "\n{} ", mark );
+				}
+				prev= Some(mark);
+				let	mut	attrs= Vec::new();
+				match self.cell_align( row, col, attr ) {
+					ETableAlign::CENTER => {
+						attrs.push( "style=\"text-align: center\"".to_string() );
+					},
+					ETableAlign::LEFT => {
+						attrs.push( "style=\"text-align: left\"".to_string() );
+					},
+					ETableAlign::RIGHT => {
+						attrs.push( "style=\"text-align: right\"".to_string() );
+					},
+					_ => {
+					},
+				}
+				if attr.colspan > 1 {
+					attrs.push( format!( "colspan=\"{}\"", attr.colspan ) );
+				}
+				if attr.rowspan > 1 {
+					attrs.push( format!( "rowspan=\"{}\"", attr.rowspan ) );
+				}
+				if !attrs.is_empty() {
+					buffer+= &format!( "{} | ", attrs.join( " " ) );
+				}
+				buffer+= &encode_to_media( &attr.text );
+			}
+			buffer+= "\n";
+		}
+		buffer+= "|}\n";
+		return	buffer;
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+impl	EncodeElement for ParagraphElement {
+	fn	output( &self, encoder: &Encoder ) -> String
+	{
+		render_paragraph( &self.text, encoder.paragraph, encode_to_media ) + "\n"
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+pub struct	Encoder {
+	pub paragraph	: ParagraphMode,
+}
+
+impl	Encoder {
+	pub	fn	new() -> Self
+	{
+		Self{
+			paragraph: ParagraphMode::Join,
+		}
+	}
+}
+
+impl	TextEncoder for Encoder {
+	fn	encode_single( &self, element: &Block ) -> String
+	{
+		match element {
+			Block::None => String::new(),
+			Block::Paragraph(e) => e.output( self ),
+			Block::HTag(e) => e.output( self ),
+			Block::List(e) => e.output( self ),
+			Block::PRETag(e) => e.output( self ),
+			Block::Table(e) => e.output( self ),
+			Block::Quote(e) => e.output( self ),
+			Block::HRTag(_) => "----\n".to_string(),
+			Block::TOC(e) => e.output( self ),
+		}
+	}
+	fn	check_block( &self, element: &Block, warnings: &mut Vec<Warning> )
+	{
+		if let Block::List(e)= element {
+			check_list_items( e, warnings );
+		}
+	}
+	fn	check_inline( &self, node: &Inline, warnings: &mut Vec<Warning> )
+	{
+		if let InlineNode::Image(image)= &node.node {
+			if is_external( &image.src ) && (!image.alt.is_empty() || !image.title.is_empty() || image.width.is_some() || image.height.is_some()) {
+				warnings.push( Warning::new( LossKind::Dropped, "image options", "the external image is the bare url", node.span ) );
+			}
+		}
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
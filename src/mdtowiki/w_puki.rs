@@ -1,13 +1,14 @@
 // 2022 Hiroyuki Ogasawara
 // vim:ts=4 sw=4 noet:
 
-use std::fs;
-use std::io::{self,BufRead};
-use regex::{self,Regex};
+use regex;
 use	lazy_static::lazy_static;
 
+use super::span::*;
+use super::inline::*;
 use super::element::*;
 use super::document::*;
+use super::warning::*;
 
 
 #[allow(dead_code)]
@@ -21,154 +22,123 @@ fn print_type<T>( _: &T )
 
 //=============================================================================
 
-struct EMMarkPat {
-	pat:  Regex,
-	cmd1: &'static str,
-	cmd2: &'static str,
-}
-
-//=============================================================================
-
-
-//=============================================================================
-
-pub fn	replace_puki_tags( line: &str ) -> String
+// Reads the arguments of &ref() or #ref(), "img.png,200x100,alt".
+fn	ref_image( args: &str ) -> Image
 {
-	lazy_static! {
-		static ref	PAT_EM_ARRAY: Vec<EMMarkPat>= vec![
-				EMMarkPat{ pat: Regex::new( r"^(.*)'''([^']+)'''(.*)$" ).unwrap(),		cmd1: "\x07B1", cmd2: "\x07b1", },
-				EMMarkPat{ pat: Regex::new( r"^(.*)''([^']+)''(.*)$" ).unwrap(),		cmd1: "\x07B2", cmd2: "\x07b2", },
-				EMMarkPat{ pat: Regex::new( r"^(.*)%%([^']+)%%(.*)$" ).unwrap(),		cmd1: "\x07D0", cmd2: "\x07d0", },
-			];
-		//static ref	PAT_LINK2: Regex= Regex::new( r"^(.*)\[\[([^|]+)(>|:)(.*)\]\](.*)$" ).unwrap();
-		static ref	PAT_LINK2: Regex= Regex::new( r"^(.*)\[\[([^|]+)(>)(.*)\]\](.*)$" ).unwrap();
-		static ref	PAT_LINK1: Regex= Regex::new( r"^(.*)\[\[(.+)\]\](.*)$" ).unwrap();
-	}
-	let	mut	buffer= line.to_string();
-	loop {
-		let	mut	bfound= false;
-		for emmark in PAT_EM_ARRAY.iter() {
-			if let Some(v)= emmark.pat.captures( &buffer ) {
-				let	mut buffer2= String::new();
-				buffer2+= &v[1];
-				buffer2+= emmark.cmd1;
-				buffer2+= &v[2];
-				buffer2+= emmark.cmd2;
-				buffer2+= &v[3];
-				buffer= buffer2;
-				bfound= true;
-				break;
-			}
-		}
-		if bfound {
+	let	mut	params= args.split( ',' ).map( |p| p.trim() );
+	let	mut	image= Image::new( params.next().unwrap_or( "" ) );
+	for param in params {
+		if image.set_size( param ) {
 			continue;
 		}
-		if let Some(v)= PAT_LINK2.captures( &buffer ) {
-			let	mut buffer2= String::new();
-			buffer2+= &v[1];
-			buffer2+= "\x07L0";
-			buffer2+= &v[4];
-			buffer2+= "\x07L1";
-			buffer2+= &v[2];
-			buffer2+= "\x07L2";
-			buffer2+= &v[5];
-			buffer= buffer2;
-			continue;
+		if let Some(w)= param.strip_prefix( "w:" ) {
+			image.width= w.parse().ok();
+		}else if let Some(h)= param.strip_prefix( "h:" ) {
+			image.height= h.parse().ok();
+		}else if !param.is_empty() && !param.ends_with( '%' ) && !REF_OPTIONS.contains( &param ) {
+			image.alt= param.to_string();
 		}
-		if let Some(v)= PAT_LINK1.captures( &buffer ) {
-			let	mut buffer2= String::new();
-			buffer2+= &v[1];
-			buffer2+= "\x07L0";
-			buffer2+= &v[2];
-			buffer2+= "\x07L1";
-			buffer2+= "\x07L2";
-			buffer2+= &v[3];
-			buffer= buffer2;
-			continue;
-		}
-		break;
 	}
-	return	buffer;
+	return	image;
 }
 
-pub fn	decode_from_puki( line0: &str ) -> String
+const	REF_OPTIONS: [&str;11]= [ "left", "center", "right", "wrap", "nowrap", "around", "noicon", "noimg", "nolink", "zoom", "full" ];
+
+fn	puki_special( parser: &InlineParser, text: &str, _prev: Option<char>, at: Span ) -> Option<(Vec<Inline>,usize)>
 {
-	return	replace_puki_tags( line0 );
+	if text.starts_with( "&br;" ) {
+		return	Some( (vec![Inline::new( InlineNode::LineBreak )], 4) );
+	}
+	if let Some(body)= text.strip_prefix( "&ref(" ) {
+		let	epos= body.find( ");" )?;
+		return	Some( (vec![Inline::new( InlineNode::Image( ref_image( &body[..epos] ) ) )], epos + 7) );
+	}
+	if let Some(body)= text.strip_prefix( "[[" ) {
+		let	epos= body.find( "]]" )?;
+		let	inner= &body[..epos];
+		//let	link= match inner.split_once( &['>', ':'] ) {
+		let	link= match inner.split_once( '>' ) {
+			Some((label,url)) => InlineNode::Link{ url: url.trim().to_string(), text: parser.parse_at( label, at.at( text, label ) ) },
+			None => InlineNode::Link{ url: inner.trim().to_string(), text: Vec::new() },
+		};
+		return	Some( (vec![Inline::new( link )], epos + 4) );
+	}
+	return	None;
 }
 
+pub fn	decode_from_puki( line: &str, span: Span ) -> Vec<Inline>
+{
+	lazy_static! {
+		static ref	PARSER: InlineParser= InlineParser{
+				delimiters: vec![
+					Delimiter{ open: "'''''",	close: "'''''",	kind: InlineKind::StrongEmphasis,	word: false, },
+					Delimiter{ open: "'''",	close: "'''",	kind: InlineKind::Emphasis,	word: false, },
+					Delimiter{ open: "''",	close: "''",	kind: InlineKind::Strong,	word: false, },
+					Delimiter{ open: "%%",	close: "%%",	kind: InlineKind::Strike,	word: false, },
+				],
+				tight: false,
+				special: puki_special,
+			};
+	}
+	return	PARSER.parse_at( line, span );
+}
+
+// A line ending with '~' forces a line break.
+fn	decode_puki_line( line: &str, span: Span ) -> Vec<Inline>
+{
+	if let Some(text)= line.trim_end().strip_suffix( '~' ) {
+		let	mut	list= decode_from_puki( text, span );
+		list.push( Inline::at( InlineNode::LineBreak, span.advance( text ) ) );
+		return	list;
+	}
+	return	decode_from_puki( line.trim_end(), span );
+}
 
-pub	fn	encode_to_puki( line: &str ) -> String
+
+pub	fn	encode_to_puki( list: &[Inline] ) -> String
 {
 	let	mut	buffer= String::new();
-	let	mut	char_it= line.chars();
-	loop {
-		let	ch= char_it.next();
-		match ch {
-			Some('\x07') => {
-				let	cmd0= char_it.next().unwrap();
-				let	cmd1= char_it.next().unwrap();
-				match cmd0 {
-					'B'|'b' => {
-						match cmd1 {
-							'1' => {
-								buffer+= "'''";
-							},
-							'2' => {
-								buffer+= "''";
-							},
-							_ => {
-								buffer+= "";
-							},
-						}
-					},
-					'D'|'d' => {
-						buffer+= "%%";
-					},
-					'L' => {
-						match cmd1 {
-							'0' => {
-								let	mut	url= "";
-								let	mut	text= "";
-								if let Some(upos)= char_it.as_str().find( '\x07' ) {
-									url= &char_it.as_str()[..upos];
-									for _ in 0..upos {
-										char_it.next();
-									}
-									char_it.next();
-									char_it.next();
-									char_it.next();
-									if let Some(tpos)= char_it.as_str().find( '\x07' ) {
-										text= &char_it.as_str()[..tpos];
-										for _ in 0..tpos {
-											char_it.next();
-										}
-										char_it.next();
-										char_it.next();
-										char_it.next();
-									}
-								}
-								buffer+= "[[";
-								buffer+= text;
-								buffer+= ">";
-								buffer+= url;
-								buffer+= "]]";
-							},
-							_ => {
-							},
-						}
-					},
-					_ => {
-					},
+	for node in list {
+		match &node.node {
+			InlineNode::Text(text) | InlineNode::Code(text) => {
+				buffer+= text;
+			},
+			InlineNode::Strong(child) => {
+				buffer+= &format!( "''{}''", encode_to_puki( child ) );
+			},
+			InlineNode::Emphasis(child) => {
+				buffer+= &format!( "'''{}'''", encode_to_puki( child ) );
+			},
+			InlineNode::Strike(child) => {
+				buffer+= &format!( "%%{}%%", encode_to_puki( child ) );
+			},
+			InlineNode::Link{ url, text } => {
+				if text.is_empty() {
+					buffer+= &format!( "[[{}]]", url );
+				}else{
+					buffer+= &format!( "[[{}>{}]]", encode_to_puki( text ), url );
+				}
+			},
+			InlineNode::Image(image) => {
+				let	mut	args= image.src.clone();
+				if image.width.is_some() || image.height.is_some() {
+					args+= &format!( ",{}", image.size_text() );
+				}
+				let	alt= if image.alt.is_empty() { &image.title } else { &image.alt };
+				if !alt.is_empty() {
+					args+= &format!( ",{}", alt );
 				}
+				buffer+= &format!( "&ref({});", args );
 			},
-			Some(c) => {
-				buffer+= &format!( "{}", c );
+			InlineNode::SoftBreak => {
+				buffer+= "\n";
 			},
-			None => {
-				return	buffer;
+			InlineNode::LineBreak => {
+				buffer+= "~\n";
 			},
 		}
 	}
+	return	buffer;
 }
 
 //=============================================================================
@@ -177,19 +147,33 @@ pub	fn	encode_to_puki( line: &str ) -> String
 //-----------------------------------------------------------------------------
 
 trait ElementGenerator {
-	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Box<dyn Element>>;
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>;
 }
 
 
 struct GenerateorContext {
+	// current line
+	span: Span,
+	// paragraph
+	para: ParagraphElement,
+	// list
+	list_block: bool,
+	list_entries: Vec<ListEntry>,
+	list_pat: regex::Regex,
+	// quote
+	quote_block: bool,
+	quote_lines: Vec<SourceLine>,
+	quote_span: Span,
 	// pre
 	pre_block: bool,
+	pre_span: Span,
 	pre_code: String,
 	pre_data: String,
 	pre_pat: regex::Regex,
 	// table
 	table_block: bool,
-	table_column: Vec<TableColumn>,
+	table_grid: Vec<(Span, Vec<TableGrid<TableAttr>>)>,
+	table_align: Vec<ETableAlign>,
 	table_pat: regex::Regex,
 }
 
@@ -197,21 +181,61 @@ impl GenerateorContext {
 	fn	new() -> Self
 	{
 		GenerateorContext{
+				span: Span::default(),
+				para: ParagraphElement::new(),
+				list_block: false,
+				list_entries: Vec::new(),
+				list_pat: regex::Regex::new( r"^(-{1,3}|\+{1,3})([^-+].*)?$" ).unwrap(),
+				quote_block: false,
+				quote_lines: Vec::new(),
+				quote_span: Span::default(),
 				pre_block: false,
+				pre_span: Span::default(),
 				pre_code: String::new(),
 				pre_data: String::new(),
 				pre_pat: regex::Regex::new( r"^ (.*)$" ).unwrap(),
 				table_block: false,
-				table_column: Vec::new(),
-				table_pat: regex::Regex::new( r"^\|(.*)\|(h)?\s*$" ).unwrap(),
+				table_grid: Vec::new(),
+				table_align: Vec::new(),
+				table_pat: regex::Regex::new( r"^\|(.*)\|([hcf])?\s*$" ).unwrap(),
 			}
 	}
 	//-------------------------------------------------------------------------
+	fn	is_list_block( &self ) -> bool
+	{
+		self.list_block
+	}
+	fn	add_list_block( &mut self, line: &str ) -> Option<Vec<Block>>
+	{
+		let	pat_result= self.list_pat.captures( line );
+		if let Some(v)= pat_result {
+			self.list_entries.push( list_entry( &v, line, self.span ) );
+			return	None;
+		}
+		self.list_block= false;
+		return	Some( build_list_tree( &mut self.list_entries ) );
+	}
+	//-------------------------------------------------------------------------
+	fn	is_quote_block( &self ) -> bool
+	{
+		self.quote_block
+	}
+	// Returns false when the line is out of the quote.
+	fn	add_quote_block( &mut self, line: &str ) -> bool
+	{
+		if let Some(text)= strip_quote_mark( line ) {
+			self.quote_lines.push( SourceLine::new( text, self.span.at( line, text ) ) );
+			return	true;
+		}
+		self.quote_block= false;
+		return	false;
+	}
+	//-------------------------------------------------------------------------
 	fn	is_pre_block( &self ) -> bool
 	{
 		self.pre_block
 	}
-	fn	add_pre_block( &mut self, line: &str ) -> Option<Box<dyn Element>>
+	fn	add_pre_block( &mut self, line: &str ) -> Option<Block>
 	{
 		let	pat_result= self.pre_pat.captures( line );
 		if let Some(v)= pat_result {
@@ -220,10 +244,10 @@ impl GenerateorContext {
 			return	None;
 		}else{
 			self.pre_block= false;
-			return	Some( Box::new( PRETagElement{
-							etype:	ElementType::PRETAG,
+			return	Some( Block::PRETag( PRETagElement{
 							text: 	(&self.pre_data).to_string(),
 							code:	(&self.pre_code).to_string(),
+							span:	self.pre_span,
 						}));
 		}
 	}
@@ -232,23 +256,26 @@ impl GenerateorContext {
 	{
 		self.table_block
 	}
-	fn	add_table_block( &mut self, line: &str ) -> Option<Box<dyn Element>>
+	fn	add_table_block( &mut self, line: &str ) -> Option<Block>
 	{
 		let	pat_result= self.table_pat.captures( line );
 		if let Some(v)= pat_result {
-			println!( "table line={}", line );
 			let	tline= &v[1];
-			let	mut	header= false;
-			if let Some(h)= v.get(2) {
-				if h.as_str() == "h" {
-					header= true;
-				}
-			}
+			let	mode= v.get( 2 ).map_or( "", |m| m.as_str() );
 			let	params= tline.split( '|' ).peekable();
-			let	mut	column= TableColumn::new();
-			for (_,td) in params.enumerate() {
+			let	mut	cells= Vec::new();
+			let	mut	aligns= Vec::new();
+			for td in params {
 				let	mut	align= ETableAlign::DEFAULT;
 				let	mut	trim_td= td.trim();
+				if trim_td == ">" {
+					cells.push( TableGrid::SpanRight );
+					continue;
+				}
+				if trim_td == "~" {
+					cells.push( TableGrid::SpanUp );
+					continue;
+				}
 				if trim_td.starts_with( "LEFT:" ) {
 					align= ETableAlign::LEFT;
 					trim_td= &trim_td[5..].trim();
@@ -259,22 +286,38 @@ impl GenerateorContext {
 					align= ETableAlign::RIGHT;
 					trim_td= &trim_td[6..].trim();
 				}
-				column.add( TableAttr{
-						text: decode_from_puki( trim_td ),
+				let	mut	header= if mode == "h" { Some(true) } else { None };
+				if let Some(text)= trim_td.strip_prefix( '~' ) {
+					header= Some(true);
+					trim_td= text;
+				}
+				aligns.push( align );
+				cells.push( TableGrid::Cell( TableAttr{
+						text: decode_from_puki( trim_td, self.span.at( line, trim_td ) ),
 						align: align,
 						header: header,
-					} );
+						colspan: 1,
+						rowspan: 1,
+					} ) );
+			}
+			if mode == "c" {
+				// the format line gives the alignment of the columns
+				self.table_align= aligns;
+			}else{
+				self.table_grid.push( (self.span, cells) );
 			}
-			self.table_column.push( column )
 		}else{
-			self.table_block= false;
-			return	Some( Box::new( TABLEElement{
-							etype:	ElementType::TABLE,
-							data:	self.table_column.to_vec(),
-						}));
+			return	Some( self.finish_table() );
 		}
 		return	None;
 	}
+	fn	finish_table( &mut self ) -> Block
+	{
+		self.table_block= false;
+		let	mut	table= build_table( std::mem::take( &mut self.table_grid ) );
+		table.align= std::mem::take( &mut self.table_align );
+		Block::Table( table )
+	}
 	//-------------------------------------------------------------------------
 }
 
@@ -296,22 +339,22 @@ impl HTagGen {
 }
 
 impl ElementGenerator for HTagGen {
-	fn	generate( &self, line: &str, _context: &mut GenerateorContext ) -> Option<Box<dyn Element>>
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
 	{
 		let	pat_result= self.pat_link.captures( line );
 		if let Some(v)= pat_result {
-			return	Some( Box::new( HTagElement{
-							etype:	ElementType::HTAG,
-							title: 	v[2].trim().to_string(),
+			return	Some( Block::HTag( HTagElement{
+							title: 	decode_from_puki( v[2].trim(), context.span.at( line, v[2].trim() ) ),
 							level:	v[1].len() as u32,
+							span:	context.span,
 						}));
 		}
 		let	pat_result= self.pat.captures( line );
 		if let Some(v)= pat_result {
-			return	Some( Box::new( HTagElement{
-							etype:	ElementType::HTAG,
-							title: 	v[2].trim().to_string(),
+			return	Some( Block::HTag( HTagElement{
+							title: 	decode_from_puki( v[2].trim(), context.span.at( line, v[2].trim() ) ),
 							level:	v[1].len() as u32,
+							span:	context.span,
 						}));
 		}
 		return	None;
@@ -321,45 +364,66 @@ impl ElementGenerator for HTagGen {
 
 //-----------------------------------------------------------------------------
 
+fn	list_entry( v: &regex::Captures, line: &str, span: Span ) -> ListEntry
+{
+	let	text= v.get( 2 ).map_or( "", |t| t.as_str().trim() );
+	ListEntry{
+		depth:		v[1].len() as u32 - 1,
+		ordered:	v[1].starts_with( '+' ),
+		text:		decode_from_puki( text, span.at( line, text ) ),
+		span:		span.at( line, &v[1] ),
+	}
+}
+
 struct LITagGen {
-	pat_ul : regex::Regex,
-	pat_ol : regex::Regex,
+	pat: regex::Regex,
 }
 
 impl LITagGen {
 	fn	new() -> Self
 	{
 		LITagGen{
-			pat_ul: regex::Regex::new( r"^(-+)(.*)$" ).unwrap(),
-			pat_ol: regex::Regex::new( r"^(\++)(.*)$" ).unwrap(),
+			pat: regex::Regex::new( r"^(-{1,3}|\+{1,3})([^-+].*)?$" ).unwrap(),
 		}
 	}
 }
 
 impl ElementGenerator for LITagGen {
-	fn	generate( &self, line: &str, _context: &mut GenerateorContext ) -> Option<Box<dyn Element>>
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
 	{
-		let	pat_result= self.pat_ul.captures( line );
+		let	pat_result= self.pat.captures( line );
 		if let Some(v)= pat_result {
-			let	indent= v[1].len() as u32;
-			let nest= indent-1;
-			return	Some( Box::new( LITagElement{
-							etype:	ElementType::ULTAG,
-							text: 	decode_from_puki( &v[2] ),
-							indent:	indent,
-							nest:	nest,
-						}));
+			context.list_block= true;
+			context.list_entries= vec![list_entry( &v, line, context.span )];
+			return	Some( Block::None );
 		}
-		let	pat_result= self.pat_ol.captures( line );
-		if let Some(v)= pat_result {
-			let	indent= v[1].len() as u32;
-			let nest= indent-1;
-			return	Some( Box::new( LITagElement{
-							etype:	ElementType::OLTAG,
-							text: 	decode_from_puki( &v[2] ),
-							indent:	indent,
-							nest:	nest,
-						}));
+		return	None;
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+// '>' '>>' '>>>' nest the quote.
+struct QuoteGen {
+}
+
+impl QuoteGen {
+	fn	new() -> Self
+	{
+		QuoteGen{
+		}
+	}
+}
+
+impl ElementGenerator for QuoteGen {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		if let Some(text)= strip_quote_mark( line ) {
+			context.quote_block= true;
+			context.quote_lines= vec![SourceLine::new( text, context.span.at( line, text ) )];
+			context.quote_span= context.span;
+			return	Some( Block::None );
 		}
 		return	None;
 	}
@@ -382,14 +446,15 @@ impl PRETagGen {
 }
 
 impl ElementGenerator for PRETagGen {
-	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Box<dyn Element>>
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
 	{
 		let	pat_result= self.pat.captures( line );
 		if let Some(v)= pat_result {
 			context.pre_block= true;
-			context.pre_data= (&v[1]).to_string();
+			context.pre_span= context.span;
+			context.pre_data= format!( "{}\n", &v[1] );
 			context.pre_code= String::new();
-			return	Some( Box::new( NONEElement{} ));
+			return	Some( Block::None );
 		}
 		return	None;
 	}
@@ -406,22 +471,23 @@ impl TABLEGen {
 	fn	new() -> Self
 	{
 		TABLEGen{
-			pat: regex::Regex::new( r"^\|.*\|(h)?\s*$" ).unwrap(),
+			pat: regex::Regex::new( r"^\|.*\|([hcf])?\s*$" ).unwrap(),
 		}
 	}
 }
 
 impl ElementGenerator for TABLEGen {
-	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Box<dyn Element>>
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
 	{
 		let	pat_result= self.pat.captures( line );
 		if let Some(_)= pat_result {
 			context.table_block= true;
-			context.table_column= Vec::new();
+			context.table_grid= Vec::new();
+			context.table_align= Vec::new();
 			if let Some(e)= context.add_table_block( line ) {
 				return	Some(e);
 			}
-			return	Some( Box::new( NONEElement{} ));
+			return	Some( Block::None );
 		}
 		return	None;
 	}
@@ -446,15 +512,44 @@ impl HRTagGen {
 }
 
 impl ElementGenerator for HRTagGen {
-	fn	generate( &self, line: &str, _context: &mut GenerateorContext ) -> Option<Box<dyn Element>>
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
 	{
 		let	pat_result= self.pat.captures( line );
 		if let Some(_)= pat_result {
-			return	Some( Box::new( HRTagElement{} ) );
+			return	Some( Block::HRTag( context.span ) );
 		}
 		let	pat_result= self.pat_hr.captures( line );
 		if let Some(_)= pat_result {
-			return	Some( Box::new( HRTagElement{} ) );
+			return	Some( Block::HRTag( context.span ) );
+		}
+		return	None;
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+// #ref() on its own line is a paragraph holding the image.
+struct RefGen {
+	pat : regex::Regex,
+}
+
+impl RefGen {
+	fn	new() -> Self
+	{
+		RefGen{
+			pat: regex::Regex::new( r"^#ref\((.*)\)\s*$" ).unwrap(),
+		}
+	}
+}
+
+impl ElementGenerator for RefGen {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		let	pat_result= self.pat.captures( line );
+		if let Some(v)= pat_result {
+			let	image= Inline::at( InlineNode::Image( ref_image( &v[1] ) ), context.span );
+			return	Some( Block::Paragraph( ParagraphElement{ text: vec![image], span: context.span } ) );
 		}
 		return	None;
 	}
@@ -477,11 +572,11 @@ impl CommentGen {
 }
 
 impl ElementGenerator for CommentGen {
-	fn	generate( &self, line: &str, _context: &mut GenerateorContext ) -> Option<Box<dyn Element>>
+	fn	generate( &self, line: &str, _context: &mut GenerateorContext ) -> Option<Block>
 	{
 		let	pat_result= self.pat.captures( line );
 		if let Some(_)= pat_result {
-			return	Some( Box::new( NONEElement{} ) );
+			return	Some( Block::None );
 		}
 		return	None;
 	}
@@ -501,13 +596,15 @@ impl Decoder {
 			Box::new( HRTagGen::new() ),
 			Box::new( HTagGen::new() ),
 			Box::new( LITagGen::new() ),
+			Box::new( QuoteGen::new() ),
 			Box::new( PRETagGen::new() ),
 			Box::new( TABLEGen::new() ),
+			Box::new( RefGen::new() ),
 			Box::new( CommentGen::new() ),
 		];
 		Decoder{ gen_table: gen_table }
 	}
-	fn	find( &self, line: &str, context: &mut GenerateorContext ) -> Option<Box<dyn Element>>
+	fn	find( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
 	{
 		for gen in &self.gen_table {
 			let	element= gen.generate( line, context );
@@ -517,44 +614,77 @@ impl Decoder {
 		}
 		return	None;
 	}
-	pub fn	load( &self, file_name: &str ) -> io::Result<Document>
+	fn	build_quote( &self, lines: &[SourceLine], span: Span ) -> Block
 	{
-		let	file= fs::File::open( file_name )?;
-		let	reader= io::BufReader::new( file );
+		Block::Quote( BlockQuoteElement{ blocks: self.decode_lines( lines ).element_list, span: span } )
+	}
+}
 
+impl TextDecoder for Decoder {
+	fn	decode_lines( &self, lines: &[SourceLine] ) -> Document
+	{
 		let	mut	page= Document::new();
 		let	mut	context= GenerateorContext::new();
 
-		for rline in reader.lines() {
-			match rline {
-				Ok(line) => {
-					if context.is_pre_block() {
-						if let Some(e)= context.add_pre_block( &line ) {
-							page.push( e );
-						}else{
-							continue;
-						}
-					}else if context.is_table_block() {
-						if let Some(e)= context.add_table_block( &line ) {
-							page.push( e );
-						}else{
-							continue;
-						}
+		for source in lines {
+			let	line= &source.text;
+			context.span= source.span;
+			if context.is_pre_block() {
+				if let Some(e)= context.add_pre_block( line ) {
+					page.push( e );
+				}else{
+					continue;
+				}
+			}else if context.is_table_block() {
+				if let Some(e)= context.add_table_block( line ) {
+					page.push( e );
+				}else{
+					continue;
+				}
+			}else if context.is_list_block() {
+				if let Some(list)= context.add_list_block( line ) {
+					for e in list {
+						page.push( e );
+					}
+				}else{
+					continue;
+				}
+			}else if context.is_quote_block() {
+				if context.add_quote_block( line ) {
+					continue;
+				}
+				page.push( self.build_quote( &std::mem::take( &mut context.quote_lines ), context.quote_span ) );
+			}
+			{
+				if let Some(e)= self.find( line, &mut context ) {
+					if let Some(p)= context.para.finish() {
+						page.push( p );
 					}
-					{
-						if let Some(e)= self.find( &line, &mut context ) {
-							page.push( e );
-						}else{
-							page.push( Box::new(DataElement{ text: decode_from_puki( &line ) }) );
-						}
+					page.push( e );
+				}else if line.trim().is_empty() {
+					if let Some(p)= context.para.finish() {
+						page.push( p );
 					}
-				},
-				Err(e) => {
-					return	Err(e);
-				},
+				}else{
+					context.para.add_line( decode_puki_line( line, source.span ) );
+				}
 			}
 		}
-		Ok(page)
+		if context.is_list_block() {
+			for e in build_list_tree( &mut context.list_entries ) {
+				page.push( e );
+			}
+		}else if context.is_quote_block() {
+			page.push( self.build_quote( &context.quote_lines, context.quote_span ) );
+		}else if context.is_table_block() {
+			page.push( context.finish_table() );
+		}else if context.is_pre_block() {
+			page.push( Block::PRETag( PRETagElement{ text: context.pre_data, code: context.pre_code, span: context.pre_span } ) );
+		}
+		if let Some(p)= context.para.finish() {
+			page.push( p );
+		}
+		page
 	}
 }
 
@@ -566,42 +696,95 @@ impl Decoder {
 //=============================================================================
 
 trait	EncodeElement {
-	fn	output( &self ) -> String;
+	fn	output( &self, encoder: &Encoder ) -> String;
 }
 
 
 //-----------------------------------------------------------------------------
 
-impl	EncodeElement for NONEElement {
-	fn	output( &self ) -> String
+impl	EncodeElement for HTagElement {
+	fn	output( &self, _encoder: &Encoder ) -> String
 	{
-		return	String::new();
+		let	tag= '*'.to_string().repeat( self.level.clamp( 1, 6 ) as usize );
+		return	format!( "{} {}\n", tag, encode_to_puki( &self.title ) );
 	}
 }
 
 
 //-----------------------------------------------------------------------------
 
-impl	EncodeElement for HTagElement {
-	fn	output( &self ) -> String
+impl	EncodeElement for TOCElement {
+	fn	output( &self, _encoder: &Encoder ) -> String
+	{
+		return	"#contents\n".to_string();
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+// A list item is a single line, the other blocks follow the item.
+fn	output_list( list: &ListElement, encoder: &Encoder, nest: usize ) -> String
+{
+	let	mut	buffer= String::new();
+	let	depth= (nest+1).min( 3 );
+	let	mark= if list.ordered { '+' } else { '-' }.to_string().repeat( depth );
+	for item in &list.items {
+		let	mut	text= String::new();
+		let	mut	children= String::new();
+		for block in &item.blocks {
+			match block {
+				Block::Paragraph(e) => {
+					if !text.is_empty() {
+						text+= "&br;";
+					}
+					text+= &render_paragraph( &e.text, ParagraphMode::Join, encode_to_puki ).replace( "~\n", "&br;" );
+				},
+				Block::List(e) => {
+					children+= &output_list( e, encoder, nest+1 );
+				},
+				_ => {
+					children+= &encoder.encode_single( block );
+				},
+			}
+		}
+		buffer+= &format!( "{} {}\n", mark, text );
+		buffer+= &children;
+	}
+	return	buffer;
+}
+
+// The lists nested the depth below the list, "---" is the deepest of PukiWiki.
+fn	nested_lists( list: &ListElement, depth: usize ) -> Vec<&ListElement>
+{
+	let	mut	result= Vec::new();
+	for item in &list.items {
+		for block in &item.blocks {
+			if let Block::List(child)= block {
+				if depth == 1 {
+					result.push( child );
+				}else{
+					result.extend( nested_lists( child, depth-1 ) );
+				}
+			}
+		}
+	}
+	return	result;
+}
+
+impl	EncodeElement for ListElement {
+	fn	output( &self, encoder: &Encoder ) -> String
 	{
-		let	tag= '*'.to_string().repeat( self.level as usize );
-		return	format!( "{} {}\n", tag, self.title );
+		output_list( self, encoder, 0 )
 	}
 }
 
 
 //-----------------------------------------------------------------------------
 
-impl	EncodeElement for LITagElement {
-	fn	output( &self ) -> String
+impl	EncodeElement for BlockQuoteElement {
+	fn	output( &self, encoder: &Encoder ) -> String
 	{
-		let	indent= self.nest+1;
-		if self.etype == ElementType::ULTAG {
-			return	format!( "{} {}\n", '-'.to_string().repeat(indent as usize), encode_to_puki( &self.text ) );
-		}else{
-			return	format!( "{} {}\n", '+'.to_string().repeat(indent as usize), encode_to_puki( &self.text ) );
-		}
+		return	prefix_quote_lines( &encoder.encode_blocks( &self.blocks ), ">" );
 	}
 }
 
@@ -609,10 +792,10 @@ impl	EncodeElement for LITagElement {
 //-----------------------------------------------------------------------------
 
 impl	EncodeElement for PRETagElement {
-	fn	output( &self ) -> String
+	fn	output( &self, _encoder: &Encoder ) -> String
 	{
 		let	mut	buffer= String::new();
-		for line in self.text.split( '\n' ) {
+		for line in self.text.lines() {
 			buffer+= " ";
 			buffer+= line;
 			buffer+= "\n";
@@ -624,31 +807,52 @@ impl	EncodeElement for PRETagElement {
 
 //-----------------------------------------------------------------------------
 
+fn	align_prefix( align: ETableAlign ) -> &'static str
+{
+	match align {
+		ETableAlign::CENTER => "CENTER:",
+		ETableAlign::LEFT => "LEFT:",
+		ETableAlign::RIGHT => "RIGHT:",
+		_ => "",
+	}
+}
+
 impl	EncodeElement for TABLEElement {
-	fn	output( &self ) -> String
+	fn	output( &self, _encoder: &Encoder ) -> String
 	{
 		let	mut	buffer= String::new();
-		for column in &self.data {
+		if self.align.iter().any( |a| *a != ETableAlign::DEFAULT ) {
 			buffer+= "|";
-			let mut	bheader= false;
-			for attr in &column.data {
-				match attr.align {
-					ETableAlign::CENTER => {
-						buffer+= "CENTER:";
-					},
-					ETableAlign::LEFT => {
-						buffer+= "LEFT:";
-					},
-					ETableAlign::RIGHT => {
-						buffer+= "RIGHT:";
+			for align in &self.align {
+				buffer+= &format!( "{}|", align_prefix( *align ) );
+			}
+			buffer+= "c\n";
+		}
+		for (row,line) in self.grid().iter().enumerate() {
+			let	bheader= self.is_header_row( row );
+			buffer+= "|";
+			for (col,cell) in line.iter().enumerate() {
+				let	attr= match cell {
+					TableGrid::Cell(attr) => attr,
+					TableGrid::SpanUp => {
+						buffer+= " ~ |";
+						continue;
 					},
 					_ => {
+						continue;
 					},
+				};
+				for _ in 1..attr.colspan {
+					buffer+= " > |";
 				}
-				buffer+= &format!( " {} |", encode_to_puki( &attr.text ) );
-				if !bheader && attr.header {
-					bheader= true;
+				let	align= self.cell_align( row, col, attr );
+				if align != self.align.get( col ).copied().unwrap_or( ETableAlign::DEFAULT ) {
+					buffer+= align_prefix( align );
 				}
+				if !bheader && self.cell_header( row, col, attr ) {
+					buffer+= "~";
+				}
+				buffer+= &format!( " {} |", encode_to_puki( &attr.text ) );
 			}
 			if bheader {
 				buffer+= "h";
@@ -662,20 +866,10 @@ impl	EncodeElement for TABLEElement {
 
 //-----------------------------------------------------------------------------
 
-impl	EncodeElement for HRTagElement {
-	fn	output( &self ) -> String
-	{
-		return	"----\n".to_string();
-	}
-}
-
-
-//-----------------------------------------------------------------------------
-
-impl	EncodeElement for DataElement {
-	fn	output( &self ) -> String
+impl	EncodeElement for ParagraphElement {
+	fn	output( &self, encoder: &Encoder ) -> String
 	{
-		encode_to_puki( &self.text ) + "\n"
+		render_paragraph( &self.text, encoder.paragraph, encode_to_puki ) + "\n"
 	}
 }
 
@@ -683,41 +877,57 @@ impl	EncodeElement for DataElement {
 //-----------------------------------------------------------------------------
 
 pub struct	Encoder {
+	pub paragraph	: ParagraphMode,
 }
 
 impl	Encoder {
 	pub	fn	new() -> Self
 	{
-		Self{}
+		Self{
+			paragraph: ParagraphMode::Join,
+		}
 	}
 }
 
 impl TextEncoder for Encoder {
-	fn	encode_single( &self, element: &Box<dyn Element> ) -> String
+	fn	encode_single( &self, element: &Block ) -> String
 	{
-		let	any= element.as_any();
-		if let Some(e)= any.downcast_ref::<NONEElement>() {
-			return	e.output();
-		}
-		if let Some(e)= any.downcast_ref::<HTagElement>() {
-			return	e.output();
-		}
-		if let Some(e)= any.downcast_ref::<LITagElement>() {
-			return	e.output();
-		}
-		if let Some(e)= any.downcast_ref::<PRETagElement>() {
-			return	e.output();
+		match element {
+			Block::None => String::new(),
+			Block::Paragraph(e) => e.output( self ),
+			Block::HTag(e) => e.output( self ),
+			Block::List(e) => e.output( self ),
+			Block::PRETag(e) => e.output( self ),
+			Block::Table(e) => e.output( self ),
+			Block::Quote(e) => e.output( self ),
+			Block::HRTag(_) => "----\n".to_string(),
+			Block::TOC(e) => e.output( self ),
+		}
+	}
+	fn	check_block( &self, element: &Block, warnings: &mut Vec<Warning> )
+	{
+		if let Block::PRETag(e)= element {
+			if !e.code.is_empty() {
+				warnings.push( Warning::new( LossKind::Dropped, "code language", "pukiwiki pre has no language", e.span ) );
+			}
 		}
-		if let Some(e)= any.downcast_ref::<TABLEElement>() {
-			return	e.output();
+		if let Block::List(e)= element {
+			check_list_items( e, warnings );
+			for deep in nested_lists( e, 3 ) {
+				warnings.push( Warning::new( LossKind::Degraded, "list depth", "written at the third level", deep.span ) );
+			}
 		}
-		if let Some(e)= any.downcast_ref::<HRTagElement>() {
-			return	e.output();
+	}
+	fn	check_inline( &self, node: &Inline, warnings: &mut Vec<Warning> )
+	{
+		if let InlineNode::Code(_)= &node.node {
+			warnings.push( Warning::new( LossKind::Dropped, "code span", "written as plain text", node.span ) );
 		}
-		if let Some(e)= any.downcast_ref::<DataElement>() {
-			return	e.output();
+		if let InlineNode::Image(image)= &node.node {
+			if !image.alt.is_empty() && !image.title.is_empty() {
+				warnings.push( Warning::new( LossKind::Dropped, "image title", "only the alt text is written", node.span ) );
+			}
 		}
-		return	String::new();
 	}
 }
 
@@ -3,8 +3,14 @@
 
 //use std::io;
 
+use regex;
+use	lazy_static::lazy_static;
+
+use super::span::*;
+use super::inline::*;
 use super::element::*;
 use super::document::*;
+use super::warning::*;
 
 
 #[allow(dead_code)]
@@ -18,129 +24,829 @@ fn print_type<T>( _: &T )
 
 //=============================================================================
 
-pub	fn	encode_to_red( line: &str ) -> String
+// Reads the style of the image, "{width:200px;height:100px}".
+fn	style_image( image: &mut Image, style: &str )
+{
+	for param in style.split( ';' ) {
+		let	Some((key,value))= param.split_once( ':' ) else { continue };
+		let	value= value.trim().trim_end_matches( "px" ).parse::<u32>().ok();
+		match key.trim() {
+			"width" => image.width= value,
+			"height" => image.height= value,
+			_ => {},
+		}
+	}
+}
+
+// The url of "text":url ends at the space, the punctuation after it is the text.
+fn	link_url( text: &str ) -> &str
+{
+	let	url= &text[..text.find( char::is_whitespace ).unwrap_or( text.len() )];
+	let	mut	url= url.trim_end_matches( ['.', ',', ';', ':', '!', '?'] );
+	if url.ends_with( ')' ) && !url.contains( '(' ) {
+		url= url.trim_end_matches( ')' );
+	}
+	return	url;
+}
+
+fn	red_special( parser: &InlineParser, text: &str, prev: Option<char>, at: Span ) -> Option<(Vec<Inline>,usize)>
+{
+	lazy_static! {
+		static ref	IMAGE: regex::Regex= regex::Regex::new( r"^!(\{([^}]*)\})?[<>=]?([^\s!()]+)(\(([^)]*)\))?!" ).unwrap();
+	}
+	if let Some(body)= text.strip_prefix( "<notextile>" ) {
+		let	epos= body.find( "</notextile>" )?;
+		return	Some( (vec![Inline::new( InlineNode::Text( body[..epos].to_string() ) )], epos + 23) );
+	}
+	if let Some(body)= text.strip_prefix( "==" ) {
+		let	epos= body.find( "==" )?;
+		return	Some( (vec![Inline::new( InlineNode::Text( body[..epos].to_string() ) )], epos + 4) );
+	}
+	for br in ["<br />", "<br/>", "<br>"] {
+		if text.starts_with( br ) {
+			return	Some( (vec![Inline::new( InlineNode::LineBreak )], br.len()) );
+		}
+	}
+	if let Some(body)= text.strip_prefix( "[[" ) {
+		let	epos= body.find( "]]" )?;
+		let	inner= &body[..epos];
+		let	link= match inner.split_once( '|' ) {
+			Some((url,label)) => InlineNode::Link{ url: url.trim().to_string(), text: parser.parse_at( label, at.at( text, label ) ) },
+			None => InlineNode::Link{ url: inner.trim().to_string(), text: Vec::new() },
+		};
+		return	Some( (vec![Inline::new( link )], epos + 4) );
+	}
+	if let Some(v)= IMAGE.captures( text ) {
+		let	mut	image= Image::new( &v[3] );
+		if let Some(style)= v.get( 2 ) {
+			style_image( &mut image, style.as_str() );
+		}
+		image.alt= v.get( 5 ).map_or( String::new(), |alt| alt.as_str().to_string() );
+		return	Some( (vec![Inline::new( InlineNode::Image( image ) )], v[0].len()) );
+	}
+	if prev.is_some_and( |c| c.is_alphanumeric() ) {
+		return	None;
+	}
+	if let Some(body)= text.strip_prefix( '"' ) {
+		let	epos= body.find( "\":" )?;
+		let	label= &body[..epos];
+		let	url= link_url( &body[epos+2..] );
+		if label.is_empty() || label.contains( '"' ) || url.is_empty() {
+			return	None;
+		}
+		// "text(title)":url
+		let	label= match label.strip_suffix( ')' ).and_then( |head| head.rsplit_once( '(' ) ) {
+			Some((head,_)) if !head.trim().is_empty() => head.trim_end(),
+			_ => label,
+		};
+		let	link= InlineNode::Link{ url: url.to_string(), text: parser.parse_at( label, at.advance( "\"" ) ) };
+		return	Some( (vec![Inline::new( link )], 1 + epos + 2 + url.len()) );
+	}
+	return	None;
+}
+
+pub fn	decode_from_red( line: &str, span: Span ) -> Vec<Inline>
+{
+	lazy_static! {
+		static ref	PARSER: InlineParser= InlineParser{
+				delimiters: vec![
+					Delimiter{ open: "**",	close: "**",	kind: InlineKind::Strong,	word: true, },
+					Delimiter{ open: "*",	close: "*",		kind: InlineKind::Strong,	word: true, },
+					Delimiter{ open: "__",	close: "__",	kind: InlineKind::Emphasis,	word: true, },
+					Delimiter{ open: "_",	close: "_",		kind: InlineKind::Emphasis,	word: true, },
+					Delimiter{ open: "-",	close: "-",		kind: InlineKind::Strike,	word: true, },
+					Delimiter{ open: "@",	close: "@",		kind: InlineKind::Code,		word: true, },
+				],
+				tight: true,
+				special: red_special,
+			};
+	}
+	return	PARSER.parse_at( line, span );
+}
+
+// Redmine writes the line break of the paragraph as <br />.
+fn	decode_red_line( line: &str, span: Span ) -> Vec<Inline>
+{
+	let	trimmed= line.trim();
+	let	mut	list= decode_from_red( trimmed, span.at( line, trimmed ) );
+	list.push( Inline::at( InlineNode::LineBreak, span.advance( line ) ) );
+	return	list;
+}
+
+
+pub	fn	encode_to_red( list: &[Inline] ) -> String
 {
 	let	mut	buffer= String::new();
-	let	mut	char_it= line.chars();
-	loop {
-		let	ch= char_it.next();
-		match ch {
-			Some('\x07') => {
-				let	cmd0= char_it.next().unwrap();
-				let	cmd1= char_it.next().unwrap();
-				match cmd0 {
-					'B'|'b' => {
-						match cmd1 {
-							'1' => {
-								buffer+= "_";
-							},
-							'2' => {
-								buffer+= "*";
-							},
-							_ => {
-								buffer+= "";
-							},
-						}
-					},
-					'D'|'d' => {
-						buffer+= "~";
-					},
-					'L' => {
-						match cmd1 {
-							'0' => {
-								let	mut	url= "";
-								let	mut	text= "";
-								if let Some(upos)= char_it.as_str().find( '\x07' ) {
-									url= &char_it.as_str()[..upos];
-									for _ in 0..upos {
-										char_it.next();
-									}
-									char_it.next();
-									char_it.next();
-									char_it.next();
-									if let Some(tpos)= char_it.as_str().find( '\x07' ) {
-										text= &char_it.as_str()[..tpos];
-										for _ in 0..tpos {
-											char_it.next();
-										}
-										char_it.next();
-										char_it.next();
-										char_it.next();
-									}
-								}
-								buffer+= "\"";
-								buffer+= text;
-								buffer+= "\":";
-								buffer+= url;
-							},
-							_ => {
-							},
-						}
-					},
-					_ => {
-					},
+	for node in list {
+		match &node.node {
+			InlineNode::Text(text) => {
+				buffer+= text;
+			},
+			InlineNode::Code(text) => {
+				buffer+= &format!( "@{}@", text );
+			},
+			InlineNode::Strong(child) => {
+				buffer+= &format!( "*{}*", encode_to_red( child ) );
+			},
+			InlineNode::Emphasis(child) => {
+				buffer+= &format!( "_{}_", encode_to_red( child ) );
+			},
+			InlineNode::Strike(child) => {
+				buffer+= &format!( "-{}-", encode_to_red( child ) );
+			},
+			InlineNode::Link{ url, text } => {
+				if text.is_empty() {
+					buffer+= &format!( "\"{}\":{}", url, url );
+				}else{
+					buffer+= &format!( "\"{}\":{}", encode_to_red( text ), url );
+				}
+			},
+			InlineNode::Image(image) => {
+				let	mut	style= Vec::new();
+				if let Some(w)= image.width {
+					style.push( format!( "width:{}px", w ) );
+				}
+				if let Some(h)= image.height {
+					style.push( format!( "height:{}px", h ) );
 				}
+				let	mut	target= image.src.clone();
+				if !style.is_empty() {
+					target= format!( "{{{}}}{}", style.join( ";" ), target );
+				}
+				let	alt= if image.alt.is_empty() { &image.title } else { &image.alt };
+				if !alt.is_empty() {
+					target+= &format!( "({})", alt );
+				}
+				buffer+= &format!( "!{}!", target );
 			},
-			Some(c) => {
-				buffer+= &format!( "{}", c );
+			InlineNode::SoftBreak => {
+				buffer+= "\n";
 			},
-			None => {
-				return	buffer;
+			InlineNode::LineBreak => {
+				buffer+= "\n";
 			},
 		}
 	}
+	return	buffer;
 }
 
 
 //=============================================================================
 
 
-//=============================================================================
+//-----------------------------------------------------------------------------
 
-trait	EncodeElement {
-	fn	output( &self ) -> String;
+trait ElementGenerator {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>;
+}
+
+
+struct GenerateorContext {
+	// current line
+	span: Span,
+	// paragraph
+	para: ParagraphElement,
+	// list
+	list_block: bool,
+	list_entries: Vec<ListEntry>,
+	list_pat: regex::Regex,
+	// quote
+	quote_block: bool,
+	quote_bq: bool,
+	quote_lines: Vec<SourceLine>,
+	quote_span: Span,
+	// pre
+	pre_block: bool,
+	pre_span: Span,
+	pre_code: String,
+	pre_data: String,
+	// table
+	table_block: bool,
+	table_grid: Vec<(Span, Vec<TableGrid<TableAttr>>)>,
+	table_rowspan: Vec<u32>,
+	table_pat: regex::Regex,
+	cell_pat: regex::Regex,
+}
+
+impl GenerateorContext {
+	fn	new() -> Self
+	{
+		GenerateorContext{
+				span: Span::default(),
+				para: ParagraphElement::new(),
+				list_block: false,
+				list_entries: Vec::new(),
+				list_pat: regex::Regex::new( r"^([*#]+)\s+(.*)$" ).unwrap(),
+				quote_block: false,
+				quote_bq: false,
+				quote_lines: Vec::new(),
+				quote_span: Span::default(),
+				pre_block: false,
+				pre_span: Span::default(),
+				pre_code: String::new(),
+				pre_data: String::new(),
+				table_block: false,
+				table_grid: Vec::new(),
+				table_rowspan: Vec::new(),
+				table_pat: regex::Regex::new( r"^\|(.*)\|\s*$" ).unwrap(),
+				cell_pat: regex::Regex::new( r"^((?:_|<>|<|>|=|\^|~|\\\d+|/\d+|\{[^}]*\}|\([^)]*\))+)\.(?:\s|$)" ).unwrap(),
+			}
+	}
+	//-------------------------------------------------------------------------
+	fn	is_list_block( &self ) -> bool
+	{
+		self.list_block
+	}
+	fn	add_list_block( &mut self, line: &str ) -> Option<Vec<Block>>
+	{
+		let	pat_result= self.list_pat.captures( line );
+		if let Some(v)= pat_result {
+			self.list_entries.push( list_entry( &v, line, self.span ) );
+			return	None;
+		}
+		self.list_block= false;
+		return	Some( build_list_tree( &mut self.list_entries ) );
+	}
+	//-------------------------------------------------------------------------
+	fn	is_quote_block( &self ) -> bool
+	{
+		self.quote_block
+	}
+	// Returns false when the line is out of the quote, "bq." continues to the empty line.
+	fn	add_quote_block( &mut self, line: &str ) -> bool
+	{
+		if self.quote_bq {
+			if !line.trim().is_empty() {
+				self.quote_lines.push( SourceLine::new( line, self.span ) );
+				return	true;
+			}
+		}else if let Some(text)= strip_quote_mark( line ) {
+			self.quote_lines.push( SourceLine::new( text, self.span.at( line, text ) ) );
+			return	true;
+		}
+		self.quote_block= false;
+		return	false;
+	}
+	//-------------------------------------------------------------------------
+	fn	is_pre_block( &self ) -> bool
+	{
+		self.pre_block
+	}
+	fn	add_pre_block( &mut self, line: &str ) -> Option<Block>
+	{
+		let	end= line.find( "</code></pre>" ).or_else( || line.find( "</pre>" ) );
+		if let Some(pos)= end {
+			self.pre_block= false;
+			if pos != 0 {
+				self.pre_data+= &line[..pos];
+				self.pre_data+= "\n";
+			}
+			return	Some( Block::PRETag( PRETagElement{
+							text: 	std::mem::take( &mut self.pre_data ),
+							code:	std::mem::take( &mut self.pre_code ),
+							span:	self.pre_span,
+						}));
+		}
+		self.pre_data+= line;
+		self.pre_data+= "\n";
+		return	None;
+	}
+	//-------------------------------------------------------------------------
+	fn	is_table_block( &self ) -> bool
+	{
+		self.table_block
+	}
+	// "|_=. text |" is a centered header cell, "\2" and "/2" are the spans.
+	// The cells under the span are not written, they are the span marks in the grid.
+	fn	add_table_block( &mut self, line: &str ) -> Option<Block>
+	{
+		let	pat_result= self.table_pat.captures( line );
+		if let Some(v)= pat_result {
+			let	mut	cells= Vec::new();
+			for td in v[1].split( '|' ) {
+				while self.table_rowspan.get( cells.len() ).is_some_and( |rows| *rows > 0 ) {
+					self.table_rowspan[cells.len()]-= 1;
+					cells.push( TableGrid::SpanUp );
+				}
+				let	mut	text= td;
+				let	mut	attr= TableAttr{ text: Vec::new(), align: ETableAlign::DEFAULT, header: None, colspan: 1, rowspan: 1 };
+				let	mut	colspan= 1;
+				let	mut	rowspan= 1;
+				if let Some(m)= self.cell_pat.captures( td ) {
+					let	modifier= m.get( 1 ).map_or( "", |m| m.as_str() );
+					let	mut	rest= modifier;
+					while !rest.is_empty() {
+						let	len= if let Some(count)= rest.strip_prefix( '\\' ) {
+								let	digits= count.chars().take_while( |c| c.is_ascii_digit() ).count();
+								colspan= count[..digits].parse().unwrap_or( 1 );
+								1 + digits
+							}else if let Some(count)= rest.strip_prefix( '/' ) {
+								let	digits= count.chars().take_while( |c| c.is_ascii_digit() ).count();
+								rowspan= count[..digits].parse().unwrap_or( 1 );
+								1 + digits
+							}else if rest.starts_with( '{' ) {
+								rest.find( '}' ).map_or( rest.len(), |pos| pos + 1 )
+							}else if rest.starts_with( '(' ) {
+								rest.find( ')' ).map_or( rest.len(), |pos| pos + 1 )
+							}else if rest.starts_with( "<>" ) {
+								2
+							}else{
+								match rest.as_bytes()[0] {
+									b'_' => attr.header= Some(true),
+									b'<' => attr.align= ETableAlign::LEFT,
+									b'>' => attr.align= ETableAlign::RIGHT,
+									b'=' => attr.align= ETableAlign::CENTER,
+									_ => {},
+								}
+								1
+							};
+						rest= &rest[len..];
+					}
+					text= &td[m[0].len()..];
+				}
+				let	text= text.trim();
+				attr.text= decode_from_red( text, self.span.at( line, text ) );
+				let	col= cells.len();
+				cells.push( TableGrid::Cell( attr ) );
+				for _ in 1..colspan {
+					cells.push( TableGrid::SpanLeft );
+				}
+				if rowspan > 1 {
+					if self.table_rowspan.len() < cells.len() {
+						self.table_rowspan.resize( cells.len(), 0 );
+					}
+					for rows in &mut self.table_rowspan[col..cells.len()] {
+						*rows= rowspan - 1;
+					}
+				}
+			}
+			while self.table_rowspan.get( cells.len() ).is_some_and( |rows| *rows > 0 ) {
+				self.table_rowspan[cells.len()]-= 1;
+				cells.push( TableGrid::SpanUp );
+			}
+			self.table_grid.push( (self.span, cells) );
+		}else{
+			self.table_block= false;
+			self.table_rowspan.clear();
+			return	Some( Block::Table( build_table( std::mem::take( &mut self.table_grid ) ) ) );
+		}
+		return	None;
+	}
+	//-------------------------------------------------------------------------
+}
+
+//-----------------------------------------------------------------------------
+
+struct HTagGen {
+	pat : regex::Regex,
+}
+
+impl HTagGen {
+	fn	new() -> Self
+	{
+		HTagGen{
+			pat: regex::Regex::new( r"^h([1-6])[^.\s]*\.\s+(.*)$" ).unwrap(),
+		}
+	}
+}
+
+impl ElementGenerator for HTagGen {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		let	pat_result= self.pat.captures( line );
+		if let Some(v)= pat_result {
+			let	title= v[2].trim();
+			return	Some( Block::HTag( HTagElement{
+							title: 	decode_from_red( title, context.span.at( line, title ) ),
+							level:	v[1].parse().unwrap_or( 1 ),
+							span:	context.span,
+						}));
+		}
+		return	None;
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+// {{toc}} lists the headings after it, they are filled at the end of the page.
+struct TOCGen {
+	pat : regex::Regex,
+}
+
+impl TOCGen {
+	fn	new() -> Self
+	{
+		TOCGen{
+			pat: regex::Regex::new( r"^\{\{[<>]?toc\}\}\s*$" ).unwrap(),
+		}
+	}
+}
+
+impl ElementGenerator for TOCGen {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		if self.pat.is_match( line ) {
+			return	Some( Block::TOC( TOCElement{ entries: Vec::new(), span: context.span } ) );
+		}
+		return	None;
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+// "*" and "#" are the depth of the list, the last mark tells the ordered list.
+fn	list_entry( v: &regex::Captures, line: &str, span: Span ) -> ListEntry
+{
+	let	text= v[2].trim();
+	ListEntry{
+		depth:		v[1].len() as u32 - 1,
+		ordered:	v[1].ends_with( '#' ),
+		text:		decode_from_red( text, span.at( line, text ) ),
+		span:		span.at( line, &v[1] ),
+	}
+}
+
+struct LITagGen {
+	pat: regex::Regex,
+}
+
+impl LITagGen {
+	fn	new() -> Self
+	{
+		LITagGen{
+			pat: regex::Regex::new( r"^([*#]+)\s+(.*)$" ).unwrap(),
+		}
+	}
+}
+
+impl ElementGenerator for LITagGen {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		let	pat_result= self.pat.captures( line );
+		if let Some(v)= pat_result {
+			context.list_block= true;
+			context.list_entries= vec![list_entry( &v, line, context.span )];
+			return	Some( Block::None );
+		}
+		return	None;
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+// "bq." and the '>' of the reply quote.
+struct QuoteGen {
+	pat : regex::Regex,
+}
+
+impl QuoteGen {
+	fn	new() -> Self
+	{
+		QuoteGen{
+			pat: regex::Regex::new( r"^bq[^.\s]*\.\s+(.*)$" ).unwrap(),
+		}
+	}
+}
+
+impl ElementGenerator for QuoteGen {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		let	pat_result= self.pat.captures( line );
+		if let Some(v)= pat_result {
+			let	text= v.get( 1 ).map_or( "", |m| m.as_str() );
+			context.quote_block= true;
+			context.quote_bq= true;
+			context.quote_lines= vec![SourceLine::new( text, context.span.at( line, text ) )];
+			context.quote_span= context.span;
+			return	Some( Block::None );
+		}
+		if let Some(text)= strip_quote_mark( line ) {
+			context.quote_block= true;
+			context.quote_bq= false;
+			context.quote_lines= vec![SourceLine::new( text, context.span.at( line, text ) )];
+			context.quote_span= context.span;
+			return	Some( Block::None );
+		}
+		return	None;
+	}
 }
 
 
 //-----------------------------------------------------------------------------
 
-impl	EncodeElement for NONEElement {
-	fn	output( &self ) -> String
+// <pre> or <pre><code class="ruby">, the text can follow the tags.
+struct PRETagGen {
+	pat : regex::Regex,
+}
+
+impl PRETagGen {
+	fn	new() -> Self
 	{
-		return	String::new();
+		PRETagGen{
+			pat: regex::Regex::new( r#"^<pre>(?:<code(?:\s+class="(?:language-)?([^"]*)")?>)?(.*)$"# ).unwrap(),
+		}
+	}
+}
+
+impl ElementGenerator for PRETagGen {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		let	pat_result= self.pat.captures( line );
+		if let Some(v)= pat_result {
+			context.pre_block= true;
+			context.pre_span= context.span;
+			context.pre_code= v.get( 1 ).map_or( String::new(), |m| m.as_str().to_string() );
+			context.pre_data= String::new();
+			let	rest= &v[2];
+			if !rest.is_empty() {
+				if let Some(e)= context.add_pre_block( rest ) {
+					return	Some(e);
+				}
+			}
+			return	Some( Block::None );
+		}
+		return	None;
 	}
 }
 
 
+//-----------------------------------------------------------------------------
+
+struct TABLEGen {
+	pat : regex::Regex,
+}
+
+impl TABLEGen {
+	fn	new() -> Self
+	{
+		TABLEGen{
+			pat: regex::Regex::new( r"^\|.*\|\s*$" ).unwrap(),
+		}
+	}
+}
+
+impl ElementGenerator for TABLEGen {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		let	pat_result= self.pat.captures( line );
+		if let Some(_)= pat_result {
+			context.table_block= true;
+			context.table_grid= Vec::new();
+			context.table_rowspan= Vec::new();
+			if let Some(e)= context.add_table_block( line ) {
+				return	Some(e);
+			}
+			return	Some( Block::None );
+		}
+		return	None;
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+struct HRTagGen {
+	pat : regex::Regex,
+}
+
+impl HRTagGen {
+	fn	new() -> Self
+	{
+		HRTagGen{
+			pat: regex::Regex::new( r"^-{3,}\s*$" ).unwrap(),
+		}
+	}
+}
+
+impl ElementGenerator for HRTagGen {
+	fn	generate( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		let	pat_result= self.pat.captures( line );
+		if let Some(_)= pat_result {
+			return	Some( Block::HRTag( context.span ) );
+		}
+		return	None;
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+pub struct Decoder {
+	gen_table: Vec<Box<dyn ElementGenerator>>,
+	pat_para: regex::Regex,
+}
+
+impl Decoder {
+	pub fn new() -> Self
+	{
+		let	gen_table: Vec<Box<dyn ElementGenerator>>= vec![
+			Box::new( HRTagGen::new() ),
+			Box::new( HTagGen::new() ),
+			Box::new( TOCGen::new() ),
+			Box::new( LITagGen::new() ),
+			Box::new( QuoteGen::new() ),
+			Box::new( PRETagGen::new() ),
+			Box::new( TABLEGen::new() ),
+		];
+		Decoder{
+			gen_table: gen_table,
+			pat_para: regex::Regex::new( r"^p[^.\s]*\.\s+" ).unwrap(),
+		}
+	}
+	fn	find( &self, line: &str, context: &mut GenerateorContext ) -> Option<Block>
+	{
+		for gen in &self.gen_table {
+			let	element= gen.generate( line, context );
+			if let Some(_)= element {
+				return	element;
+			}
+		}
+		return	None;
+	}
+	fn	build_quote( &self, lines: &[SourceLine], span: Span ) -> Block
+	{
+		Block::Quote( BlockQuoteElement{ blocks: self.decode_lines( lines ).element_list, span: span } )
+	}
+}
+
+impl TextDecoder for Decoder {
+	fn	decode_lines( &self, lines: &[SourceLine] ) -> Document
+	{
+		let	mut	page= Document::new();
+		let	mut	context= GenerateorContext::new();
+
+		for source in lines {
+			let	line= &source.text;
+			context.span= source.span;
+			if context.is_pre_block() {
+				if let Some(e)= context.add_pre_block( line ) {
+					page.push( e );
+				}
+				continue;
+			}else if context.is_table_block() {
+				if let Some(e)= context.add_table_block( line ) {
+					page.push( e );
+				}else{
+					continue;
+				}
+			}else if context.is_list_block() {
+				if let Some(list)= context.add_list_block( line ) {
+					for e in list {
+						page.push( e );
+					}
+				}else{
+					continue;
+				}
+			}else if context.is_quote_block() {
+				if context.add_quote_block( line ) {
+					continue;
+				}
+				page.push( self.build_quote( &std::mem::take( &mut context.quote_lines ), context.quote_span ) );
+			}
+			{
+				if let Some(e)= self.find( line, &mut context ) {
+					if let Some(p)= context.para.finish() {
+						page.push( p );
+					}
+					page.push( e );
+				}else if line.trim().is_empty() {
+					if let Some(p)= context.para.finish() {
+						page.push( p );
+					}
+				}else{
+					// "p." is the plain paragraph
+					let	text= self.pat_para.find( line ).map_or( line.as_str(), |m| &line[m.end()..] );
+					context.para.add_line( decode_red_line( text, source.span.at( line, text ) ) );
+				}
+			}
+		}
+		if context.is_list_block() {
+			for e in build_list_tree( &mut context.list_entries ) {
+				page.push( e );
+			}
+		}else if context.is_quote_block() {
+			page.push( self.build_quote( &context.quote_lines, context.quote_span ) );
+		}else if context.is_table_block() {
+			page.push( Block::Table( build_table( std::mem::take( &mut context.table_grid ) ) ) );
+		}else if context.is_pre_block() {
+			page.push( Block::PRETag( PRETagElement{ text: context.pre_data, code: context.pre_code, span: context.pre_span } ) );
+		}
+		if let Some(p)= context.para.finish() {
+			page.push( p );
+		}
+		fill_toc( &mut page.element_list );
+		page
+	}
+}
+
+
+//=============================================================================
+
+trait	EncodeElement {
+	fn	output( &self, encoder: &Encoder ) -> String;
+}
+
+
 //-----------------------------------------------------------------------------
 
 impl	EncodeElement for HTagElement {
-	fn	output( &self ) -> String
+	fn	output( &self, _encoder: &Encoder ) -> String
 	{
-		return	format!( "h{}. {}\n", self.level, self.title );
+		return	format!( "h{}. {}\n", self.level.clamp( 1, 6 ), encode_to_red( &self.title ) );
 	}
 }
 
 
 //-----------------------------------------------------------------------------
 
-impl	EncodeElement for LITagElement {
-	fn	output( &self ) -> String
+impl	EncodeElement for TOCElement {
+	fn	output( &self, _encoder: &Encoder ) -> String
 	{
-		let	indent= self.nest+1;
-		if self.etype == ElementType::ULTAG {
-			return	format!( "{} {}\n", '*'.to_string().repeat(indent as usize), encode_to_red( &self.text ) );
-		}else{
-			return	format!( "{} {}\n", '#'.to_string().repeat(indent as usize), encode_to_red( &self.text ) );
+		return	"{{toc}}\n".to_string();
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+// A list item is a single line, the other blocks follow the item.
+fn	output_list( list: &ListElement, encoder: &Encoder, prefix: &str ) -> String
+{
+	let	mut	buffer= String::new();
+	let	mark= format!( "{}{}", prefix, if list.ordered { '#' } else { '*' } );
+	for item in &list.items {
+		let	mut	text= String::new();
+		let	mut	children= String::new();
+		for block in &item.blocks {
+			match block {
+				Block::Paragraph(e) => {
+					if !text.is_empty() {
+						text+= " ";
+					}
+					text+= &render_paragraph( &e.text, ParagraphMode::Join, encode_to_red ).replace( '\n', " " );
+				},
+				Block::List(e) => {
+					children+= &output_list( e, encoder, &mark );
+				},
+				_ => {
+					children+= &encoder.encode_single( block );
+				},
+			}
+		}
+		buffer+= &format!( "{} {}\n", mark, text );
+		buffer+= &children;
+	}
+	return	buffer;
+}
+
+impl	EncodeElement for ListElement {
+	fn	output( &self, encoder: &Encoder ) -> String
+	{
+		output_list( self, encoder, "" )
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+// Textile can't nest the quote, the inner quotes are flattened.
+fn	output_quote( blocks: &[Block], encoder: &Encoder ) -> String
+{
+	let	mut	buffer= String::new();
+	for (i,block) in blocks.iter().enumerate() {
+		if i != 0 {
+			buffer+= "\n";
+		}
+		match block {
+			Block::Paragraph(e) => {
+				buffer+= &format!( "bq. {}\n", render_paragraph( &e.text, encoder.paragraph, encode_to_red ) );
+			},
+			Block::Quote(e) => {
+				buffer+= &output_quote( &e.blocks, encoder );
+			},
+			_ => {
+				buffer+= &encoder.encode_single( block );
+			},
 		}
 	}
+	return	buffer;
+}
+
+impl	EncodeElement for BlockQuoteElement {
+	fn	output( &self, encoder: &Encoder ) -> String
+	{
+		output_quote( &self.blocks, encoder )
+	}
 }
 
 
 //-----------------------------------------------------------------------------
 
 impl	EncodeElement for PRETagElement {
-	fn	output( &self ) -> String
+	fn	output( &self, _encoder: &Encoder ) -> String
 	{
 		if self.code.is_empty() {
 			return	format!( "<pre>\n{}</pre>\n", self.text );
@@ -153,14 +859,38 @@ impl	EncodeElement for PRETagElement {
 //-----------------------------------------------------------------------------
 
 impl	EncodeElement for TABLEElement {
-	fn	output( &self ) -> String
+	fn	output( &self, _encoder: &Encoder ) -> String
 	{
 		let	mut	buffer= String::new();
-		for column in &self.data {
+		for (row,line) in self.grid().iter().enumerate() {
 			buffer+= "|";
-			for attr in &column.data {
-				if attr.header {
-					buffer+= "_.";
+			for (col,cell) in line.iter().enumerate() {
+				let	TableGrid::Cell(attr)= cell else { continue };
+				let	mut	modifier= String::new();
+				if self.cell_header( row, col, attr ) {
+					modifier+= "_";
+				}
+				match self.cell_align( row, col, attr ) {
+					ETableAlign::CENTER => {
+						modifier+= "=";
+					},
+					ETableAlign::LEFT => {
+						modifier+= "<";
+					},
+					ETableAlign::RIGHT => {
+						modifier+= ">";
+					},
+					_ => {
+					},
+				}
+				if attr.colspan > 1 {
+					modifier+= &format!( "\\{}", attr.colspan );
+				}
+				if attr.rowspan > 1 {
+					modifier+= &format!( "/{}", attr.rowspan );
+				}
+				if !modifier.is_empty() {
+					buffer+= &format!( "{}.", modifier );
 				}
 				buffer+= &format!( " {} |", encode_to_red( &attr.text ) );
 			}
@@ -173,20 +903,10 @@ impl	EncodeElement for TABLEElement {
 
 //-----------------------------------------------------------------------------
 
-impl	EncodeElement for HRTagElement {
-	fn	output( &self ) -> String
-	{
-		return	"----\n".to_string();
-	}
-}
-
-
-//-----------------------------------------------------------------------------
-
-impl	EncodeElement for DataElement {
-	fn	output( &self ) -> String
+impl	EncodeElement for ParagraphElement {
+	fn	output( &self, encoder: &Encoder ) -> String
 	{
-		encode_to_red( &self.text ) + "\n"
+		render_paragraph( &self.text, encoder.paragraph, encode_to_red ) + "\n"
 	}
 }
 
@@ -194,41 +914,56 @@ impl	EncodeElement for DataElement {
 //-----------------------------------------------------------------------------
 
 pub struct	Encoder {
+	pub paragraph	: ParagraphMode,
 }
 
 impl	Encoder {
 	pub	fn	new() -> Self
 	{
-		Self{}
+		Self{
+			paragraph: ParagraphMode::Join,
+		}
 	}
 }
 
 impl	TextEncoder for Encoder {
-	fn	encode_single( &self, element: &Box<dyn Element> ) -> String
+	fn	encode_single( &self, element: &Block ) -> String
 	{
-		let	any= element.as_any();
-		if let Some(e)= any.downcast_ref::<NONEElement>() {
-			return	e.output();
-		}
-		if let Some(e)= any.downcast_ref::<HTagElement>() {
-			return	e.output();
-		}
-		if let Some(e)= any.downcast_ref::<LITagElement>() {
-			return	e.output();
+		match element {
+			Block::None => String::new(),
+			Block::Paragraph(e) => e.output( self ),
+			Block::HTag(e) => e.output( self ),
+			Block::List(e) => e.output( self ),
+			Block::PRETag(e) => e.output( self ),
+			Block::Table(e) => e.output( self ),
+			Block::Quote(e) => e.output( self ),
+			Block::HRTag(_) => "----\n".to_string(),
+			Block::TOC(e) => e.output( self ),
 		}
-		if let Some(e)= any.downcast_ref::<PRETagElement>() {
-			return	e.output();
-		}
-		if let Some(e)= any.downcast_ref::<TABLEElement>() {
-			return	e.output();
+	}
+	fn	check_block( &self, element: &Block, warnings: &mut Vec<Warning> )
+	{
+		if let Block::Quote(e)= element {
+			if e.blocks.iter().any( |block| matches!( block, Block::Quote(_) ) ) {
+				warnings.push( Warning::new( LossKind::Degraded, "nested quote", "flattened into one quote", e.span ) );
+			}
 		}
-		if let Some(e)= any.downcast_ref::<HRTagElement>() {
-			return	e.output();
+		if let Block::List(e)= element {
+			for item in &e.items {
+				if item.blocks.iter().filter( |block| matches!( block, Block::Paragraph(_) ) ).count() > 1 {
+					warnings.push( Warning::new( LossKind::Degraded, "list paragraph", "joined into the item line", item.span ) );
+				}
+			}
+			check_list_items( e, warnings );
 		}
-		if let Some(e)= any.downcast_ref::<DataElement>() {
-			return	e.output();
+	}
+	fn	check_inline( &self, node: &Inline, warnings: &mut Vec<Warning> )
+	{
+		if let InlineNode::Image(image)= &node.node {
+			if !image.alt.is_empty() && !image.title.is_empty() {
+				warnings.push( Warning::new( LossKind::Dropped, "image title", "only the alt text is written", node.span ) );
+			}
 		}
-		return	String::new();
 	}
 }
 
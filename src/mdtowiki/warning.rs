@@ -0,0 +1,54 @@
+// 2022 Hiroyuki Ogasawara
+// vim:ts=4 sw=4 noet:
+
+use std::fmt;
+use super::span::Span;
+
+//-----------------------------------------------------------------------------
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum LossKind {
+	Dropped,		// the output has no trace of it
+	Degraded,		// written in a weaker form
+}
+
+impl fmt::Display for LossKind {
+	fn	fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+	{
+		match self {
+			LossKind::Dropped => write!( f, "dropped" ),
+			LossKind::Degraded => write!( f, "degraded" ),
+		}
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
+// Something the target format can't keep, reported by the TextEncoder.
+#[derive(Debug,Clone)]
+pub struct Warning {
+	pub kind	: LossKind,
+	pub element	: &'static str,		// "code span", "table span", ..
+	pub note	: &'static str,		// what the encoder wrote instead
+	pub span	: Span,
+}
+
+impl Warning {
+	pub fn	new( kind: LossKind, element: &'static str, note: &'static str, span: Span ) -> Self
+	{
+		Warning{ kind: kind, element: element, note: note, span: span }
+	}
+}
+
+// "code span dropped, written as plain text"
+impl fmt::Display for Warning {
+	fn	fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+	{
+		write!( f, "{} {}, {}", self.element, self.kind, self.note )
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+
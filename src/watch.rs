@@ -0,0 +1,87 @@
+// 2022 Hiroyuki Ogasawara
+// vim:ts=4 sw=4 noet:
+
+use std::fs;
+use std::path::{Path,PathBuf};
+use std::thread;
+use std::time::{Duration,SystemTime};
+use super::{Log,Settings};
+
+
+//-----------------------------------------------------------------------------
+
+const	POLL: Duration= Duration::from_millis( 200 );
+const	DEBOUNCE: Duration= Duration::from_millis( 300 );	// no changes for this long after a save
+
+// The modified time and the size of the input file, or of every file of the input tree.
+// A missing file is not in the list, the editors which save to a new file and rename it
+// make it missing for a moment.
+type Stamp= Vec<(PathBuf,Option<SystemTime>,u64)>;
+
+fn	collect_stamp( path: &Path, stamp: &mut Stamp )
+{
+	let	meta= match fs::metadata( path ) {
+		Ok(meta) => meta,
+		Err(_) => return,
+	};
+	if meta.is_dir() {
+		let	mut	entries= match fs::read_dir( path ) {
+			Ok(entries) => entries.filter_map( |entry| entry.ok().map( |e| e.path() ) ).collect::<Vec<PathBuf>>(),
+			Err(_) => return,
+		};
+		entries.sort();
+		for entry in entries {
+			collect_stamp( &entry, stamp );
+		}
+	}else{
+		stamp.push( (path.to_path_buf(), meta.modified().ok(), meta.len()) );
+	}
+}
+
+fn	stamp( path: &Path ) -> Stamp
+{
+	let	mut	stamp= Vec::new();
+	collect_stamp( path, &mut stamp );
+	return	stamp;
+}
+
+
+//-----------------------------------------------------------------------------
+
+// Converts once, then again every time the input changes until the process is stopped.
+// The rapid saves are converted once when the input has not changed for DEBOUNCE.
+// The stamp is taken after the conversion, the outputs written in the input tree don't trigger it.
+pub fn	watch( input_file: &str, settings: &Settings, mut convert: impl FnMut() ) -> !
+{
+	let	path= Path::new( input_file );
+	let	mut	log= Log::stderr();
+	convert();
+	let	mut	last= stamp( path );
+	if settings.verbose {
+		log.line( format!( "watch:  {}, Ctrl-C to stop", input_file ) );
+	}
+	loop {
+		thread::sleep( POLL );
+		let	mut	current= stamp( path );
+		if current == last {
+			continue;
+		}
+		loop {
+			thread::sleep( DEBOUNCE );
+			let	next= stamp( path );
+			if next == current && !next.is_empty() {
+				break;
+			}
+			current= next;
+		}
+		if settings.verbose {
+			log.line( format!( "watch:  {} changed", input_file ) );
+		}
+		convert();
+		last= stamp( path );
+	}
+}
+
+
+//-----------------------------------------------------------------------------
+